@@ -81,9 +81,12 @@ impl EventDelegate for App {
             false as _
         }
 
-        #[cfg(target_os = "macos")] const PLATFORM_SURFACE: &str = "VK_MVK_macos_surface";
-        #[cfg(windows)] const PLATFORM_SURFACE: &str = "VK_KHR_win32_surface";
-        #[cfg(brature = "with_xcb")] const PLATFORM_SURFACE: &str = "VK_KHR_xcb_surface";
+        #[cfg(feature = "with_headless")] const PLATFORM_SURFACE: &str = "VK_EXT_headless_surface";
+        #[cfg(all(target_os = "macos", not(feature = "with_headless")))]
+        const PLATFORM_SURFACE: &str = "VK_MVK_macos_surface";
+        #[cfg(all(windows, not(feature = "with_headless")))] const PLATFORM_SURFACE: &str = "VK_KHR_win32_surface";
+        #[cfg(all(feature = "with_xcb", not(feature = "with_headless")))]
+        const PLATFORM_SURFACE: &str = "VK_KHR_xcb_surface";
         let instance = br::InstanceBuilder::new("appframe_integ", (0, 1, 0), "Ferrite", (0, 1, 0))
             .add_extensions(vec!["VK_KHR_surface", PLATFORM_SURFACE, "VK_EXT_debug_report"])
             .add_layer("VK_LAYER_LUNARG_standard_validation")
@@ -267,6 +270,12 @@ impl MainWindow {
             wait_semaphores: Cow::Borrowed(&[(&self.brrrite.semaphore_sync_next, br::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)]),
             signal_semaphores: Cow::Borrowed(&[&self.brrrite.semaphore_command_completion])
         }], Some(&self.brrrite.brnce_command_completion))?;
+        // ヘッドレスでは表示前に描画結果を読み戻す
+        #[cfg(feature = "with_headless")] {
+            self.brrrite.brnce_command_completion.wait()?;
+            self.window.get().read_back_presented(&self.brrrite.adapter, &self.brrrite.device, self.brrrite.gq,
+                &wrt.images[next], PixelFormat::Rgba8)?;
+        }
         self.brrrite.queue.present(&[(&wrt.swapchain, next as _)], &[&self.brrrite.semaphore_command_completion])?;
         // コマンドバッファの使用が終了したことを明示する
         self.brrrite.brnce_command_completion.wait()?; self.brrrite.brnce_command_completion.reset()?;
@@ -275,7 +284,7 @@ impl MainWindow {
 }
 struct WindowRenderTargets
 {
-    framebuffers: Vec<br::Framebuffer>, backbuffers: Vec<br::ImageView>,
+    framebuffers: Vec<br::Framebuffer>, backbuffers: Vec<br::ImageView>, images: Vec<br::Image>,
     swapchain: br::Swapchain, size: br::Extent2D
 }
 impl WindowRenderTargets {
@@ -287,8 +296,11 @@ impl WindowRenderTargets {
         };
         if surface_size.0 <= 0 || surface_size.1 <= 0 { return Ok(None); }
 
+        // ヘッドレスでは読み戻しのために転送元にする
+        #[cfg(feature = "with_headless")] let usage = br::ImageUsage::COLOR_ATTACHMENT.transfer_src();
+        #[cfg(not(feature = "with_headless"))] let usage = br::ImageUsage::COLOR_ATTACHMENT;
         let swapchain = br::SwapchainBuilder::new(&surface.object, surface.buffer_count, &surface.format,
-            &surface_size, br::ImageUsage::COLOR_ATTACHMENT)
+            &surface_size, usage)
                 .present_mode(surface.present_mode).pre_transform(br::SurfaceTransform::Identity)
                 .composite_alpha(surface.composite_mode).create(&f.device)?;
         // acquire_nextより前にやらないと死ぬ(get_images)
@@ -314,7 +326,7 @@ impl WindowRenderTargets {
         
         Ok(Some(WindowRenderTargets
         {
-            swapchain, backbuffers: bb_views, framebuffers, size: surface_size
+            swapchain, backbuffers: bb_views, images: backbuffers, framebuffers, size: surface_size
         }))
     }
 }
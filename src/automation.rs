@@ -0,0 +1,323 @@
+//! Automation endpoint for driving a running application from test scripts
+//!
+//! Enabled by setting `APPFRAME_AUTOMATION` to the path of a Unix domain socket(XCB backend).
+//! The socket is created with mode 0600, so only the user running the application can connect.
+//! Clients send a JSON object per line and receive a JSON object per line for each request.
+//! Requests may carry an `id`, which is echoed in the response. Windows are identified by their index in creation order.
+//!
+//! | Request | Response |
+//! |---|---|
+//! | `{"cmd":"list_windows"}` | `{"ok":true,"windows":[{"id":0,"title":"..","x":0,"y":0,"width":640,"height":480,"visible":true,"focused":false}]}` |
+//! | `{"cmd":"key","window":0,"keycode":38,"pressed":true}` | `{"ok":true}` |
+//! | `{"cmd":"pointer_move","window":0,"x":10,"y":20}` | `{"ok":true}` |
+//! | `{"cmd":"pointer_button","window":0,"button":"left","pressed":true,"x":10,"y":20}` | `{"ok":true}` |
+//! | `{"cmd":"ime_preedit","window":0,"text":"にほ","cursor_start":6,"cursor_end":6}` | `{"ok":true}` |
+//! | `{"cmd":"ime_commit","window":0,"text":"日本"}` | `{"ok":true}` |
+//! | `{"cmd":"close","window":0}` | `{"ok":true,"accepted":true}` |
+//! | `{"cmd":"capture","window":0}` | `{"ok":true,"width":640,"height":480,"format":"bgra8","alpha":false,"data":"<base64>"}` |
+//! | `{"cmd":"wait_idle"}` | `{"ok":true}`, sent once all events are processed and no window waits for a redraw |
+//!
+//! `button` is `"left"`, `"middle"`, `"right"` or a button number. Failed requests are answered with
+//! `{"ok":false,"error":".."}`. Injected events go through the same dispatcher as the events from the window server.
+//! `ime_preedit` and `ime_commit` stand in for an input method server; the cursor of `ime_preedit` is optional.
+
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::io::{Read, Write, Result as IOResult, ErrorKind};
+use std::fs;
+use std::path::Path;
+use {WindowEvent, Image, PixelFormat};
+use json::{self, parse_object, field, string_field};
+use recording::parse_button;
+
+/// Environment variable naming the socket path of the endpoint
+pub const AUTOMATION_ENV: &str = "APPFRAME_AUTOMATION";
+
+pub(crate) enum Command
+{
+    ListWindows,
+    /// Deliver an event. `CloseRequested` is answered with whether the close is accepted
+    Inject { window: u32, event: WindowEvent },
+    Capture { window: u32 },
+    WaitIdle
+}
+pub(crate) struct WindowInfo
+{
+    pub id: u32, pub title: String, pub position: (i32, i32), pub size: (u32, u32), pub visible: bool, pub focused: bool
+}
+pub(crate) enum Response { Ok, Closed(bool), Windows(Vec<WindowInfo>), Image(Image), Error(String) }
+
+/// A request waiting for its response
+pub(crate) struct Request { client: u64, id: Option<String>, pub command: Result<Command, String> }
+
+/// Responses not yet accepted by the socket are kept in `output` from `written` and written as it becomes writable
+struct Client { token: u64, stream: UnixStream, buffer: Vec<u8>, output: Vec<u8>, written: usize }
+impl Client
+{
+    /// Write as much of the output as the socket accepts. Err if the client is gone
+    fn flush(&mut self) -> IOResult<()>
+    {
+        while self.written < self.output.len()
+        {
+            match self.stream.write(&self.output[self.written ..])
+            {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => self.written += n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+        self.output.clear(); self.written = 0;
+        Ok(())
+    }
+}
+/// Clients that read nothing of this many bytes of responses are dropped
+const MAX_OUTPUT: usize = 1 << 28;
+pub(crate) struct AutomationServer
+{
+    listener: UnixListener, clients: Vec<Client>, next_token: u64, idle_waiters: Vec<Request>
+}
+impl AutomationServer
+{
+    /// Listen on `path`, replacing a stale socket file. Only the user can connect to the socket
+    pub fn bind<P: AsRef<Path>>(path: P) -> IOResult<Self>
+    {
+        if let Err(e) = fs::remove_file(path.as_ref()) { if e.kind() != ErrorKind::NotFound { return Err(e); } }
+        // created with mode 0600 rather than changed afterwards, which would let others connect in between
+        let mask = unsafe { ::libc::umask(0o177) };
+        let listener = UnixListener::bind(path);
+        unsafe { ::libc::umask(mask); }
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+        Ok(AutomationServer { listener, clients: Vec::new(), next_token: 0, idle_waiters: Vec::new() })
+    }
+    /// Descriptors to wait on together with the window server connection. Clients with pending output wait to be writable too
+    pub fn fds(&self) -> Vec<::libc::pollfd>
+    {
+        let pollfd = |fd: RawFd, events| ::libc::pollfd { fd, events, revents: 0 };
+        Some(pollfd(self.listener.as_raw_fd(), ::libc::POLLIN)).into_iter().chain(self.clients.iter().map(|c|
+        {
+            pollfd(c.stream.as_raw_fd(), if c.output.is_empty() { ::libc::POLLIN } else { ::libc::POLLIN | ::libc::POLLOUT })
+        })).collect()
+    }
+
+    /// Accept new clients, write pending responses and read complete requests. `WaitIdle` requests are kept until `notify_idle`
+    pub fn poll_requests(&mut self) -> Vec<Request>
+    {
+        while let Ok((stream, _)) = self.listener.accept()
+        {
+            if stream.set_nonblocking(true).is_err() { continue; }
+            self.clients.push(Client { token: self.next_token, stream, buffer: Vec::new(), output: Vec::new(), written: 0 });
+            self.next_token += 1;
+        }
+        let mut requests = Vec::new();
+        let mut buf = [0u8; 4096];
+        self.clients.retain(|c| c.buffer.len() < 1 << 20);
+        let mut closed = Vec::new();
+        for c in &mut self.clients
+        {
+            if c.flush().is_err() { closed.push(c.token); continue; }
+            loop
+            {
+                match c.stream.read(&mut buf)
+                {
+                    Ok(0) => { closed.push(c.token); break; },
+                    Ok(n) => c.buffer.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => { closed.push(c.token); break; }
+                }
+            }
+            while let Some(p) = c.buffer.iter().position(|&b| b == b'\n')
+            {
+                let line = c.buffer.drain(..= p).collect::<Vec<_>>();
+                let line = String::from_utf8_lossy(&line);
+                if !line.trim().is_empty() { requests.push(parse_request(c.token, &line)); }
+            }
+        }
+        self.clients.retain(|c| !closed.contains(&c.token));
+        let (waits, others) = requests.into_iter().partition(|r| matches!(r.command, Ok(Command::WaitIdle)));
+        self.idle_waiters.extend::<Vec<_>>(waits);
+        others
+    }
+    pub fn respond(&mut self, r: Request, response: Response)
+    {
+        let mut line = String::from("{");
+        if let Some(ref id) = r.id { line.push_str(&format!("\"id\":{},", id)); }
+        match response
+        {
+            Response::Ok => line.push_str("\"ok\":true"),
+            Response::Closed(accepted) => line.push_str(&format!("\"ok\":true,\"accepted\":{}", accepted)),
+            Response::Error(ref e) => line.push_str(&format!("\"ok\":false,\"error\":{}", json::string(e))),
+            Response::Windows(ref ws) =>
+            {
+                let ws = ws.iter().map(|w| format!(
+                    "{{\"id\":{},\"title\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"visible\":{},\"focused\":{}}}",
+                    w.id, json::string(&w.title), w.position.0, w.position.1, w.size.0, w.size.1, w.visible, w.focused))
+                    .collect::<Vec<_>>();
+                line.push_str(&format!("\"ok\":true,\"windows\":[{}]", ws.join(",")));
+            },
+            Response::Image(ref img) => line.push_str(&format!(
+                "\"ok\":true,\"width\":{},\"height\":{},\"format\":\"{}\",\"alpha\":{},\"data\":\"{}\"",
+                img.width, img.height, match img.format { PixelFormat::Rgba8 => "rgba8", PixelFormat::Bgra8 => "bgra8" },
+                img.has_alpha, json::base64(&img.data)))
+        }
+        line.push_str("}\n");
+        let failed = match self.clients.iter_mut().find(|c| c.token == r.client)
+        {
+            Some(c) => { c.output.extend_from_slice(line.as_bytes()); c.output.len() - c.written > MAX_OUTPUT || c.flush().is_err() },
+            None => false
+        };
+        if failed { self.clients.retain(|c| c.token != r.client); }
+    }
+    /// Answer the pending `WaitIdle` requests
+    pub fn notify_idle(&mut self)
+    {
+        for r in ::std::mem::take(&mut self.idle_waiters) { self.respond(r, Response::Ok); }
+    }
+    pub fn has_idle_waiters(&self) -> bool { !self.idle_waiters.is_empty() }
+}
+
+fn parse_request(client: u64, line: &str) -> Request
+{
+    let fields = match parse_object(line)
+    {
+        Some(f) => f, None => return Request { client, id: None, command: Err("malformed request".to_owned()) }
+    };
+    let id = field(&fields, "id").map(|s| s.to_owned());
+    Request { client, id, command: parse_command(&fields) }
+}
+fn parse_command(fields: &[(&str, &str)]) -> Result<Command, String>
+{
+    macro_rules! get
+    {
+        ($k: expr) => { field(fields, $k).and_then(|v| v.parse().ok()).ok_or_else(|| format!("missing or invalid {}", $k))? }
+    }
+    match string_field(fields, "cmd").unwrap_or("")
+    {
+        "list_windows" => Ok(Command::ListWindows),
+        "wait_idle" => Ok(Command::WaitIdle),
+        "capture" => Ok(Command::Capture { window: get!("window") }),
+        "close" => Ok(Command::Inject { window: get!("window"), event: WindowEvent::CloseRequested }),
+        "key" => Ok(Command::Inject
+        {
+            window: get!("window"),
+            event: if get!("pressed") { WindowEvent::KeyDown(get!("keycode")) } else { WindowEvent::KeyUp(get!("keycode")) }
+        }),
+        "pointer_move" => Ok(Command::Inject { window: get!("window"), event: WindowEvent::PointerMoved { x: get!("x"), y: get!("y") } }),
+        "pointer_button" => Ok(Command::Inject
+        {
+            window: get!("window"),
+            event: WindowEvent::PointerButton
+            {
+                button: field(fields, "button").and_then(parse_button).ok_or("missing or invalid button")?,
+                pressed: get!("pressed"), x: get!("x"), y: get!("y")
+            }
+        }),
+        "ime_preedit" => Ok(Command::Inject
+        {
+            window: get!("window"),
+            event: WindowEvent::ImePreedit
+            {
+                text: text(fields)?,
+                cursor: if field(fields, "cursor_start").is_some() { Some((get!("cursor_start"), get!("cursor_end"))) } else { None }
+            }
+        }),
+        "ime_commit" => Ok(Command::Inject { window: get!("window"), event: WindowEvent::ImeCommit(text(fields)?) }),
+        c => Err(format!("unknown command {:?}", c))
+    }
+}
+
+fn text(fields: &[(&str, &str)]) -> Result<String, String>
+{
+    string_field(fields, "text").and_then(json::unescape).ok_or_else(|| "missing or invalid text".to_owned())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn socket_path(name: &str) -> PathBuf
+    {
+        ::std::env::temp_dir().join(format!("appframe-automation-{}-{}", ::std::process::id(), name))
+    }
+    fn command(line: &str) -> Result<Command, String> { parse_request(0, line).command }
+    /// Poll until `client` has read `n` whole lines
+    fn read_lines(server: &mut AutomationServer, client: &mut UnixStream, n: usize) -> Vec<String>
+    {
+        let (mut line, mut lines) = (Vec::new(), 0);
+        let mut buf = [0u8; 4096];
+        while lines < n
+        {
+            assert!(server.poll_requests().is_empty());
+            match client.read(&mut buf)
+            {
+                Ok(n) => { lines += buf[.. n].iter().filter(|&&b| b == b'\n').count(); line.extend_from_slice(&buf[.. n]); },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => panic!("{}", e)
+            }
+        }
+        String::from_utf8(line).unwrap().lines().map(|l| l.to_owned()).collect()
+    }
+
+    #[test]
+    fn requests_are_parsed()
+    {
+        match command("{\"cmd\":\"key\",\"window\":1,\"keycode\":38,\"pressed\":false}")
+        {
+            Ok(Command::Inject { window: 1, event: WindowEvent::KeyUp(38) }) => (), _ => panic!()
+        }
+        match command("{\"cmd\":\"ime_preedit\",\"window\":0,\"text\":\"\\u306b\",\"cursor_start\":3,\"cursor_end\":3}")
+        {
+            Ok(Command::Inject { event: WindowEvent::ImePreedit { ref text, cursor: Some((3, 3)) }, .. }) if text == "に" => (),
+            _ => panic!()
+        }
+        match command("{\"cmd\":\"pointer_button\",\"window\":0,\"button\":5,\"pressed\":true,\"x\":1,\"y\":2}")
+        {
+            Ok(Command::Inject { event: WindowEvent::PointerButton { button: ::MouseButton::Other(5), .. }, .. }) => (),
+            _ => panic!()
+        }
+        assert_eq!(parse_request(0, "{\"id\":\"a\",\"cmd\":\"close\",\"window\":0}").id, Some("\"a\"".to_owned()));
+        assert_eq!(command("{\"cmd\":\"capture\"}").err().unwrap(), "missing or invalid window");
+        assert_eq!(command("{\"cmd\":\"fly\"}").err().unwrap(), "unknown command \"fly\"");
+        assert_eq!(command("cmd=list_windows").err().unwrap(), "malformed request");
+    }
+    #[test]
+    fn socket_is_private()
+    {
+        let path = socket_path("private");
+        let _server = AutomationServer::bind(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn responses_wait_for_slow_readers()
+    {
+        let path = socket_path("slow");
+        let mut server = AutomationServer::bind(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.set_nonblocking(true).unwrap();
+        client.write_all(b"{\"id\":1,\"cmd\":\"capture\",\"window\":0}\n{\"id\":2,\"cmd\":\"list_windows\"}\n").unwrap();
+        let mut requests = Vec::new();
+        while requests.len() < 2 { requests.extend(server.poll_requests()); }
+
+        // far larger than the socket buffer; answered without blocking while nobody reads
+        let image = Image { width: 1024, height: 1024, format: PixelFormat::Rgba8, has_alpha: true, data: vec![0; 1 << 22] };
+        let mut requests = requests.into_iter();
+        server.respond(requests.next().unwrap(), Response::Image(image));
+        server.respond(requests.next().unwrap(), Response::Windows(Vec::new()));
+        assert!(server.fds()[1].events & ::libc::POLLOUT != 0);
+
+        let lines = read_lines(&mut server, &mut client, 2);
+        let capture = &lines[0];
+        assert!(capture.starts_with("{\"id\":1,\"ok\":true,\"width\":1024,\"height\":1024,\"format\":\"rgba8\",\"alpha\":true,\"data\":\"AAAA"));
+        assert!(capture.ends_with("AA==\"}") && capture.len() == 83 + (1usize << 22).div_ceil(3) * 4 + 2, "{}", capture.len());
+        assert_eq!(lines[1], "{\"id\":2,\"ok\":true,\"windows\":[]}");
+        assert_eq!(server.fds()[1].events, ::libc::POLLIN);
+        fs::remove_file(&path).unwrap();
+    }
+}
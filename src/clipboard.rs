@@ -0,0 +1,40 @@
+//! Clipboard access shared by the backends
+
+use mime::{MimeData, TEXT_UTF8};
+
+/// Receives the requested data, or None if the selection is empty or does not offer the format
+pub type ReceiveCallback = Box<dyn FnOnce(Option<Vec<u8>>)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Selection
+{
+    Clipboard, Primary,
+    /// XdndSelection. The headless backend has no drag and drop source
+    #[cfg(not(feature = "with_headless"))] Dnd
+}
+
+/// Backend side of `Clipboard`
+pub(crate) trait SelectionBackend
+{
+    fn set_selection(&self, which: Selection, data: MimeData);
+    fn get_selection(&self, which: Selection, mime: &str, callback: ReceiveCallback);
+}
+
+/// The system clipboard(`GUIApplication::clipboard`) or the primary selection(`GUIApplication::primary_selection`)
+pub struct Clipboard<'a> { backend: &'a dyn SelectionBackend, which: Selection }
+impl<'a> Clipboard<'a>
+{
+    pub(crate) fn new(backend: &'a dyn SelectionBackend, which: Selection) -> Self { Clipboard { backend, which } }
+    /// Take the selection with the data. Empty data releases it
+    pub fn set(&self, data: MimeData) { self.backend.set_selection(self.which, data); }
+    pub fn set_text(&self, text: &str)
+    {
+        let mut data = MimeData::new(); data.insert(TEXT_UTF8.to_owned(), text.as_bytes().to_owned());
+        self.set(data);
+    }
+    /// Request the data in a format. The callback is called from the event loop once the data arrives
+    pub fn get<F: FnOnce(Option<Vec<u8>>) + 'static>(&self, mime: &str, callback: F)
+    {
+        self.backend.get_selection(self.which, mime, Box::new(callback));
+    }
+}
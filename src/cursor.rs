@@ -0,0 +1,29 @@
+//! Mouse cursors
+
+/// Cursor shapes from the system(cursor theme on XCB)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum CursorIcon
+{
+    #[default]
+    Default, Text, Hand, Crosshair, Move, Wait, Progress, Help, NotAllowed, Grab, Grabbing,
+    /// Vertical resize
+    ResizeNS,
+    /// Horizontal resize
+    ResizeEW,
+    /// Resize along the northeast-southwest diagonal
+    ResizeNESW,
+    /// Resize along the northwest-southeast diagonal
+    ResizeNWSE
+}
+
+/// How `Window::set_cursor_grab` holds the pointer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GrabMode
+{
+    #[default]
+    None,
+    /// The pointer cannot leave the client area
+    Confined,
+    /// The pointer is hidden and stays in place. Movements are reported by `WindowEventDelegate::raw_mouse_motion`
+    Locked
+}
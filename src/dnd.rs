@@ -0,0 +1,30 @@
+//! Drag and drop
+
+use std::path::PathBuf;
+use mime::{self, MimeData, URI_LIST};
+
+/// What dropping the dragged data does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropEffect { None, Copy, Move, Link }
+
+/// Data dropped on a window, in the formats offered by the source
+#[derive(Clone, Debug, PartialEq)]
+pub struct DropData { pub data: MimeData }
+impl DropData
+{
+    /// Decoded `text/uri-list`
+    pub fn uris(&self) -> Vec<String> { self.data.get(URI_LIST).map_or_else(Vec::new, |d| mime::parse_uri_list(d)) }
+    /// Local files of the `text/uri-list`
+    pub fn file_paths(&self) -> Vec<PathBuf> { self.uris().iter().filter_map(|u| mime::file_uri_path(u)).collect() }
+}
+
+/// Produces dragged data in a MIME type, or None if it cannot
+pub type DragProvider = Box<dyn Fn(&str) -> Option<Vec<u8>>>;
+/// Data dragged out of a window(`Window::start_drag`)
+pub struct DragData
+{
+    /// MIME types offered to drop targets
+    pub formats: Vec<String>,
+    /// Produces the data in one of `formats` when a target asks for it
+    pub provider: DragProvider
+}
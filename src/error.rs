@@ -0,0 +1,86 @@
+//! Errors reported by the backends
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IOError;
+use std::error::Error as StdError;
+
+#[derive(Debug)]
+pub enum Error
+{
+    /// Failed to connect to the window server. Holds the display name if it is known
+    DisplayConnection(Option<String>),
+    /// The window server offers no visual of the required depth and class
+    UnsupportedVisual { depth: u8, class: &'static str },
+    /// The window server rejected a request
+    Protocol(ProtocolError),
+    /// A required extension or window manager feature is not available
+    ExtensionMissing(&'static str),
+    /// Failed to create a window or a rendering surface
+    SurfaceCreation(String),
+    /// Failed to set up the application object of the platform(e.g. `NSApplication`). Holds what failed
+    ApplicationInit(&'static str),
+    /// The backend does not implement the operation. Holds the operation
+    Unsupported(&'static str),
+    /// An error reported by the OS or an I/O operation
+    Io(IOError)
+}
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// An error reported by the window server for a request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolError
+{
+    pub error_code: u8,
+    /// e.g. `BadMatch`
+    pub error_name: &'static str,
+    pub major_opcode: u8, pub minor_opcode: u16,
+    /// Name of the failed request, e.g. `CreateWindow`
+    pub request_name: &'static str,
+    /// The extension the request belongs to. `None` for core requests
+    pub extension: Option<&'static str>,
+    /// The bad resource ID or value, if the error carries one
+    pub resource_id: u32,
+    pub sequence: u32,
+    /// The call that sent the request, if it was sent without waiting for the result(e.g. `create_window`)
+    pub call: Option<&'static str>
+}
+impl Display for ProtocolError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult
+    {
+        write!(fmt, "{}({}) in ", self.error_name, self.error_code)?;
+        if let Some(x) = self.extension { write!(fmt, "{}:", x)?; }
+        write!(fmt, "{}(major {}, minor {}), resource 0x{:x}, sequence {}",
+            self.request_name, self.major_opcode, self.minor_opcode, self.resource_id, self.sequence)?;
+        if let Some(c) = self.call { write!(fmt, ", sent by {}", c)?; }
+        Ok(())
+    }
+}
+
+impl From<IOError> for Error { fn from(e: IOError) -> Self { Error::Io(e) } }
+impl Display for Error
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult
+    {
+        match *self
+        {
+            Error::DisplayConnection(Some(ref d)) => write!(fmt, "Failed to connect to the display {:?}", d),
+            Error::DisplayConnection(None) => write!(fmt, "Failed to connect to the display"),
+            Error::UnsupportedVisual { depth, class } => write!(fmt, "There is no {} visual of depth {}", class, depth),
+            Error::Protocol(ref e) => e.fmt(fmt),
+            Error::ExtensionMissing(name) => write!(fmt, "{} is not available", name),
+            Error::SurfaceCreation(ref msg) => write!(fmt, "Failed to create a surface: {}", msg),
+            Error::ApplicationInit(what) => write!(fmt, "Failed to initialize the application: {}", what),
+            Error::Unsupported(op) => write!(fmt, "{} is not supported by this backend", op),
+            Error::Io(ref e) => write!(fmt, "I/O error: {}", e)
+        }
+    }
+}
+impl StdError for Error
+{
+    fn description(&self) -> &str { "appframe error" }
+    fn cause(&self) -> Option<&dyn StdError>
+    {
+        match *self { Error::Io(ref e) => Some(e), _ => None }
+    }
+}
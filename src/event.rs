@@ -0,0 +1,88 @@
+//! Window events delivered to `WindowEventDelegate`
+
+use WindowEventDelegate;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton { Left, Middle, Right, Other(u8) }
+
+/// Stage of a touch or a gesture. On XCB only gestures are `Cancelled`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TouchPhase { Started, Moved, Ended, Cancelled }
+
+/// Stage of a scroll. Wheels scroll in `Moved` steps only
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollPhase { Started, Moved, Ended, Momentum }
+/// Kind of the device that scrolled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollSource { Wheel, Touchpad, Other }
+/// Pixels per wheel notch in `WindowEventDelegate::smooth_scroll`.
+/// The scroll distance of a click on touchpads with libinput, so touchpad deltas follow the fingers
+pub const SCROLL_PIXELS_PER_NOTCH: f32 = 15.0;
+
+/// A touchpad gesture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture
+{
+    /// Two or more fingers moving together or apart. `scale` and `rotation`(clockwise degrees) are relative to the start
+    Pinch { scale: f32, rotation: f32 },
+    /// Fingers moving in the same direction, by (dx, dy) since the last event
+    Swipe { dx: f32, dy: f32, fingers: u32 },
+    /// Fingers resting on the touchpad
+    Hold
+}
+
+/// An event delegated to a window, in a backend independent form
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowEvent
+{
+    Resize { width: u32, height: u32 },
+    Focus(bool),
+    CloseRequested,
+    /// Key pressed(platform keycode)
+    KeyDown(u32),
+    /// Key released(platform keycode)
+    KeyUp(u32),
+    PointerMoved { x: f32, y: f32 },
+    PointerButton { button: MouseButton, pressed: bool, x: f32, y: f32 },
+    /// Scroll amount in wheel notches, fractional for touchpads and high-resolution wheels
+    Scroll { dx: f32, dy: f32, phase: ScrollPhase, source: ScrollSource },
+    /// Unaccelerated pointer movement in device units
+    RawMouseMotion { dx: f32, dy: f32 },
+    /// A touch point. `id` is unique among the touches in progress
+    Touch { phase: TouchPhase, id: u32, x: f32, y: f32 },
+    /// Stylus state. `pressure` is 0.0 to 1.0, tilts are -1.0 to 1.0 and bit n of `buttons` is the button n + 1(the tip is button 1)
+    Pen { x: f32, y: f32, pressure: f32, tilt_x: f32, tilt_y: f32, buttons: u32, eraser: bool },
+    Gesture { gesture: Gesture, phase: TouchPhase },
+    /// Text being composed by the input method. `cursor` is a byte range of `text`
+    ImePreedit { text: String, cursor: Option<(usize, usize)> },
+    /// Text committed by the input method
+    ImeCommit(String)
+}
+impl WindowEvent
+{
+    /// Call the corresponding callback of `d`.
+    /// Returns whether the close is accepted for `CloseRequested`, otherwise true
+    pub(crate) fn dispatch_to<WE: WindowEventDelegate>(&self, d: &WE) -> bool
+    {
+        match *self
+        {
+            WindowEvent::Resize { width, height } => d.resize(width, height, false),
+            WindowEvent::Focus(f) => d.focus(f),
+            WindowEvent::CloseRequested => return d.close_requested(),
+            WindowEvent::KeyDown(k) => d.key_down(k),
+            WindowEvent::KeyUp(k) => d.key_up(k),
+            WindowEvent::PointerMoved { x, y } => d.pointer_moved(x, y),
+            WindowEvent::PointerButton { button, pressed, x, y } => d.pointer_button(button, pressed, x, y),
+            WindowEvent::Scroll { dx, dy, phase, source } =>
+                d.smooth_scroll((dx * SCROLL_PIXELS_PER_NOTCH, dy * SCROLL_PIXELS_PER_NOTCH), phase, source),
+            WindowEvent::RawMouseMotion { dx, dy } => d.raw_mouse_motion(dx, dy),
+            WindowEvent::Touch { phase, id, x, y } => d.touch(phase, id, (x, y)),
+            WindowEvent::Pen { x, y, pressure, tilt_x, tilt_y, buttons, eraser } =>
+                d.pen((x, y), pressure, (tilt_x, tilt_y), buttons, eraser),
+            WindowEvent::Gesture { gesture, phase } => d.gesture(gesture, phase),
+            WindowEvent::ImePreedit { ref text, cursor } => d.ime_preedit(text, cursor),
+            WindowEvent::ImeCommit(ref text) => d.ime_commit(text)
+        }
+        true
+    }
+}
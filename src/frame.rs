@@ -0,0 +1,164 @@
+//! Frame scheduling shared by backends
+
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+use Rect;
+
+/// Damage rectangles are merged into their union beyond this count
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// How the rendering callback of a window is driven
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RenderMode
+{
+    /// Render only when the window is exposed or marked as dirty
+    OnDemand,
+    /// Render every frame, paced by the display if the backend supports it
+    #[default]
+    Continuous,
+    /// Render at the given rate(in Hz)
+    FixedRate(u32)
+}
+impl RenderMode
+{
+    /// Interval between frames for `FixedRate`
+    pub fn interval(&self) -> Option<Duration>
+    {
+        match *self
+        {
+            RenderMode::FixedRate(hz) if hz > 0 => Some(Duration::from_nanos(1_000_000_000 / hz as u64)),
+            _ => None
+        }
+    }
+}
+
+/// Timing information of a frame passed to `WindowEventDelegate::render_frame`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInfo
+{
+    /// Monotonic time of this frame, measured from the creation of the window(or the application on some backends)
+    pub timestamp: Duration,
+    /// Time elapsed since the previous frame(zero for the first frame)
+    pub delta: Duration,
+    /// Number of frames rendered before this frame
+    pub frame_number: u64,
+    /// Predicted time when this frame reaches the screen, on the same time base as `timestamp`(if the backend knows)
+    pub predicted_present: Option<Duration>,
+    /// Areas to redraw, accumulated since the previous frame. Empty means the whole view.
+    /// Can be passed to `View::present` as is
+    pub dirty_rects: Vec<Rect>
+}
+
+/// Generates `FrameInfo`s for a window
+pub(crate) struct FrameClock { last: Cell<Option<Duration>>, count: Cell<u64> }
+impl FrameClock
+{
+    pub fn new() -> Self { FrameClock { last: Cell::new(None), count: Cell::new(0) } }
+    pub fn tick(&self, now: Duration, predicted_present: Option<Duration>, dirty_rects: Vec<Rect>) -> FrameInfo
+    {
+        let delta = self.last.replace(Some(now)).map(|l| now.checked_sub(l).unwrap_or_default()).unwrap_or_default();
+        let frame_number = self.count.get(); self.count.set(frame_number + 1);
+        FrameInfo { timestamp: now, delta, frame_number, predicted_present, dirty_rects }
+    }
+}
+
+/// How `RenderMode::Continuous` is paced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pacing
+{
+    /// Render every iteration of the event loop
+    Free,
+    /// Render when the backend marks the schedule as dirty(e.g. vblank notifications)
+    External,
+    /// Render at the given interval(e.g. the refresh rate of the monitor)
+    Interval(Duration)
+}
+
+/// Per-window render scheduling state
+pub(crate) struct FrameSchedule
+{
+    mode: Cell<RenderMode>, pacing: Cell<Pacing>, dirty: Cell<bool>, next_due: Cell<Option<Duration>>,
+    /// Damage since the last frame. None if the whole view is invalidated
+    damage: RefCell<Option<Vec<Rect>>>
+}
+impl FrameSchedule
+{
+    pub fn new(mode: RenderMode) -> Self
+    {
+        FrameSchedule
+        {
+            mode: Cell::new(mode), pacing: Cell::new(Pacing::Free), dirty: Cell::new(true), next_due: Cell::new(None),
+            damage: RefCell::new(None)
+        }
+    }
+    pub fn mode(&self) -> RenderMode { self.mode.get() }
+    pub fn set_mode(&self, mode: RenderMode) { self.mode.set(mode); self.next_due.set(None); self.mark_dirty(); }
+    pub fn pacing(&self) -> Pacing { self.pacing.get() }
+    pub fn set_pacing(&self, pacing: Pacing) { self.pacing.set(pacing); self.next_due.set(None); }
+    /// Request a redraw of the whole view
+    pub fn mark_dirty(&self) { self.dirty.set(true); *self.damage.borrow_mut() = None; }
+    /// Request a redraw of the area
+    pub fn mark_dirty_rect(&self, r: Rect) { self.add_damage(r); self.dirty.set(true); }
+    /// Request a redraw of the accumulated damage
+    pub fn request_redraw(&self) { self.dirty.set(true); }
+    /// Accumulate damage without requesting a redraw yet
+    pub fn add_damage(&self, r: Rect)
+    {
+        if r.is_empty() { return; }
+        if let Some(ref mut rects) = *self.damage.borrow_mut()
+        {
+            rects.push(r);
+            if rects.len() > MAX_DIRTY_RECTS
+            {
+                let u = rects.iter().fold(Rect::default(), |u, r| u.union(r));
+                *rects = vec![u];
+            }
+        }
+    }
+    /// Damage accumulated for the frame being rendered. Empty means the whole view
+    pub fn take_damage(&self) -> Vec<Rect> { self.damage.replace(Some(Vec::new())).unwrap_or_default() }
+    /// A redraw is requested and not rendered yet
+    pub fn is_dirty(&self) -> bool { self.dirty.get() }
+    /// Clear the redraw request when a frame is rendered outside of `due`
+    #[cfg(all(target_os = "macos", not(feature = "with_headless")))]
+    pub fn rendered(&self) { self.dirty.set(false); }
+
+    /// Returns true if a frame should be rendered at `now`(time elapsed since the application started)
+    pub fn due(&self, now: Duration) -> bool
+    {
+        match self.effective_interval()
+        {
+            None => match self.mode.get()
+            {
+                RenderMode::Continuous if self.pacing.get() == Pacing::Free => { self.dirty.set(false); true },
+                _ => self.dirty.replace(false)
+            },
+            Some(interval) => match self.next_due.get()
+            {
+                Some(t) if t > now => self.dirty.replace(false),
+                // skip frames that could not be catched up
+                Some(t) if now - t < interval => { self.next_due.set(Some(t + interval)); self.dirty.set(false); true },
+                _ => { self.next_due.set(Some(now + interval)); self.dirty.set(false); true }
+            }
+        }
+    }
+    /// The time when `due` becomes true next without any external request, if any
+    pub fn deadline(&self) -> Option<Duration>
+    {
+        if self.dirty.get() { return Some(Duration::from_secs(0)); }
+        match (self.mode.get(), self.effective_interval())
+        {
+            (_, Some(_)) => Some(self.next_due.get().unwrap_or_default()),
+            (RenderMode::Continuous, None) if self.pacing.get() == Pacing::Free => Some(Duration::from_secs(0)),
+            _ => None
+        }
+    }
+    fn effective_interval(&self) -> Option<Duration>
+    {
+        match (self.mode.get(), self.pacing.get())
+        {
+            (RenderMode::Continuous, Pacing::Interval(iv)) => Some(iv),
+            (m, _) => m.interval()
+        }
+    }
+}
@@ -0,0 +1,722 @@
+//! Headless Runner: windows live in memory and time is virtual
+//!
+//! Tests drive the application by posting `WindowEvent`s and advancing the virtual clock.
+
+use std::rc::*;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::Duration;
+use std::io::{Write, BufRead, Result as IOResult, Error as IOError, ErrorKind};
+use {GUIApplicationRunner, EventDelegate, WindowEventDelegate, Window, View, WindowBuilder, RenderMode, WindowEvent};
+use {PixelBuffer, PixelFormat, Rect, Image, Error, Result, Clipboard, MimeData, ReceiveCallback, CursorIcon, GrabMode};
+use clipboard::{Selection, SelectionBackend};
+use frame::{FrameSchedule, FrameClock, FrameInfo, Pacing};
+use recording::{Recorder, Replay, Record, RecordedEvent};
+#[cfg(feature = "with_bedrock")] use bedrock as br;
+
+/// Refresh rate of the virtual display, used to pace `RenderMode::Continuous`
+pub const REFRESH_RATE: u32 = 60;
+
+/// Assigned from 1 in creation order
+pub type WindowID = u32;
+
+pub struct GUIApplication<E: EventDelegate>
+{
+    dg: E, clock: Cell<Duration>, quit_requested: Cell<bool>, next_id: Cell<WindowID>,
+    queue: RefCell<VecDeque<(WindowID, WindowEvent)>>, windows: RefCell<BTreeMap<WindowID, Rc<WindowEntry>>>,
+    /// Windows in creation order, indexed by recordings
+    created: RefCell<Vec<WindowID>>, recorder: RefCell<Option<Recorder>>, pacing: Cell<Pacing>,
+    /// In-memory selections. Reads are answered by the next `run_until_idle`
+    selections: RefCell<BTreeMap<Selection, MimeData>>,
+    selection_reads: RefCell<VecDeque<(ReceiveCallback, Option<Vec<u8>>)>>
+}
+impl<E: EventDelegate> GUIApplicationRunner<E> for GUIApplication<E>
+{
+    /// Runs until all windows are closed or `quit` is called, advancing the virtual clock frame by frame
+    fn try_run(delegate: E) -> Result<i32>
+    {
+        let app = GUIApplication::launch(delegate);
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        // nothing posts events from outside, so an idle application would never wake up
+        while !app.quit_requested.get() && !app.is_idle() { app.advance(frame); }
+        Ok(0)
+    }
+    fn event_delegate(&self) -> &E { &self.dg }
+}
+impl<E: EventDelegate> GUIApplication<E>
+{
+    /// Create an application and call `postinit` and `on_activated` of the delegate
+    pub fn launch(delegate: E) -> Rc<Self>
+    {
+        let app = Rc::new(GUIApplication
+        {
+            dg: delegate, clock: Cell::new(Duration::from_secs(0)), quit_requested: Cell::new(false),
+            next_id: Cell::new(1), queue: RefCell::new(VecDeque::new()), windows: RefCell::new(BTreeMap::new()),
+            created: RefCell::new(Vec::new()), recorder: RefCell::new(None), pacing: Cell::new(Pacing::External), selections: RefCell::new(BTreeMap::new()),
+            selection_reads: RefCell::new(VecDeque::new())
+        });
+        app.dg.postinit(&app);
+        app.dg.on_activated(&app);
+        app
+    }
+
+    /// Pace `Continuous` windows by the virtual refreshes of `advance`(the default, like XCB with the Present extension)
+    /// or by a timer started at their first frame(like backends without vblank notifications)
+    pub fn set_vblank_pacing(&self, enabled: bool)
+    {
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        self.pacing.set(if enabled { Pacing::External } else { Pacing::Interval(frame) });
+        for w in self.windows.borrow().values() { w.schedule.set_pacing(self.pacing.get()); }
+    }
+    /// Current virtual time
+    pub fn now(&self) -> Duration { self.clock.get() }
+    pub fn quit(&self) { self.quit_requested.set(true); }
+    pub fn is_quit_requested(&self) -> bool { self.quit_requested.get() }
+    /// IDs of the live windows, in creation order
+    pub fn windows(&self) -> Vec<WindowID> { self.windows.borrow().keys().cloned().collect() }
+    pub fn window_state(&self, id: WindowID) -> Option<WindowState> { self.windows.borrow().get(&id).map(|w| w.state()) }
+    /// The image most recently presented to the window, if any
+    pub fn presented_image(&self, id: WindowID) -> Option<PresentedImage>
+    {
+        self.windows.borrow().get(&id).and_then(|w| w.presented.borrow().clone())
+    }
+
+    /// Pixels last presented to the window(see `Window::capture`)
+    pub fn capture(&self, id: WindowID) -> Result<Image>
+    {
+        self.windows.borrow().get(&id).ok_or_else(|| Error::Io(IOError::new(ErrorKind::NotFound, "No such window")))?.capture()
+    }
+
+    /// Always true: gestures are posted as `WindowEvent::Gesture`
+    pub fn gestures_available(&self) -> bool { true }
+
+    /// The in-memory clipboard
+    pub fn clipboard(&self) -> Clipboard<'_> { Clipboard::new(self, Selection::Clipboard) }
+    /// The in-memory primary selection. None if the delegate opts out
+    pub fn primary_selection(&self) -> Option<Clipboard<'_>>
+    {
+        if self.dg.uses_primary_selection() { Some(Clipboard::new(self, Selection::Primary)) } else { None }
+    }
+    /// Current contents of the clipboard, for inspection without waiting for `run_until_idle`
+    pub fn clipboard_contents(&self) -> MimeData { self.selection_contents(Selection::Clipboard) }
+    pub fn primary_selection_contents(&self) -> MimeData { self.selection_contents(Selection::Primary) }
+    fn selection_contents(&self, which: Selection) -> MimeData
+    {
+        self.selections.borrow().get(&which).cloned().unwrap_or_default()
+    }
+
+    /// Queue an event for the window. It is delivered by the next `run_until_idle` or `advance`
+    pub fn post_event(&self, window: WindowID, event: WindowEvent) { self.queue.borrow_mut().push_back((window, event)); }
+    /// Deliver all queued events and clipboard reads, and render the windows due at the current virtual time.
+    /// Each window is rendered at most once per call
+    pub fn run_until_idle(&self)
+    {
+        let mut rendered = BTreeSet::new();
+        loop
+        {
+            let e = self.queue.borrow_mut().pop_front();
+            if let Some((id, e)) = e { self.dispatch(id, &e); continue; }
+            let r = self.selection_reads.borrow_mut().pop_front();
+            if let Some((callback, data)) = r { callback(data); continue; }
+            if !self.render_due_windows(&mut rendered) { break; }
+        }
+    }
+    /// Advance the virtual clock by `d`, rendering every frame scheduled in the meantime
+    pub fn advance(&self, d: Duration)
+    {
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        let target = self.clock.get() + d;
+        loop
+        {
+            self.run_until_idle();
+            let now = self.clock.get();
+            // windows dirtied while rendering are rendered at the next virtual refresh
+            let deadline = self.next_frame_deadline().map(|t| if t <= now { now + frame } else { t });
+            let vblank = self.next_vblank(now);
+            match deadline.into_iter().chain(vblank).min()
+            {
+                Some(t) if t <= target && !self.quit_requested.get() =>
+                {
+                    self.clock.set(t);
+                    if Some(t) == vblank { self.vblank(); }
+                },
+                _ => break
+            }
+        }
+        self.clock.set(target);
+        self.run_until_idle();
+    }
+    /// True if all windows are closed, or no event, clipboard read or frame is pending
+    pub fn is_idle(&self) -> bool
+    {
+        if self.windows.borrow().values().all(|w| w.closed.get()) { return true; }
+        self.queue.borrow().is_empty() && self.selection_reads.borrow().is_empty() &&
+            self.next_frame_deadline().is_none() && self.next_vblank(self.clock.get()).is_none()
+    }
+
+    /// Record delegated events and rendered frames from now on
+    pub fn record_to<W: Write + 'static>(&self, sink: W) -> IOResult<()>
+    {
+        *self.recorder.borrow_mut() = Some(Recorder::new(sink)?); Ok(())
+    }
+    /// Feed a recording through the dispatcher. The virtual clock follows the recorded timestamps
+    /// and windows are rendered only where the recording says
+    pub fn replay<R: BufRead>(&self, source: R) -> IOResult<()>
+    {
+        for r in Replay::new(source)?
+        {
+            let r = r?;
+            if r.timestamp > self.clock.get() { self.clock.set(r.timestamp); }
+            let id = match self.created.borrow().get(r.window as usize) { Some(&id) => id, None => continue };
+            match r.event
+            {
+                RecordedEvent::Window(e) => self.dispatch(id, &e),
+                RecordedEvent::Frame { predicted_present } =>
+                {
+                    let w = self.windows.borrow().get(&id).cloned();
+                    if let Some(w) = w { self.render_window(id, &w, predicted_present); }
+                },
+                e => self.notify(id, e)
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the virtual clock to `t`(never backwards) without rendering, deliver queued events
+    /// and render the window once regardless of its render mode
+    pub fn render_at(&self, id: WindowID, t: Duration)
+    {
+        if t > self.clock.get() { self.clock.set(t); }
+        loop
+        {
+            let e = self.queue.borrow_mut().pop_front();
+            match e { Some((id, e)) => self.dispatch(id, &e), None => break }
+        }
+        let w = match self.windows.borrow().get(&id) { Some(w) => w.clone(), None => return };
+        let now = self.clock.get();
+        self.render_window(id, &w, Some(now));
+    }
+    fn render_window(&self, id: WindowID, w: &WindowEntry, predicted_present: Option<Duration>)
+    {
+        let now = self.clock.get();
+        self.record(id, RecordedEvent::Frame { predicted_present });
+        w.frames.set(w.frames.get() + 1);
+        w.callbacks.render(&w.clock.tick(now, predicted_present, w.schedule.take_damage()));
+    }
+    fn record(&self, id: WindowID, event: RecordedEvent)
+    {
+        let mut rec = self.recorder.borrow_mut();
+        let window = match self.created.borrow().iter().position(|&c| c == id) { Some(i) => i as u32, None => return };
+        let failed = rec.as_mut().is_some_and(|r| r.record(&Record { timestamp: self.clock.get(), window, event }).is_err());
+        // a broken sink stops recording rather than the application
+        if failed { *rec = None; }
+    }
+
+    /// Record and call a callback other than an event
+    fn notify(&self, id: WindowID, e: RecordedEvent)
+    {
+        let w = match self.windows.borrow().get(&id) { Some(w) if !w.closed.get() => w.clone(), _ => return };
+        self.record(id, e.clone());
+        w.callbacks.notify(&e);
+    }
+    fn dispatch(&self, id: WindowID, e: &WindowEvent)
+    {
+        let w = match self.windows.borrow().get(&id) { Some(w) if !w.closed.get() => w.clone(), _ => return };
+        self.record(id, RecordedEvent::Window(e.clone()));
+        match *e
+        {
+            WindowEvent::Resize { width, height } =>
+            {
+                w.size.set((width as _, height as _)); w.schedule.mark_dirty();
+            },
+            WindowEvent::Focus(f) =>
+            {
+                w.focused.set(f);
+                if !f { w.cursor_grab.set(GrabMode::None); }
+            },
+            _ => ()
+        }
+        if !w.callbacks.dispatch(e) { return; }
+        if let WindowEvent::CloseRequested = *e
+        {
+            w.closed.set(true); w.visible.set(false); w.focused.set(false);
+            if self.windows.borrow().values().all(|w| w.closed.get()) { self.quit(); }
+        }
+    }
+    fn next_frame_deadline(&self) -> Option<Duration>
+    {
+        self.windows.borrow().values().filter(|w| w.visible.get()).filter_map(|w| w.schedule.deadline()).min()
+    }
+    /// Virtual refreshes happen at multiples of the frame interval, while a window is paced by them
+    fn next_vblank(&self, now: Duration) -> Option<Duration>
+    {
+        if !self.windows.borrow().values().any(|w| w.paced_by_vblank()) { return None; }
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        let n = now.as_nanos() / frame.as_nanos() + 1;
+        Some(Duration::from_nanos((n * frame.as_nanos()) as u64))
+    }
+    fn vblank(&self)
+    {
+        for w in self.windows.borrow().values().filter(|w| w.paced_by_vblank()) { w.schedule.request_redraw(); }
+    }
+    /// Returns false if no window is rendered
+    fn render_due_windows(&self, rendered: &mut BTreeSet<WindowID>) -> bool
+    {
+        let now = self.clock.get();
+        // callbacks may create or drop windows
+        let due = self.windows.borrow().iter()
+            .filter(|&(id, w)| !rendered.contains(id) && w.visible.get() && w.schedule.due(now))
+            .map(|(&id, w)| (id, w.clone())).collect::<Vec<_>>();
+        for &(id, ref w) in &due
+        {
+            rendered.insert(id);
+            self.render_window(id, w, Some(now));
+        }
+        !due.is_empty()
+    }
+}
+
+impl<E: EventDelegate> SelectionBackend for GUIApplication<E>
+{
+    fn set_selection(&self, which: Selection, data: MimeData)
+    {
+        let mut s = self.selections.borrow_mut();
+        if data.is_empty() { s.remove(&which); } else { s.insert(which, data); }
+    }
+    /// The data is taken at the time of the request
+    fn get_selection(&self, which: Selection, mime: &str, callback: ReceiveCallback)
+    {
+        let data = self.selections.borrow().get(&which).and_then(|d| d.get(mime).cloned());
+        self.selection_reads.borrow_mut().push_back((callback, data));
+    }
+}
+
+#[cfg(feature = "with_bedrock")]
+#[repr(C)] #[allow(non_snake_case)]
+struct VkHeadlessSurfaceCreateInfoEXT
+{
+    sType: br::vk::VkStructureType, pNext: *const ::libc::c_void, flags: br::vk::VkFlags
+}
+#[cfg(feature = "with_bedrock")]
+const VK_STRUCTURE_TYPE_HEADLESS_SURFACE_CREATE_INFO_EXT: br::vk::VkStructureType = 1000256000;
+#[cfg(feature = "with_bedrock")]
+#[allow(non_camel_case_types)]
+type PFN_vkCreateHeadlessSurfaceEXT = extern "system" fn(instance: br::vk::VkInstance,
+    pCreateInfo: *const VkHeadlessSurfaceCreateInfoEXT, pAllocator: *const br::vk::VkAllocationCallbacks,
+    pSurface: *mut br::vk::VkSurfaceKHR) -> br::vk::VkResult;
+
+/// Surfaces are created by `VK_EXT_headless_surface`, so the instance must be created with the extension enabled
+#[cfg(feature = "with_bedrock")]
+impl<E: EventDelegate> ::BedrockRenderingServer for GUIApplication<E>
+{
+    /// Headless surfaces can be presented from any queue family
+    fn presentation_support(&self, _adapter: &br::PhysicalDevice, _rendered_qf: u32) -> bool { true }
+    fn create_surface<WE: WindowEventDelegate>(&self, _w: &NativeView<WE>, instance: &br::Instance)
+        -> Result<br::Surface>
+    {
+        use br::VkHandle;
+        let create: PFN_vkCreateHeadlessSurfaceEXT = instance.extra_procedure("vkCreateHeadlessSurfaceEXT")
+            .ok_or(Error::ExtensionMissing("VK_EXT_headless_surface"))?;
+        let cinfo = VkHeadlessSurfaceCreateInfoEXT
+        {
+            sType: VK_STRUCTURE_TYPE_HEADLESS_SURFACE_CREATE_INFO_EXT, pNext: ::std::ptr::null(), flags: 0
+        };
+        let mut h = br::vk::VK_NULL_HANDLE as _;
+        let r = create(instance.native_ptr(), &cinfo, ::std::ptr::null(), &mut h);
+        if r != br::vk::VK_SUCCESS
+        {
+            return Err(Error::SurfaceCreation(format!("vkCreateHeadlessSurfaceEXT returned {}", r)));
+        }
+        Ok(unsafe { br::Surface::from_raw(instance, h) })
+    }
+}
+
+/// Contents presented to a headless window
+#[derive(Clone, Debug, PartialEq)]
+pub struct PresentedImage
+{
+    pub size: (u32, u32), pub format: PixelFormat, pub data: Vec<u8>,
+    /// The window is transparent, so the 4th byte of each pixel is alpha
+    pub has_alpha: bool,
+    /// Areas updated by the presentation(the whole image for swapchains)
+    pub damage: Vec<Rect>,
+    /// Value of `WindowState::frames_rendered` when the image was presented
+    pub frame: u64
+}
+
+/// Snapshot of the state of a headless window
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowState
+{
+    pub caption: String, pub size: (u16, u16), pub visible: bool, pub focused: bool, pub closed: bool,
+    pub render_mode: RenderMode, pub frames_rendered: u64,
+    /// A redraw is requested and not rendered yet
+    pub redraw_requested: bool,
+    /// Set by `Window::set_ime_cursor_area`
+    pub ime_cursor_area: Option<Rect>,
+    /// Set by `Window::set_cursor`. None while a custom cursor is shown
+    pub cursor: Option<CursorIcon>,
+    pub cursor_visible: bool,
+    /// Set by `Window::set_cursor_grab`. Reset when the window loses focus
+    pub cursor_grab: GrabMode
+}
+
+/// Type-erased window callbacks used by the dispatcher
+trait WindowCallbacks
+{
+    fn render(&self, frame: &FrameInfo);
+    fn dispatch(&self, e: &WindowEvent) -> bool;
+    fn notify(&self, e: &RecordedEvent);
+}
+impl<WE: WindowEventDelegate> WindowCallbacks for Weak<WE>
+{
+    fn render(&self, frame: &FrameInfo) { if let Some(e) = self.upgrade() { e.render_frame(frame); } }
+    fn dispatch(&self, e: &WindowEvent) -> bool { self.upgrade().is_none_or(|d| e.dispatch_to(&*d)) }
+    fn notify(&self, e: &RecordedEvent) { if let Some(d) = self.upgrade() { e.notify(&*d); } }
+}
+struct WindowEntry
+{
+    caption: String, size: Cell<(u16, u16)>, visible: Cell<bool>, focused: Cell<bool>, closed: Cell<bool>, transparent: bool,
+    schedule: FrameSchedule, clock: FrameClock, frames: Cell<u64>, presented: RefCell<Option<PresentedImage>>,
+    pixels: RefCell<PixelBuffer>, ime_cursor_area: Cell<Option<Rect>>,
+    cursor: Cell<Option<CursorIcon>>, cursor_visible: Cell<bool>, cursor_grab: Cell<GrabMode>,
+    callbacks: Box<dyn WindowCallbacks>
+}
+impl WindowEntry
+{
+    fn state(&self) -> WindowState
+    {
+        WindowState
+        {
+            caption: self.caption.clone(), size: self.size.get(), visible: self.visible.get(),
+            focused: self.focused.get(), closed: self.closed.get(), render_mode: self.schedule.mode(),
+            frames_rendered: self.frames.get(), redraw_requested: self.schedule.is_dirty(), ime_cursor_area: self.ime_cursor_area.get(),
+            cursor: self.cursor.get(), cursor_visible: self.cursor_visible.get(), cursor_grab: self.cursor_grab.get()
+        }
+    }
+    fn paced_by_vblank(&self) -> bool
+    {
+        self.visible.get() && self.schedule.mode() == RenderMode::Continuous && self.schedule.pacing() == Pacing::External
+    }
+    fn capture(&self) -> Result<Image>
+    {
+        match *self.presented.borrow()
+        {
+            Some(ref p) =>
+            {
+                let mut data = p.data.clone();
+                if !p.has_alpha { for px in data.chunks_mut(4) { px[3] = 0xff; } }
+                Ok(Image { width: p.size.0, height: p.size.1, format: p.format, has_alpha: p.has_alpha, data })
+            },
+            None => Err(Error::Io(IOError::new(ErrorKind::NotFound, "Nothing has been presented to the window")))
+        }
+    }
+}
+
+pub struct NativeWindow<WE: WindowEventDelegate>
+{
+    id: WindowID, server: Rc<GUIApplication<WE::ClientDelegate>>, entry: Rc<WindowEntry>
+}
+pub type NativeView<WE> = NativeWindow<WE>;
+impl<WE: WindowEventDelegate> NativeWindow<WE>
+{
+    pub fn id(&self) -> WindowID { self.id }
+    pub fn state(&self) -> WindowState { self.entry.state() }
+    pub fn presented_image(&self) -> Option<PresentedImage> { self.entry.presented.borrow().clone() }
+}
+/// Headless swapchains discard their images, so presented images are read back by the backend
+#[cfg(feature = "with_bedrock")]
+impl<WE: WindowEventDelegate> NativeWindow<WE>
+{
+    /// Copy a swapchain image of the window to host memory and keep it as the presented image.
+    /// Call it after rendering and before presenting: `image` must be acquired, idle in `PresentSrc` layout on `queue_family`
+    /// and have `TRANSFER_SRC` usage. Waits for the copy
+    pub fn read_back_presented(&self, adapter: &br::PhysicalDevice, device: &br::Device, queue_family: u32,
+        image: &br::Image, format: PixelFormat) -> br::Result<()>
+    {
+        let (width, height) = (self.entry.size.get().0 as u32, self.entry.size.get().1 as u32);
+        let bytes = width as usize * height as usize * 4;
+        let buf = br::BufferDesc::new(bytes, br::BufferUsage::TRANSFER_DEST).create(device)?;
+        let memindex = adapter.memory_properties().find_host_visible_index()
+            .ok_or(br::VkResultBox(br::vk::VK_ERROR_OUT_OF_HOST_MEMORY))?;
+        let mem = br::DeviceMemory::allocate(device, buf.requirements().size as _, memindex)?;
+        buf.bind(&mem, 0)?;
+        let pool = br::CommandPool::new(device, queue_family, true, false)?;
+        let commands = pool.alloc(1, true)?;
+        let subref = br::ImageSubref::color(image, 0, 0);
+        commands[0].begin()?
+            .pipeline_barrier(br::PipelineStageFlags::BOTTOM_OF_PIPE, br::PipelineStageFlags::TRANSFER, true, &[], &[],
+                &[br::ImageMemoryBarrier::new(&subref, br::ImageLayout::PresentSrc, br::ImageLayout::TransferSrcOpt)])
+            .copy_image_to_buffer(image, br::ImageLayout::TransferSrcOpt, &buf, &[br::vk::VkBufferImageCopy
+            {
+                bufferOffset: 0, bufferRowLength: 0, bufferImageHeight: 0,
+                imageSubresource: br::vk::VkImageSubresourceLayers
+                {
+                    aspectMask: br::vk::VK_IMAGE_ASPECT_COLOR_BIT, mipLevel: 0, baseArrayLayer: 0, layerCount: 1
+                },
+                imageOffset: br::vk::VkOffset3D { x: 0, y: 0, z: 0 },
+                imageExtent: br::vk::VkExtent3D { width, height, depth: 1 }
+            }])
+            .pipeline_barrier(br::PipelineStageFlags::TRANSFER, br::PipelineStageFlags::HOST, true, &[],
+                &[br::BufferMemoryBarrier::new(&buf, 0 .. bytes, br::AccessFlags::TRANSFER.write, br::AccessFlags::HOST.read)],
+                &[br::ImageMemoryBarrier::new(&subref, br::ImageLayout::TransferSrcOpt, br::ImageLayout::PresentSrc)]);
+        let fence = br::Fence::new(device, false)?;
+        device.queue(queue_family, 0).submit(&[br::SubmissionBatch
+        {
+            command_buffers: ::std::borrow::Cow::Borrowed(&commands), .. Default::default()
+        }], Some(&fence))?;
+        fence.wait()?;
+        let data = unsafe { mem.map(0 .. bytes)?.slice::<u8>(0, bytes).to_owned() };
+        *self.entry.presented.borrow_mut() = Some(PresentedImage
+        {
+            size: (width, height), format, data, has_alpha: self.entry.transparent, damage: vec![Rect::new(0, 0, width, height)],
+            frame: self.entry.frames.get()
+        });
+        Ok(())
+    }
+}
+impl<WE: WindowEventDelegate> Window for NativeWindow<WE>
+{
+    fn show(&self) { self.entry.visible.set(true); self.entry.schedule.mark_dirty(); }
+    #[cfg(feature = "with_bedrock")]
+    fn mark_dirty(&self) { self.entry.schedule.mark_dirty(); }
+    fn mark_dirty_rect(&self, r: Rect) { self.entry.schedule.mark_dirty_rect(r); }
+    fn set_render_mode(&self, mode: RenderMode) { self.entry.schedule.set_mode(mode); }
+    /// The last presented image
+    fn capture(&self) -> Result<Image> { self.entry.capture() }
+    fn set_ime_cursor_area(&self, area: Rect) { self.entry.ime_cursor_area.set(Some(area)); }
+    fn set_cursor(&self, icon: CursorIcon) { self.entry.cursor.set(Some(icon)); }
+    fn set_custom_cursor(&self, _image: &Image, _hotspot: (u32, u32)) -> Result<()> { self.entry.cursor.set(None); Ok(()) }
+    fn set_cursor_visible(&self, visible: bool) { self.entry.cursor_visible.set(visible); }
+    /// Fails while the window is hidden or unfocused
+    fn set_cursor_grab(&self, mode: GrabMode) -> bool
+    {
+        if mode != GrabMode::None && !(self.entry.visible.get() && self.entry.focused.get()) { return false; }
+        self.entry.cursor_grab.set(mode); true
+    }
+}
+impl<WE: WindowEventDelegate> View for NativeView<WE>
+{
+    fn size(&self) -> (u16, u16) { self.entry.size.get() }
+    fn pixel_buffer(&self) -> RefMut<'_, PixelBuffer>
+    {
+        let mut b = self.entry.pixels.borrow_mut();
+        b.fit(self.width() as _, self.height() as _); b
+    }
+    /// The buffer is kept as the presented image of the window
+    fn present(&self, damage: &[Rect]) -> Result<()>
+    {
+        let b = self.pixel_buffer();
+        *self.entry.presented.borrow_mut() = Some(PresentedImage
+        {
+            size: (b.width(), b.height()), format: b.format(), data: b.data().to_owned(), has_alpha: self.entry.transparent,
+            damage: b.clip_damage(damage), frame: self.entry.frames.get()
+        });
+        Ok(())
+    }
+}
+impl<WE: WindowEventDelegate> Drop for NativeWindow<WE>
+{
+    fn drop(&mut self) { self.server.windows.borrow_mut().remove(&self.id); }
+}
+
+pub struct NativeWindowBuilder<'c>
+{
+    width: u16, height: u16, caption: &'c str, transparent: bool, render_mode: RenderMode
+}
+impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
+{
+    fn new(width: u16, height: u16, caption: &'c str) -> Self
+    {
+        NativeWindowBuilder { width, height, caption, transparent: false, render_mode: RenderMode::default() }
+    }
+    fn closable(&mut self, _c: bool) -> &mut Self { self }
+    fn resizable(&mut self, _c: bool) -> &mut Self { self }
+    /// Presented images of transparent windows keep their alpha
+    fn transparent(&mut self, c: bool) -> &mut Self { self.transparent = c; self }
+    fn render_mode(&mut self, mode: RenderMode) -> &mut Self { self.render_mode = mode; self }
+
+    fn create<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
+        -> Result<NativeWindow<WE>>
+    {
+        let id = server.next_id.get(); server.next_id.set(id + 1);
+        let schedule = FrameSchedule::new(self.render_mode);
+        schedule.set_pacing(server.pacing.get());
+        let entry = Rc::new(WindowEntry
+        {
+            caption: self.caption.to_owned(), size: Cell::new((self.width, self.height)),
+            visible: Cell::new(false), focused: Cell::new(false), closed: Cell::new(false), transparent: self.transparent,
+            schedule, clock: FrameClock::new(), frames: Cell::new(0), presented: RefCell::new(None),
+            pixels: RefCell::new(PixelBuffer::new(self.width as _, self.height as _, PixelFormat::Rgba8)),
+            ime_cursor_area: Cell::new(None), cursor: Cell::new(Some(CursorIcon::Default)), cursor_visible: Cell::new(true),
+            cursor_grab: Cell::new(GrabMode::None),
+            callbacks: Box::new(Rc::downgrade(event))
+        });
+        server.windows.borrow_mut().insert(id, entry.clone());
+        server.created.borrow_mut().push(id);
+        Ok(NativeWindow { id, server: server.clone(), entry })
+    }
+    #[cfg(feature = "with_bedrock")]
+    fn create_renderable<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
+        -> Result<NativeWindow<WE>> where WE::ClientDelegate: 'static
+    {
+        let w = self.create(server, event)?;
+        event.init_view(&w);
+        Ok(w)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use {DropEffect, DropData};
+
+    struct App { mode: RenderMode, transparent: bool, window: RefCell<Option<(Rc<Win>, NativeWindow<Win>)>> }
+    impl EventDelegate for App
+    {
+        fn postinit(&self, app: &Rc<GUIApplication<Self>>)
+        {
+            let d = Rc::new(Win { frames: RefCell::new(Vec::new()), drags: RefCell::new(Vec::new()) });
+            let w = NativeWindowBuilder::new(64, 48, "test").render_mode(self.mode).transparent(self.transparent)
+                .create(app, &d).unwrap();
+            w.show();
+            *self.window.borrow_mut() = Some((d, w));
+        }
+    }
+    struct Win { frames: RefCell<Vec<Duration>>, drags: RefCell<Vec<String>> }
+    impl WindowEventDelegate for Win
+    {
+        type ClientDelegate = App;
+        fn render_frame(&self, frame: &FrameInfo) { self.frames.borrow_mut().push(frame.timestamp); }
+        fn drag_entered(&self, formats: &[String], x: f32, y: f32) -> DropEffect
+        {
+            self.drags.borrow_mut().push(format!("entered {:?} at {},{}", formats, x, y)); DropEffect::Copy
+        }
+        fn dropped(&self, data: &DropData) { self.drags.borrow_mut().push(format!("dropped {:?}", data.uris())); }
+        fn drag_finished(&self, effect: DropEffect) { self.drags.borrow_mut().push(format!("finished {:?}", effect)); }
+    }
+    fn launch(mode: RenderMode) -> Rc<GUIApplication<App>>
+    {
+        GUIApplication::launch(App { mode, transparent: false, window: RefCell::new(None) })
+    }
+    fn frames(app: &GUIApplication<App>) -> Vec<Duration>
+    {
+        app.dg.window.borrow().as_ref().unwrap().0.frames.borrow().clone()
+    }
+    fn ms(n: u64) -> Duration { Duration::from_millis(n) }
+
+    #[test]
+    fn continuous_windows_follow_virtual_refreshes()
+    {
+        let app = launch(RenderMode::Continuous);
+        app.advance(ms(50));
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        assert_eq!(frames(&app), vec![ms(0), frame, frame * 2, frame * 3]);
+        assert_eq!(app.now(), ms(50));
+    }
+    #[test]
+    fn timer_paced_windows_follow_their_first_frame()
+    {
+        let app = launch(RenderMode::OnDemand);
+        app.set_vblank_pacing(false);
+        app.advance(ms(5));
+        app.dg.window.borrow().as_ref().unwrap().1.set_render_mode(RenderMode::Continuous);
+        app.advance(ms(35));
+        let frame = RenderMode::FixedRate(REFRESH_RATE).interval().unwrap();
+        assert_eq!(frames(&app), vec![ms(0), ms(5), ms(5) + frame, ms(5) + frame * 2]);
+    }
+    #[test]
+    fn fixed_rate_windows_render_at_their_rate()
+    {
+        let app = launch(RenderMode::FixedRate(10));
+        app.advance(ms(250));
+        assert_eq!(frames(&app), vec![ms(0), ms(100), ms(200)]);
+    }
+    #[test]
+    fn on_demand_windows_render_when_dirty()
+    {
+        let app = launch(RenderMode::OnDemand);
+        app.advance(ms(100));
+        assert_eq!(frames(&app), vec![ms(0)]);
+        assert!(!app.window_state(1).unwrap().redraw_requested);
+        app.post_event(1, WindowEvent::Resize { width: 32, height: 32 });
+        app.advance(ms(100));
+        assert_eq!(frames(&app), vec![ms(0), ms(100)]);
+        assert_eq!(app.window_state(1).unwrap().size, (32, 32));
+        assert!(app.is_idle());
+        app.dg.window.borrow().as_ref().unwrap().1.mark_dirty_rect(Rect::new(0, 0, 8, 8));
+        assert!(app.window_state(1).unwrap().redraw_requested);
+        app.advance(ms(100));
+        assert_eq!(frames(&app), vec![ms(0), ms(100), ms(200)]);
+        assert!(!app.window_state(1).unwrap().redraw_requested);
+    }
+    #[test]
+    fn captures_keep_alpha_of_transparent_windows()
+    {
+        for &transparent in &[false, true]
+        {
+            let app = GUIApplication::launch(App { mode: RenderMode::OnDemand, transparent, window: RefCell::new(None) });
+            app.dg.window.borrow().as_ref().unwrap().1.present(&[]).unwrap();
+            let image = app.capture(1).unwrap();
+            assert_eq!(image.has_alpha, transparent);
+            // the buffer starts cleared to zero
+            assert_eq!(image.rgba_at(0, 0)[3], if transparent { 0 } else { 0xff });
+        }
+    }
+    #[test]
+    fn replays_render_where_recorded()
+    {
+        struct Sink(Rc<RefCell<Vec<u8>>>);
+        impl Write for Sink
+        {
+            fn write(&mut self, b: &[u8]) -> IOResult<usize> { self.0.borrow_mut().extend_from_slice(b); Ok(b.len()) }
+            fn flush(&mut self) -> IOResult<()> { Ok(()) }
+        }
+        let bytes = Rc::new(RefCell::new(Vec::new()));
+        let recorded = launch(RenderMode::FixedRate(10));
+        recorded.record_to(Sink(bytes.clone())).unwrap();
+        recorded.post_event(1, WindowEvent::Resize { width: 32, height: 16 });
+        recorded.advance(ms(250));
+
+        let replayed = launch(RenderMode::OnDemand);
+        replayed.replay(&bytes.borrow()[..]).unwrap();
+        assert_eq!(frames(&replayed), vec![ms(0), ms(100), ms(200)]);
+        assert_eq!(replayed.window_state(1).unwrap().size, (32, 16));
+        assert_eq!(replayed.now(), ms(200));
+    }
+    #[test]
+    fn replays_deliver_drag_and_drop_callbacks()
+    {
+        let app = launch(RenderMode::OnDemand);
+        let recording = concat!("{\"format\":\"appframe-recording\",\"version\":1}\n",
+            "{\"t\":0,\"window\":0,\"type\":\"drag_entered\",\"formats\":\"text/uri-list\",\"x\":3,\"y\":4}\n",
+            "{\"t\":5,\"window\":0,\"type\":\"dropped\",\"formats\":\"text/uri-list\",\"data\":\"ZmlsZTovLy9h\"}\n",
+            "{\"t\":9,\"window\":0,\"type\":\"drag_finished\",\"effect\":\"move\"}\n");
+        app.replay(recording.as_bytes()).unwrap();
+        let drags = app.dg.window.borrow().as_ref().unwrap().0.drags.borrow().clone();
+        assert_eq!(drags, vec!["entered [\"text/uri-list\"] at 3,4", "dropped [\"file:///a\"]", "finished Move"]);
+    }
+    #[test]
+    fn closing_the_last_window_quits()
+    {
+        let app = launch(RenderMode::Continuous);
+        app.post_event(1, WindowEvent::CloseRequested);
+        app.run_until_idle();
+        assert!(app.is_quit_requested());
+        assert!(app.window_state(1).unwrap().closed);
+    }
+    #[test]
+    fn run_returns_without_windows()
+    {
+        struct Empty;
+        impl EventDelegate for Empty { }
+        assert_eq!(GUIApplication::try_run(Empty).unwrap(), 0);
+    }
+    #[test]
+    fn run_returns_when_nothing_is_scheduled()
+    {
+        let app = App { mode: RenderMode::OnDemand, transparent: false, window: RefCell::new(None) };
+        assert_eq!(GUIApplication::try_run(app).unwrap(), 0);
+    }
+}
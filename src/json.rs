@@ -0,0 +1,173 @@
+//! Minimal JSON helpers for the line-based formats
+
+pub fn field<'s>(fields: &[(&str, &'s str)], key: &str) -> Option<&'s str>
+{
+    fields.iter().find(|&&(k, _)| k == key).map(|&(_, v)| v)
+}
+/// Split a flat JSON object into keys and raw values(strings keep their quotes and escapes). Nested values are not supported
+pub fn parse_object(s: &str) -> Option<Vec<(&str, &str)>>
+{
+    let body = s.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut fields = Vec::new();
+    let mut rest = body.trim();
+    while !rest.is_empty()
+    {
+        if !rest.starts_with('"') { return None; }
+        let kend = string_end(rest)?;
+        let key = &rest[1 .. kend];
+        rest = rest[kend + 1 ..].trim_start().strip_prefix(':')?.trim_start();
+        let vend = if rest.starts_with('"') { string_end(rest)? + 1 } else { rest.find(',').unwrap_or(rest.len()) };
+        fields.push((key, rest[.. vend].trim()));
+        rest = rest[vend ..].trim_start();
+        if let Some(r) = rest.strip_prefix(',') { rest = r.trim_start(); }
+    }
+    Some(fields)
+}
+/// Index of the closing quote of the string starting at the head of `s`
+fn string_end(s: &str) -> Option<usize>
+{
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1)
+    {
+        match c { '\\' if !escaped => escaped = true, '"' if !escaped => return Some(i), _ => escaped = false }
+    }
+    None
+}
+/// Quote a string
+pub fn string(s: &str) -> String
+{
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars()
+    {
+        match c
+        {
+            '"' => r.push_str("\\\""), '\\' => r.push_str("\\\\"), '\n' => r.push_str("\\n"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c)
+        }
+    }
+    r.push('"'); r
+}
+/// Decode the escapes of a string without its quotes
+pub fn unescape(s: &str) -> Option<String>
+{
+    let mut r = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next()
+    {
+        if c != '\\' { r.push(c); continue; }
+        r.push(match chars.next()?
+        {
+            'n' => '\n', 't' => '\t', 'r' => '\r', 'b' => '\u{8}', 'f' => '\u{c}',
+            'u' =>
+            {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                if hex.len() != 4 { return None; }
+                ::std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+            },
+            c => c
+        });
+    }
+    Some(r)
+}
+/// Value of a string field, without the quotes
+pub fn string_field<'s>(fields: &[(&str, &'s str)], key: &str) -> Option<&'s str>
+{
+    field(fields, key).filter(|v| v.len() >= 2).and_then(|v| v.strip_prefix('"')?.strip_suffix('"'))
+}
+
+/// Standard base64 with padding
+pub fn base64(data: &[u8]) -> String
+{
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for c in data.chunks(3)
+    {
+        let n = (c[0] as u32) << 16 | (*c.get(1).unwrap_or(&0) as u32) << 8 | *c.get(2).unwrap_or(&0) as u32;
+        for i in 0 .. 4
+        {
+            s.push(if i <= c.len() { TABLE[(n >> (18 - i * 6)) as usize & 63] as char } else { '=' });
+        }
+    }
+    s
+}
+/// Decode standard base64 with padding
+pub fn unbase64(s: &str) -> Option<Vec<u8>>
+{
+    if !s.len().is_multiple_of(4) { return None; }
+    let mut d = Vec::with_capacity(s.len() / 4 * 3);
+    for c in s.as_bytes().chunks(4)
+    {
+        let pad = c.iter().rev().take_while(|&&b| b == b'=').count();
+        if pad > 2 { return None; }
+        let mut n = 0u32;
+        for &b in &c[.. 4 - pad]
+        {
+            n = n << 6 | match b
+            {
+                b'A' ..= b'Z' => b - b'A', b'a' ..= b'z' => b - b'a' + 26, b'0' ..= b'9' => b - b'0' + 52,
+                b'+' => 62, b'/' => 63, _ => return None
+            } as u32;
+        }
+        n <<= 6 * pad;
+        d.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][.. 3 - pad]);
+    }
+    Some(d)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn flat_objects()
+    {
+        let fields = parse_object(r#" { "a": 1, "b":"x,\"y\"" ,"c" :true} "#).unwrap();
+        assert_eq!(fields, vec![("a", "1"), ("b", r#""x,\"y\"""#), ("c", "true")]);
+        assert_eq!(field(&fields, "c"), Some("true"));
+        assert_eq!(field(&fields, "d"), None);
+        assert_eq!(string_field(&fields, "b"), Some(r#"x,\"y\""#));
+        assert_eq!(string_field(&fields, "a"), None);
+        assert_eq!(parse_object("{}"), Some(vec![]));
+    }
+    #[test]
+    fn malformed_objects()
+    {
+        for s in &["", "[1]", "{\"a\":1", "{a:1}", "{\"a\" 1}", "{\"a\":\"open}"] { assert_eq!(parse_object(s), None, "{}", s); }
+    }
+    #[test]
+    fn strings_round_trip()
+    {
+        let s = "quote\" backslash\\ newline\n tab\t bell\u{7} \u{3042}";
+        let q = string(s);
+        assert_eq!(q, "\"quote\\\" backslash\\\\ newline\\n tab\\u0009 bell\\u0007 \u{3042}\"");
+        assert_eq!(unescape(&q[1 .. q.len() - 1]).as_ref().map(|s| &s[..]), Some(s));
+        let line = format!("{{\"text\":{}}}", q);
+        let fields = parse_object(&line).unwrap();
+        assert_eq!(string_field(&fields, "text").and_then(unescape).as_ref().map(|s| &s[..]), Some(s));
+    }
+    #[test]
+    fn invalid_escapes()
+    {
+        assert_eq!(unescape("\\u12"), None);
+        assert_eq!(unescape("\\ud800"), None);
+        assert_eq!(unescape("trailing\\"), None);
+        assert_eq!(unescape("\\/").as_ref().map(|s| &s[..]), Some("/"));
+    }
+    #[test]
+    fn base64_round_trips()
+    {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd, 0xfc]), "//79/A==");
+        for d in &[&b""[..], b"f", b"fo", b"foo", &[0xff, 0xfe, 0xfd, 0xfc]]
+        {
+            assert_eq!(unbase64(&base64(d)).as_ref().map(|d| &d[..]), Some(*d));
+        }
+        for s in &["Zg=", "Z===", "Zg=a", "Zm9*"] { assert_eq!(unbase64(s), None, "{}", s); }
+    }
+}
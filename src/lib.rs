@@ -1,27 +1,67 @@
 
 extern crate libc;
 #[cfg(feature = "with_bedrock")] extern crate bedrock;
+#[cfg(feature = "testing")] extern crate png;
 
 #[cfg(target_os = "macos")] #[macro_use] extern crate objc;
 #[cfg(target_os = "macos")] extern crate appkit;
 #[cfg(target_os = "macos")] #[macro_use] extern crate appkit_derive;
-#[cfg(target_os = "macos")] mod macos;
-#[cfg(target_os = "macos")] pub use macos::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
+#[cfg(all(target_os = "macos", not(feature = "with_headless")))] mod macos;
+#[cfg(all(target_os = "macos", not(feature = "with_headless")))]
+pub use macos::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
 
 #[cfg(windows)] extern crate winapi;
-#[cfg(windows)] mod win32;
-#[cfg(windows)] pub use win32::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
+#[cfg(all(windows, not(feature = "with_headless")))] mod win32;
+#[cfg(all(windows, not(feature = "with_headless")))]
+pub use win32::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
 
-#[cfg(feature = "with_xcb")] mod rxcb;
-#[cfg(feature = "with_xcb")] mod xcb;
-#[cfg(feature = "with_xcb")] pub use xcb::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod rxcb;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xcb;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xproto;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xselection;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xdnd;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xim;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xcursor;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] mod xinput;
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))]
+pub use xcb::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
+
+/// In-memory backend for tests. Replaces the platform backend when enabled
+#[cfg(feature = "with_headless")] pub mod headless;
+#[cfg(feature = "with_headless")] pub use headless::{GUIApplication, NativeWindow, NativeView, NativeWindowBuilder};
+/// Golden image tests on the headless backend
+#[cfg(feature = "testing")] pub mod testing;
+
+mod frame;
+pub use frame::{RenderMode, FrameInfo};
+mod event;
+pub use event::{WindowEvent, MouseButton, TouchPhase, Gesture, ScrollPhase, ScrollSource, SCROLL_PIXELS_PER_NOTCH};
+pub mod recording;
+mod json;
+/// Automation endpoint on a Unix domain socket
+#[cfg(all(feature = "with_xcb", not(feature = "with_headless")))] pub mod automation;
+mod pixels;
+pub use pixels::{PixelFormat, PixelBuffer, Rect, Image};
+mod error;
+pub use error::{Error, Result, ProtocolError};
+pub mod mime;
+pub use mime::{MimeData, TEXT_UTF8, IMAGE_PNG, URI_LIST};
+#[cfg(any(feature = "with_xcb", feature = "with_headless"))] mod clipboard;
+#[cfg(any(feature = "with_xcb", feature = "with_headless"))] pub use clipboard::{Clipboard, ReceiveCallback};
+mod dnd;
+pub use dnd::{DropEffect, DropData, DragData, DragProvider};
+mod cursor;
+pub use cursor::{CursorIcon, GrabMode};
 
 use std::rc::Rc;
-use std::io::Result as IOResult;
+use std::cell::RefMut;
 
 pub trait GUIApplicationRunner<E: EventDelegate>
 {
-    fn run(delegate: E) -> i32;
+    /// Runs the application. Returns the exit code, or the error that prevented it from starting
+    fn try_run(delegate: E) -> Result<i32>;
+    /// Runs the application. Panics if it cannot start
+    fn run(delegate: E) -> i32 { Self::try_run(delegate).unwrap_or_else(|e| panic!("{}", e)) }
     fn event_delegate(&self) -> &E;
 }
 #[cfg(feature = "with_bedrock")]
@@ -29,19 +69,43 @@ pub trait BedrockRenderingServer
 {
     fn presentation_support(&self, adapter: &bedrock::PhysicalDevice, rendered_qf: u32) -> bool;
     fn create_surface<WE: WindowEventDelegate>(&self, w: &NativeView<WE>, instance: &bedrock::Instance)
-        -> bedrock::Result<bedrock::Surface>;
+        -> Result<bedrock::Surface>;
 }
 pub trait Window
 {
     fn show(&self);
     #[cfg(feature = "with_bedrock")]
     fn mark_dirty(&self);
+    /// Request a redraw of an area. Areas accumulate until the next frame(`FrameInfo::dirty_rects`)
+    fn mark_dirty_rect(&self, r: Rect);
+    /// Change how the rendering callback is driven
+    fn set_render_mode(&self, mode: RenderMode);
+    /// Current pixels of the client area
+    fn capture(&self) -> Result<Image>;
+    /// Start dragging data out of the window while a pointer button is pressed. `allowed_effects` are in order of preference.
+    /// The end is reported by `WindowEventDelegate::drag_finished`. Returns false if the drag cannot start(XCB)
+    fn start_drag(&self, _data: DragData, _allowed_effects: &[DropEffect]) -> bool { false }
+    /// Area of the text insertion point, where the input method places its candidate window
+    fn set_ime_cursor_area(&self, _area: Rect) { }
+    /// Change the cursor shown over the client area
+    fn set_cursor(&self, _icon: CursorIcon) { }
+    /// Show an image as the cursor. `hotspot` is the click point in the image. Replaced by the next `set_cursor`
+    fn set_custom_cursor(&self, _image: &Image, _hotspot: (u32, u32)) -> Result<()> { Err(Error::ExtensionMissing("custom cursors")) }
+    /// Hide the cursor over the client area(if false passed) or show it again
+    fn set_cursor_visible(&self, _visible: bool) { }
+    /// Grab the pointer to the window, or release it with `GrabMode::None`. The grab is released when the window loses focus.
+    /// Returns false if the pointer cannot be grabbed(e.g. the window is not visible or another client holds a grab)
+    fn set_cursor_grab(&self, _mode: GrabMode) -> bool { false }
 }
 pub trait View
 {
     fn size(&self) -> (u16, u16);
     fn width(&self) -> u16 { self.size().0 }
     fn height(&self) -> u16 { self.size().1 }
+    /// CPU-side frame sized to the view, shown by `present`. Release the borrow before calling `present`
+    fn pixel_buffer(&self) -> RefMut<'_, PixelBuffer>;
+    /// Show the contents of `pixel_buffer`. Only the `damage` areas are updated(the whole view if empty)
+    fn present(&self, damage: &[Rect]) -> Result<()>;
 }
 pub trait WindowBuilder<'c> : Sized
 {
@@ -52,20 +116,26 @@ pub trait WindowBuilder<'c> : Sized
     fn resizable(&mut self, c: bool) -> &mut Self;
     /// Set whether the window's background is transparent
     fn transparent(&mut self, c: bool) -> &mut Self;
+    /// Set how the rendering callback is driven(default: `RenderMode::Continuous`)
+    fn render_mode(&mut self, mode: RenderMode) -> &mut Self;
 
     /// Create a window
     fn create<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>>;
+        -> Result<NativeWindow<WE>>;
     #[cfg(feature = "with_bedrock")]
     /// Create a Renderable window
     fn create_renderable<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>> where WE::ClientDelegate: 'static;
+        -> Result<NativeWindow<WE>> where WE::ClientDelegate: 'static;
 }
 
 pub trait EventDelegate : Sized
 {
     fn postinit(&self, _server: &Rc<GUIApplication<Self>>) { }
     fn on_activated(&self, _server: &Rc<GUIApplication<Self>>) { }
+    /// Called with errors the window server reports asynchronously for requests(XCB). Printed to stderr by default
+    fn protocol_error(&self, error: &ProtocolError) { eprintln!("appframe: X11 protocol error: {}", error); }
+    /// Return false to opt out of the primary selection(`GUIApplication::primary_selection` returns None)
+    fn uses_primary_selection(&self) -> bool { true }
 
     /*
     #[cfg(feature = "with_bedrock")]
@@ -76,13 +146,62 @@ pub trait EventDelegate : Sized
     */
 }
 
-pub trait WindowEventDelegate : Sized
+pub trait WindowEventDelegate : Sized + 'static
 {
     type ClientDelegate: EventDelegate;
 
     fn init_view(&self, _view: &NativeView<Self>) { }
+    /// Called when a frame should be rendered. Calls `render` by default
+    fn render_frame(&self, _frame: &FrameInfo) { self.render() }
+    /// Called when a frame should be rendered, if `render_frame` is not overridden
     fn render(&self) { }
     fn resize(&self, _width: u32, _height: u32, _in_live_resize: bool) { }
+    fn focus(&self, _focused: bool) { }
+    /// Called when the user requests closing the window. Return false to keep it open
+    fn close_requested(&self) -> bool { true }
+    fn key_down(&self, _keycode: u32) { }
+    fn key_up(&self, _keycode: u32) { }
+    fn pointer_moved(&self, _x: f32, _y: f32) { }
+    /// Positions of pointer motions merged into the following `pointer_moved`, oldest first(XCB)
+    fn pointer_motion_history(&self, _points: &[(f32, f32)]) { }
+    fn pointer_button(&self, _button: MouseButton, _pressed: bool, _x: f32, _y: f32) { }
+    /// Scrolled by wheel notches, called by `smooth_scroll` by default
+    fn scroll(&self, _dx: f32, _dy: f32) { }
+    /// Scrolled by fractional pixels(`SCROLL_PIXELS_PER_NOTCH` per wheel notch). Touchpads report `Started` and `Ended`
+    /// around their scrolls(XCB). Calls `scroll` with notches by default
+    fn smooth_scroll(&self, delta: (f32, f32), phase: ScrollPhase, _source: ScrollSource)
+    {
+        if phase != ScrollPhase::Ended { self.scroll(delta.0 / SCROLL_PIXELS_PER_NOTCH, delta.1 / SCROLL_PIXELS_PER_NOTCH); }
+    }
+    /// Unaccelerated pointer movement in device units, while the window grabs the pointer(XCB)
+    fn raw_mouse_motion(&self, _dx: f32, _dy: f32) { }
+    /// A touch point of a touchscreen started, moved or ended. Touches are not emulated as pointer events(XCB).
+    /// XCB never reports `TouchPhase::Cancelled`: XInput ends touches taken by another client with an ordinary end
+    fn touch(&self, _phase: TouchPhase, _id: u32, _position: (f32, f32)) { }
+    /// A stylus moved or its buttons changed. `pressure` is 0.0 to 1.0, `tilt` is -1.0 to 1.0 on each axis and
+    /// bit n of `buttons` is the button n + 1(the tip is button 1). Pointer events are delivered as well(XCB).
+    /// `eraser` is guessed from the device name, as X does not tell the tool
+    fn pen(&self, _position: (f32, f32), _pressure: f32, _tilt: (f32, f32), _buttons: u32, _eraser: bool) { }
+    /// A touchpad gesture started, progressed or ended. `GUIApplication::gestures_available` tells if they are reported(XCB)
+    fn gesture(&self, _gesture: Gesture, _phase: TouchPhase) { }
+    /// The input method is composing `text`. `cursor` is a byte range of it. Empty text ends the composition(XCB)
+    fn ime_preedit(&self, _text: &str, _cursor: Option<(usize, usize)>) { }
+    /// The input method committed text(XCB)
+    fn ime_commit(&self, _text: &str) { }
+    /// Data dragged from another application entered the window at (x, y). `formats` are MIME types.
+    /// Returns the effect of dropping it, `DropEffect::None` to refuse(XCB)
+    fn drag_entered(&self, _formats: &[String], _x: f32, _y: f32) -> DropEffect { DropEffect::None }
+    /// The drag moved in the window. Return Some to change the effect
+    fn drag_moved(&self, _x: f32, _y: f32) -> Option<DropEffect> { None }
+    /// The drag left the window or was cancelled
+    fn drag_left(&self) { }
+    /// Formats to read from a drop, of the offered `formats`. Only these are passed to `dropped`(all of them by default).
+    /// Leave out large formats the window does not use, since each is transferred before the drop is delivered
+    fn drop_formats(&self, formats: &[String]) -> Vec<String> { formats.to_vec() }
+    /// Data was dropped on the window with the effect accepted last
+    fn dropped(&self, _data: &DropData) { }
+    /// A drag started by `Window::start_drag` ended with the effect accepted by the target. `DropEffect::None` if it was cancelled or refused
+    fn drag_finished(&self, _effect: DropEffect) { }
 }
 
 pub struct EmptyWindowEventDelegate<E: EventDelegate>(std::marker::PhantomData<Rc<E>>);
@@ -90,7 +209,7 @@ impl<E: EventDelegate> Default for EmptyWindowEventDelegate<E>
 {
     fn default() -> Self { EmptyWindowEventDelegate(std::marker::PhantomData) }
 }
-impl<E: EventDelegate> WindowEventDelegate for EmptyWindowEventDelegate<E>
+impl<E: EventDelegate + 'static> WindowEventDelegate for EmptyWindowEventDelegate<E>
 {
     type ClientDelegate = E;
 }
@@ -4,9 +4,12 @@ use appkit::*;
 use objc::runtime::*;
 use objc::declare::*;
 use std::rc::*;
-use {GUIApplicationRunner, WindowEventDelegate, EventDelegate, Window, WindowBuilder};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use {GUIApplicationRunner, WindowEventDelegate, EventDelegate, Window, WindowBuilder, RenderMode, Image, Error, Rect};
+use frame::{FrameSchedule, FrameClock};
+use std::time::Duration;
 use std::marker::PhantomData;
-use std::io::{Result as IOResult, Error as IOError, ErrorKind};
 #[cfg_attr(not(feature = "with_bedrock"), allow(unused_imports))]
 use std::ops::{Deref, DerefMut};
 use std::mem::transmute;
@@ -162,15 +165,16 @@ impl<E: EventDelegate + 'static> AppDelegate<E>
 pub struct GUIApplication<E: EventDelegate>(E);
 impl<E: EventDelegate + 'static> GUIApplicationRunner<E> for GUIApplication<E>
 {
-    fn run(delegate: E) -> i32
+    fn try_run(delegate: E) -> ::Result<i32>
     {
         let app = Rc::new(GUIApplication(delegate));
-        let appdelegate = AppDelegate::new(&app).unwrap();
-        let nsapp = NSApplication::shared().expect("initializing shared NSApplication");
+        let appdelegate = AppDelegate::new(&app)
+            .map_err(|_| Error::ApplicationInit("allocating the AppDelegate"))?;
+        let nsapp = NSApplication::shared().map_err(|_| Error::ApplicationInit("retrieving the shared NSApplication"))?;
         nsapp.set_delegate(appdelegate.objid());
         nsapp.set_activation_policy(NSApplicationActivationPolicy::Regular);
         nsapp.run();
-        0
+        Ok(0)
     }
     fn event_delegate(&self) -> &E { &self.0 }
 }
@@ -179,9 +183,9 @@ impl<E: EventDelegate> ::BedrockRenderingServer for GUIApplication<E>
 {
     fn presentation_support(&self, _adapter: &br::PhysicalDevice, _queue_family_index: u32) -> bool { true }
     fn create_surface<WE: WindowEventDelegate>(&self, w: &FeRenderableView<WE>, instance: &br::Instance)
-        -> br::Result<br::Surface>
+        -> ::Result<br::Surface>
     {
-        br::Surface::new_macos(instance, w as *const _ as _)
+        br::Surface::new_macos(instance, w as *const _ as _).map_err(|e| Error::SurfaceCreation(format!("{:?}", e)))
     }
 }
 
@@ -190,14 +194,19 @@ impl<WE: WindowEventDelegate> Window for NativeWindow<WE>
 {
     fn show(&self) { self.0.make_key_and_order_front(NSApplication::shared().unwrap().objid()); }
     #[cfg(feature = "with_bedrock")]
-    fn mark_dirty(&self) {
-        let _: () = unsafe { msg_send![transmute::<_, &Object>(self.1.view()), setNeedsDisplay: YES] };
+    fn mark_dirty(&self) { self.1.mark_dirty(); }
+    fn mark_dirty_rect(&self, r: Rect) { self.1.mark_dirty_rect(r); }
+    fn set_render_mode(&self, mode: RenderMode) { self.1.set_render_mode(mode); }
+    fn capture(&self) -> ::Result<Image>
+    {
+        Err(Error::Unsupported("Window capture"))
     }
 }
 
 pub struct NativeWindowBuilder<'c>
 {
-    style: NSWindowStyleMask, width: u16, height: u16, caption: &'c str, transparency: bool
+    style: NSWindowStyleMask, width: u16, height: u16, caption: &'c str, transparency: bool,
+    render_mode: RenderMode
 }
 impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
 {
@@ -206,7 +215,7 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
         NativeWindowBuilder
         {
             style: NSWindowStyleMask::TITLED | NSWindowStyleMask::CLOSABLE | NSWindowStyleMask::MINIATURIZABLE | NSWindowStyleMask::RESIZABLE,
-            width, height, caption, transparency: false
+            width, height, caption, transparency: false, render_mode: RenderMode::default()
         }
     }
     fn closable(&mut self, c: bool) -> &mut Self
@@ -218,10 +227,11 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
         if c { self.style |= NSWindowStyleMask::RESIZABLE } else { self.style &= !NSWindowStyleMask::RESIZABLE; } self
     }
     fn transparent(&mut self, c: bool) -> &mut Self { self.transparency = c; self }
+    fn render_mode(&mut self, mode: RenderMode) -> &mut Self { self.render_mode = mode; self }
 
     fn create<WE: WindowEventDelegate>(&self, _server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>> {
-        let wc = NativeWindowController::new(self.caption, &self.client_rect(), event)?;
+        -> ::Result<NativeWindow<WE>> {
+        let wc = NativeWindowController::new(self.caption, &self.client_rect(), self.render_mode, event)?;
         unsafe {
             NSWindow::with_view_controller_ptr(wc.id()).map(|w| {
                 if self.transparency {
@@ -230,12 +240,12 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
                     wc.view().layer().expect("No layer").set_opaque(false);
                 }
                 w.center(); return NativeWindow(w, wc);
-            }).map_err(|_| IOError::new(ErrorKind::Other, "System I/O Error on creating NSWindow"))
+            }).map_err(|_| Error::SurfaceCreation("Failed to create NSWindow".to_owned()))
         }
     }
     #[cfg(feature = "with_bedrock")]
     fn create_renderable<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>> where WE::ClientDelegate: 'static
+        -> ::Result<NativeWindow<WE>> where WE::ClientDelegate: 'static
     {
         self.create(server, event)
     }
@@ -277,41 +287,42 @@ impl<WE: WindowEventDelegate> FeRenderableView<WE> {
             class FeRenderableView : NSView {
                 - (BOOL) wantsUpdateLayer = yesman;
                 - (objc_id) makeBackingLayer = make_backing_layer;
-                #[cfg(feature = "manual_rendering")]
                 ivar event_delegate: usize;
-                #[cfg(feature = "manual_rendering")]
+                ivar render_schedule: usize;
                 - mut dealloc = Self::dealloc;
-                #[cfg(feature = "manual_rendering")]
                 - displayLayer:(objc_id) = Self::display_layer;
                 - setFrameSize:(NSSize) = Self::set_frame_size;
                 - viewDidEndLiveResize = Self::did_end_live_resize;
             }
         })
     }
-    fn new(d: Weak<WE>) -> Result<CocoaObject<Self>, ()> {
+    fn new(d: Weak<WE>, rs: Rc<RenderSchedule>) -> Result<CocoaObject<Self>, ()> {
         let obj: objc_id = unsafe { msg_send![Self::class(), new] };
         if obj.is_null() { return Err(()); } else
         {
             unsafe {
                 move_boxed_ptr(&mut *obj, "event_delegate", Box::new(d));
+                move_boxed_ptr(&mut *obj, "render_schedule", Box::new(rs));
                 return Ok(CocoaObject::from_id_unchecked(obj));
             }
         }
     }
 
-    #[cfg(feature = "manual_rendering")]
     extern fn display_layer(this: &Object, _sel: Sel, _layer: objc_id)
     {
         // println!("DisplayLayer");
         let d: &Weak<WE> = unsafe { retrieve_ptr(this, "event_delegate") };
-        d.upgrade().unwrap().render();
+        let rs: &Rc<RenderSchedule> = unsafe { retrieve_ptr(this, "render_schedule") };
+        let now = rs.host_time_to_duration(unsafe { ::libc::mach_absolute_time() });
+        rs.schedule.rendered();
+        d.upgrade().unwrap().render_frame(&rs.clock.tick(now, None, rs.schedule.take_damage()));
     }
-    #[cfg(feature = "manual_rendering")]
     extern fn dealloc(this: &mut Object, _sel: Sel)
     {
         unsafe
         {
             drop(take_ptr::<Weak<WE>>(this, "event_delegate"));
+            drop(take_ptr::<Rc<RenderSchedule>>(this, "render_schedule"));
             msg_send![super(this, Class::get("NSView").unwrap()), dealloc]
         }
     }
@@ -344,12 +355,34 @@ impl<WE: WindowEventDelegate> FeRenderableView<WE> {
 }
 #[cfg(feature = "with_bedrock")] pub type NativeView<E> = FeRenderableView<E>;
 #[cfg(not(feature = "with_bedrock"))] pub type NativeView<E> = (NSView, PhantomData<E>);
-#[cfg(feature = "manual_rendering")]
 pub struct FeRenderableViewCtrlIvarShadowings<WE: WindowEventDelegate>
 {
     _ev: Weak<WE>, _server: Weak<GUIApplication<WE::ClientDelegate>>,
-    #[cfg(not(feature = "manual_rendering"))]
-    _displaylink: CVDisplayLink
+    _displaylink: CVDisplayLink, _schedule: Rc<RenderSchedule>, _link_times: Arc<LinkTimes>
+}
+/// Host times of the latest display link callback, handed over to the main thread.
+/// `RenderSchedule` is not thread safe and is used on the main thread only
+struct LinkTimes { now: AtomicU64, present: AtomicU64, posted: AtomicBool }
+/// Render mode and frame timing shared by `NativeWindowController` and its view
+struct RenderSchedule { schedule: FrameSchedule, clock: FrameClock, epoch: u64, timebase: ::libc::mach_timebase_info }
+impl RenderSchedule
+{
+    fn new(mode: RenderMode) -> Self
+    {
+        let mut timebase = ::libc::mach_timebase_info { numer: 0, denom: 0 };
+        unsafe { ::libc::mach_timebase_info(&mut timebase); }
+        RenderSchedule
+        {
+            schedule: FrameSchedule::new(mode), clock: FrameClock::new(),
+            epoch: unsafe { ::libc::mach_absolute_time() }, timebase
+        }
+    }
+    /// Converts a host time(mach absolute time) to the time elapsed since the window was created
+    fn host_time_to_duration(&self, t: u64) -> Duration
+    {
+        let ticks = t.saturating_sub(self.epoch) as u128;
+        Duration::from_nanos((ticks * self.timebase.numer as u128 / self.timebase.denom as u128) as u64)
+    }
 }
 #[derive(ObjcObjectBase)]
 pub struct NativeWindowController<WE: WindowEventDelegate>(Object, PhantomData<FeRenderableViewCtrlIvarShadowings<WE>>);
@@ -373,11 +406,11 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE>
                 ivar initial_frame_size: NSRect;
                 - mut loadView = Self::load_view;
                 - mut viewDidLoad = Self::view_did_load;
-                #[cfg(not(feature = "manual_rendering"))]
                 ivar dp_link_instance: usize;
-                #[cfg(not(feature = "manual_rendering"))]
+                ivar render_schedule: usize;
+                ivar link_times: usize;
                 - viewDidAppear = Self::view_did_appear;
-                #[cfg(not(feature = "manual_rendering"))]
+                - displayLinkFired:(objc_id) = Self::display_link_fired;
                 - viewWillDisappear = Self::view_will_disappear;
 
                 - mut dealloc = Self::dealloc;
@@ -385,32 +418,38 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE>
         })
     }
 
-    fn new(title: &str, initial_frame_size: &NSRect, ev: &Rc<WE>) -> IOResult<CocoaObject<Self>>
+    fn new(title: &str, initial_frame_size: &NSRect, render_mode: RenderMode, ev: &Rc<WE>)
+        -> ::Result<CocoaObject<Self>>
     {
         let title = NSString::from_str(title)
-            .map_err(|_| IOError::new(ErrorKind::Other, "System I/O Error on creating NSString"))?;
+            .map_err(|_| Error::SurfaceCreation("Failed to create NSString".to_owned()))?;
 
         let obj: objc_id = unsafe { msg_send![Self::class(), new] };
         if obj.is_null()
         {
-            return Err(IOError::new(ErrorKind::Other, "Failed to Alloc/Init of NativeWindowController"));
+            return Err(Error::SurfaceCreation("Failed to Alloc/Init of NativeWindowController".to_owned()));
         }
         unsafe
         {
             move_boxed_ptr(&mut *obj, "event_delegate_ptr", Box::new(Rc::downgrade(ev)));
             (*obj).set_ivar("initial_frame_size", initial_frame_size.clone());
+            move_boxed_ptr(&mut *obj, "render_schedule", Box::new(Rc::new(RenderSchedule::new(render_mode))));
+            move_boxed_ptr(&mut *obj, "link_times", Box::new(Arc::new(LinkTimes
+            {
+                now: AtomicU64::new(0), present: AtomicU64::new(0), posted: AtomicBool::new(false)
+            })));
         }
-        #[cfg(not(feature = "manual_rendering"))] unsafe
+        unsafe
         {
             let displaylink = Box::new(CVDisplayLink::with_active_display()
-                .ok_or_else(|| IOError::new(ErrorKind::Other, "System I/O Error on creating CVDisplayLink"))?);
+                .ok_or_else(|| Error::SurfaceCreation("Failed to create CVDisplayLink".to_owned()))?);
             displaylink.set_callback(Some(Self::on_update_sync), obj as *mut _);
             move_boxed_ptr(&mut *obj, "dp_link_instance", displaylink);
         }
         let obj = unsafe
         {
             CocoaObject::<Self>::from_id(obj)
-                .map_err(|_| IOError::new(ErrorKind::Other, "System I/O Error on creating NativeWindowController"))?
+                .map_err(|_| Error::SurfaceCreation("Failed to create NativeWindowController".to_owned()))?
         };
         obj.set_title(&title); Ok(obj)
     }
@@ -420,7 +459,8 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE>
     {
         let fsize = unsafe { this.get_ivar::<NSRect>("initial_frame_size") };
         let ev: &Weak<WE> = unsafe { retrieve_ptr(this, "event_delegate_ptr") };
-        let mut view = FeRenderableView::new(ev.clone()).expect("Failed to create Renderable View");
+        let rs: &Rc<RenderSchedule> = unsafe { retrieve_ptr(this, "render_schedule") };
+        let mut view = FeRenderableView::new(ev.clone(), rs.clone()).expect("Failed to create Renderable View");
 
         view.set_frame(fsize);
         /*if let Some(l) = view.layer_mut() { l.set_frame(fsize.clone()); }
@@ -436,31 +476,88 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE>
         v.set_layer_contents_redraw_policy(2  /* NSViewLayerContentsRedrawDuringViewResize */);
         ev.upgrade().unwrap().init_view(&v);
     }
-    #[cfg(not(feature = "manual_rendering"))]
+    fn set_render_mode(&self, mode: RenderMode)
+    {
+        let this: &Object = unsafe { transmute(self) };
+        unsafe { retrieve_ptr::<Rc<RenderSchedule>>(this, "render_schedule") }.schedule.set_mode(mode);
+        let link = unsafe { retrieve_ptr::<CVDisplayLink>(this, "dp_link_instance") };
+        if mode == RenderMode::OnDemand { link.stop(); } else { link.start(); }
+        let _: () = unsafe { msg_send![transmute::<_, &Object>(self.view()), setNeedsDisplay: YES] };
+    }
+    #[cfg(feature = "with_bedrock")]
+    fn mark_dirty(&self)
+    {
+        let this: &Object = unsafe { transmute(self) };
+        unsafe { retrieve_ptr::<Rc<RenderSchedule>>(this, "render_schedule") }.schedule.mark_dirty();
+        let _: () = unsafe { msg_send![transmute::<_, &Object>(self.view()), setNeedsDisplay: YES] };
+    }
+    fn mark_dirty_rect(&self, r: Rect)
+    {
+        let this: &Object = unsafe { transmute(self) };
+        unsafe { retrieve_ptr::<Rc<RenderSchedule>>(this, "render_schedule") }.schedule.mark_dirty_rect(r);
+        let view = unsafe { transmute::<_, &Object>(self.view()) };
+        let bounds: NSRect = unsafe { msg_send![view, bounds] };
+        // Rect has its origin at the top left, NSView at the bottom left
+        let rect = NSRect
+        {
+            origin: CGPoint { x: r.x as _, y: bounds.size.height - (r.y as i64 + r.height as i64) as f64 },
+            size: CGSize { width: r.width as _, height: r.height as _ }
+        };
+        let _: () = unsafe { msg_send![view, setNeedsDisplayInRect: rect] };
+    }
     extern fn view_did_appear(this: &Object, _sel: Sel)
     {
-        if let &Some(ref l) = unsafe { retrieve_ptr::<Option<CVDisplayLink>>(this, "dp_link_instance") } { l.start(); }
+        let rs = unsafe { retrieve_ptr::<Rc<RenderSchedule>>(this, "render_schedule") };
+        if rs.schedule.mode() != RenderMode::OnDemand
+        {
+            unsafe { retrieve_ptr::<CVDisplayLink>(this, "dp_link_instance") }.start();
+        }
     }
-    #[cfg(not(feature = "manual_rendering"))]
     extern fn view_will_disappear(this: &Object, _sel: Sel)
     {
-        if let &Some(ref l) = unsafe { retrieve_ptr::<Option<CVDisplayLink>>(this, "dp_link_instance") } { l.stop(); }
+        unsafe { retrieve_ptr::<CVDisplayLink>(this, "dp_link_instance") }.stop();
     }
-    #[cfg(not(feature = "manual_rendering"))]
-    extern "system" fn on_update_sync(_link: CVDisplayLinkRef, _now: *const CVTimeStamp, _outtime: *const CVTimeStamp,
+    /// Called on the display link thread: records the times and lets the main thread render
+    extern "system" fn on_update_sync(_link: CVDisplayLinkRef, now: *const CVTimeStamp, outtime: *const CVTimeStamp,
         _flags: CVOptionFlags, _flags_out: *mut CVOptionFlags, context: *mut ::libc::c_void) -> CVReturn
     {
-        // println!("DPLINK... {}", unsafe { (*_now).hostTime });
-        let e: &Weak<WE> = unsafe { retrieve_ptr(&*(context as *mut Object), "event_delegate_ptr") };
-        e.render();
+        let this = unsafe { &*(context as *mut Object) };
+        let times = unsafe { retrieve_ptr::<Arc<LinkTimes>>(this, "link_times") };
+        unsafe
+        {
+            times.now.store((*now).hostTime, Ordering::Relaxed);
+            times.present.store((*outtime).hostTime, Ordering::Relaxed);
+        }
+        // one message at a time: a busy main thread skips vblanks instead of queueing them
+        if !times.posted.swap(true, Ordering::AcqRel)
+        {
+            let _: () = unsafe
+            {
+                msg_send![this, performSelectorOnMainThread: sel!(displayLinkFired:) withObject: ::std::ptr::null_mut::<Object>()
+                    waitUntilDone: NO]
+            };
+        }
         return 0;
     }
+    extern fn display_link_fired(this: &Object, _sel: Sel, _arg: objc_id)
+    {
+        let times = unsafe { retrieve_ptr::<Arc<LinkTimes>>(this, "link_times") };
+        times.posted.store(false, Ordering::Release);
+        let rs = unsafe { retrieve_ptr::<Rc<RenderSchedule>>(this, "render_schedule") };
+        let now = rs.host_time_to_duration(times.now.load(Ordering::Relaxed));
+        let present = rs.host_time_to_duration(times.present.load(Ordering::Relaxed));
+        // OnDemand windows are driven by displayLayer
+        if rs.schedule.mode() == RenderMode::OnDemand || !rs.schedule.due(now) { return; }
+        let e: &Weak<WE> = unsafe { retrieve_ptr(this, "event_delegate_ptr") };
+        if let Some(e) = e.upgrade() { e.render_frame(&rs.clock.tick(now, Some(present), rs.schedule.take_damage())); }
+    }
     extern fn dealloc(this: &mut Object, _sel: Sel)
     {
-        #[cfg(not(feature = "manual_rendering"))]
-        unsafe { drop(take_ptr::<CVDisplayLink>("dp_link_instance")); }
         unsafe
         {
+            drop(take_ptr::<CVDisplayLink>(this, "dp_link_instance"));
+            drop(take_ptr::<Rc<RenderSchedule>>(this, "render_schedule"));
+            drop(take_ptr::<Arc<LinkTimes>>(this, "link_times"));
             drop(take_ptr::<Weak<WE>>(this, "event_delegate_ptr"));
 
             msg_send![super(this, Class::get("NSViewController").unwrap()), dealloc]
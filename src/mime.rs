@@ -0,0 +1,44 @@
+//! Data keyed by MIME type, shared by the clipboard and drag and drop
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Contents in each format, keyed by MIME type
+pub type MimeData = BTreeMap<String, Vec<u8>>;
+/// UTF-8 text(`UTF8_STRING` on X11)
+pub const TEXT_UTF8: &str = "text/plain;charset=utf-8";
+pub const IMAGE_PNG: &str = "image/png";
+/// List of URIs(RFC 2483), e.g. files dragged from a file manager
+pub const URI_LIST: &str = "text/uri-list";
+
+/// URIs of a `text/uri-list`. Comments and blank lines are skipped
+pub fn parse_uri_list(data: &[u8]) -> Vec<String>
+{
+    String::from_utf8_lossy(data).lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_owned()).collect()
+}
+/// Local path of a `file:` URI(percent-decoded). None for other schemes
+pub fn file_uri_path(uri: &str) -> Option<PathBuf>
+{
+    let rest = uri.strip_prefix("file:")?;
+    // file://host/path or file:/path
+    let path = match rest.strip_prefix("//") { Some(r) => &r[r.find('/')? ..], None => rest };
+    let bytes = percent_decode(path);
+    #[cfg(unix)] { use std::os::unix::ffi::OsStringExt; Some(PathBuf::from(::std::ffi::OsString::from_vec(bytes))) }
+    #[cfg(not(unix))] { Some(PathBuf::from(String::from_utf8_lossy(&bytes).trim_start_matches('/').to_owned())) }
+}
+fn percent_decode(s: &str) -> Vec<u8>
+{
+    let (b, mut out) = (s.as_bytes(), Vec::with_capacity(s.len()));
+    let mut i = 0;
+    while i < b.len()
+    {
+        let hex = if b[i] == b'%' && i + 2 < b.len() { ::std::str::from_utf8(&b[i + 1 .. i + 3]).ok() } else { None };
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok())
+        {
+            Some(v) => { out.push(v); i += 3; },
+            None => { out.push(b[i]); i += 1; }
+        }
+    }
+    out
+}
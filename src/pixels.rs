@@ -0,0 +1,96 @@
+//! CPU-side pixel buffers shown by `View::present`
+
+/// Byte order of a pixel(8 bits per channel)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat { Rgba8, Bgra8 }
+
+/// A rectangle in pixels, relative to the top-left corner of a view
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Rect { pub x: i32, pub y: i32, pub width: u32, pub height: u32 }
+impl Rect
+{
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self { Rect { x, y, width, height } }
+    pub fn is_empty(&self) -> bool { self.width == 0 || self.height == 0 }
+    pub fn right(&self) -> i32 { self.x + self.width as i32 }
+    pub fn bottom(&self) -> i32 { self.y + self.height as i32 }
+    pub fn intersection(&self, other: &Rect) -> Option<Rect>
+    {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (r, b) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+        if r <= x || b <= y { None } else { Some(Rect::new(x, y, (r - x) as _, (b - y) as _)) }
+    }
+    /// The smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect
+    {
+        if self.is_empty() { return *other; }
+        if other.is_empty() { return *self; }
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        Rect::new(x, y, (self.right().max(other.right()) - x) as _, (self.bottom().max(other.bottom()) - y) as _)
+    }
+}
+
+/// A frame sized to a view. Rows are tightly packed, 4 bytes per pixel
+pub struct PixelBuffer { width: u32, height: u32, format: PixelFormat, data: Vec<u8> }
+impl PixelBuffer
+{
+    pub(crate) fn new(width: u32, height: u32, format: PixelFormat) -> Self
+    {
+        PixelBuffer { width, height, format, data: vec![0; width as usize * height as usize * 4] }
+    }
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+    pub fn format(&self) -> PixelFormat { self.format }
+    /// Bytes per row
+    pub fn stride(&self) -> usize { self.width as usize * 4 }
+    pub fn bounds(&self) -> Rect { Rect::new(0, 0, self.width, self.height) }
+    pub fn data(&self) -> &[u8] { &self.data }
+    pub fn data_mut(&mut self) -> &mut [u8] { &mut self.data }
+    /// Bytes of the row `y` in the horizontal range of `r`
+    pub fn row_span(&self, r: &Rect, y: u32) -> &[u8]
+    {
+        let o = y as usize * self.stride() + r.x as usize * 4;
+        &self.data[o .. o + r.width as usize * 4]
+    }
+
+    /// Resize to the view. The contents are cleared if the size changes
+    pub(crate) fn fit(&mut self, width: u32, height: u32)
+    {
+        if (self.width, self.height) != (width, height) { *self = PixelBuffer::new(width, height, self.format); }
+    }
+    /// Damage rectangles clipped to the buffer. Empty `damage` means the whole buffer
+    pub(crate) fn clip_damage(&self, damage: &[Rect]) -> Vec<Rect>
+    {
+        if damage.is_empty() { return if self.bounds().is_empty() { Vec::new() } else { vec![self.bounds()] }; }
+        damage.iter().filter_map(|r| r.intersection(&self.bounds())).collect()
+    }
+}
+
+/// Pixels captured from a window. Rows are tightly packed, 4 bytes per pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image
+{
+    pub width: u32, pub height: u32, pub format: PixelFormat,
+    /// Whether the alpha channel is meaningful(premultiplied). Alpha bytes are 0xff otherwise
+    pub has_alpha: bool,
+    pub data: Vec<u8>
+}
+impl Image
+{
+    /// The pixel at (x, y) in RGBA order
+    pub fn rgba_at(&self, x: u32, y: u32) -> [u8; 4]
+    {
+        let o = (y as usize * self.width as usize + x as usize) * 4;
+        let p = &self.data[o .. o + 4];
+        match self.format { PixelFormat::Rgba8 => [p[0], p[1], p[2], p[3]], PixelFormat::Bgra8 => [p[2], p[1], p[0], p[3]] }
+    }
+    /// Convert to `PixelFormat::Rgba8`
+    pub fn into_rgba(mut self) -> Image
+    {
+        if self.format == PixelFormat::Bgra8
+        {
+            for p in self.data.chunks_mut(4) { p.swap(0, 2); }
+            self.format = PixelFormat::Rgba8;
+        }
+        self
+    }
+}
@@ -0,0 +1,479 @@
+//! Recording and replaying delegated events
+//!
+//! A recording is a JSON Lines file. The first line is a header:
+//!
+//! ```text
+//! {"format":"appframe-recording","version":1}
+//! ```
+//!
+//! Each following line is a record, with the time in nanoseconds(`t`) and the index of the window in creation order:
+//!
+//! ```text
+//! {"t":16666666,"window":0,"type":"key_down","keycode":38}
+//! {"t":33333333,"window":0,"type":"frame","predicted":50000000}
+//! ```
+//!
+//! Types and their fields: `resize`(width, height), `focus`(focused), `close_requested`, `key_down`/`key_up`(keycode),
+//! `pointer_moved`(x, y), `pointer_button`(button: "left"/"middle"/"right"/number, pressed, x, y), `scroll`(dx, dy, phase: "started"/"moved"/"ended"/"momentum" and source: "wheel"/"touchpad"/"other", optional),
+//! `raw_mouse_motion`(dx, dy), `touch`(phase: "started"/"moved"/"ended"/"cancelled", id, x, y),
+//! `pen`(x, y, pressure, tilt_x, tilt_y, buttons, eraser),
+//! `gesture`(phase, kind: "pinch" with scale and rotation/"swipe" with dx, dy and fingers/"hold"), `ime_preedit`(text, cursor_start and cursor_end, optional), `ime_commit`(text) and `frame`(predicted, optional). `frame` records a call of the rendering callback.
+//!
+//! Callbacks other than events are recorded as `motion_history`(points: "x,y;x,y;..."), `drag_entered`(formats: one per line, x, y),
+//! `drag_moved`(x, y), `drag_left`, `dropped`(formats and data: base64 of each format, separated by commas) and
+//! `drag_finished`(effect: "none"/"copy"/"move"/"link"). The effects the delegate returns are not recorded.
+//!
+//! The XCB backend records to the file named by `APPFRAME_RECORD` and replays the file named by `APPFRAME_REPLAY`.
+
+use std::io::{Write, BufRead, Result as IOResult, Error as IOError, ErrorKind};
+use std::time::Duration;
+use {WindowEvent, WindowEventDelegate, MouseButton, TouchPhase, Gesture, ScrollPhase, ScrollSource, DropEffect, DropData};
+use json::{self, parse_object, field, string_field};
+
+pub const FORMAT_VERSION: u32 = 1;
+/// Environment variable naming the file to record to
+pub const RECORD_ENV: &str = "APPFRAME_RECORD";
+/// Environment variable naming the file to replay
+pub const REPLAY_ENV: &str = "APPFRAME_REPLAY";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordedEvent
+{
+    Window(WindowEvent),
+    /// The rendering callback was called
+    Frame { predicted_present: Option<Duration> },
+    MotionHistory(Vec<(f32, f32)>),
+    DragEntered { formats: Vec<String>, x: f32, y: f32 },
+    DragMoved { x: f32, y: f32 },
+    DragLeft,
+    Dropped(DropData),
+    DragFinished(DropEffect)
+}
+impl RecordedEvent
+{
+    /// Call the callback of a record other than a `Window` event or a `Frame`.
+    /// Returns the effect `drag_entered` or `drag_moved` accepted
+    pub(crate) fn notify<WE: WindowEventDelegate>(&self, d: &WE) -> Option<DropEffect>
+    {
+        match *self
+        {
+            RecordedEvent::MotionHistory(ref points) => d.pointer_motion_history(points),
+            RecordedEvent::DragEntered { ref formats, x, y } => return Some(d.drag_entered(formats, x, y)),
+            RecordedEvent::DragMoved { x, y } => return d.drag_moved(x, y),
+            RecordedEvent::DragLeft => d.drag_left(),
+            RecordedEvent::Dropped(ref data) => d.dropped(data),
+            RecordedEvent::DragFinished(effect) => d.drag_finished(effect),
+            RecordedEvent::Window(_) | RecordedEvent::Frame { .. } => ()
+        }
+        None
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record
+{
+    /// On the time base of `FrameInfo::timestamp`
+    pub timestamp: Duration,
+    /// Index of the window in creation order
+    pub window: u32,
+    pub event: RecordedEvent
+}
+
+/// Writes records, a line per record
+pub struct Recorder { sink: Box<dyn Write> }
+impl Recorder
+{
+    pub fn new<W: Write + 'static>(mut sink: W) -> IOResult<Self>
+    {
+        writeln!(sink, "{{\"format\":\"appframe-recording\",\"version\":{}}}", FORMAT_VERSION)?;
+        Ok(Recorder { sink: Box::new(sink) })
+    }
+    pub fn record(&mut self, r: &Record) -> IOResult<()>
+    {
+        let mut line = format!("{{\"t\":{},\"window\":{},", nanos(r.timestamp), r.window);
+        match r.event
+        {
+            RecordedEvent::Frame { predicted_present } =>
+            {
+                line.push_str("\"type\":\"frame\"");
+                if let Some(p) = predicted_present { line.push_str(&format!(",\"predicted\":{}", nanos(p))); }
+            },
+            RecordedEvent::MotionHistory(ref points) => line.push_str(&format!("\"type\":\"motion_history\",\"points\":\"{}\"",
+                points.iter().map(|&(x, y)| format!("{},{}", x, y)).collect::<Vec<_>>().join(";"))),
+            RecordedEvent::DragEntered { ref formats, x, y } => line.push_str(&format!(
+                "\"type\":\"drag_entered\",\"formats\":{},\"x\":{},\"y\":{}", json::string(&formats.join("\n")), x, y)),
+            RecordedEvent::DragMoved { x, y } => line.push_str(&format!("\"type\":\"drag_moved\",\"x\":{},\"y\":{}", x, y)),
+            RecordedEvent::DragLeft => line.push_str("\"type\":\"drag_left\""),
+            RecordedEvent::Dropped(ref d) => line.push_str(&format!("\"type\":\"dropped\",\"formats\":{},\"data\":\"{}\"",
+                json::string(&d.data.keys().cloned().collect::<Vec<_>>().join("\n")),
+                d.data.values().map(|v| json::base64(v)).collect::<Vec<_>>().join(","))),
+            RecordedEvent::DragFinished(effect) =>
+                line.push_str(&format!("\"type\":\"drag_finished\",\"effect\":{}", effect_name(effect))),
+            RecordedEvent::Window(ref e) => line.push_str(&match *e
+            {
+                WindowEvent::Resize { width, height } => format!("\"type\":\"resize\",\"width\":{},\"height\":{}", width, height),
+                WindowEvent::Focus(f) => format!("\"type\":\"focus\",\"focused\":{}", f),
+                WindowEvent::CloseRequested => "\"type\":\"close_requested\"".to_owned(),
+                WindowEvent::KeyDown(k) => format!("\"type\":\"key_down\",\"keycode\":{}", k),
+                WindowEvent::KeyUp(k) => format!("\"type\":\"key_up\",\"keycode\":{}", k),
+                WindowEvent::PointerMoved { x, y } => format!("\"type\":\"pointer_moved\",\"x\":{},\"y\":{}", x, y),
+                WindowEvent::PointerButton { button, pressed, x, y } => format!(
+                    "\"type\":\"pointer_button\",\"button\":{},\"pressed\":{},\"x\":{},\"y\":{}",
+                    button_name(button), pressed, x, y),
+                WindowEvent::Scroll { dx, dy, phase, source } => format!(
+                    "\"type\":\"scroll\",\"dx\":{},\"dy\":{},\"phase\":{},\"source\":{}",
+                    dx, dy, scroll_phase_name(phase), scroll_source_name(source)),
+                WindowEvent::RawMouseMotion { dx, dy } => format!("\"type\":\"raw_mouse_motion\",\"dx\":{},\"dy\":{}", dx, dy),
+                WindowEvent::Touch { phase, id, x, y } => format!("\"type\":\"touch\",\"phase\":{},\"id\":{},\"x\":{},\"y\":{}",
+                    phase_name(phase), id, x, y),
+                WindowEvent::Pen { x, y, pressure, tilt_x, tilt_y, buttons, eraser } => format!(
+                    "\"type\":\"pen\",\"x\":{},\"y\":{},\"pressure\":{},\"tilt_x\":{},\"tilt_y\":{},\"buttons\":{},\"eraser\":{}",
+                    x, y, pressure, tilt_x, tilt_y, buttons, eraser),
+                WindowEvent::Gesture { gesture, phase } => format!("\"type\":\"gesture\",\"phase\":{},{}", phase_name(phase), match gesture
+                {
+                    Gesture::Pinch { scale, rotation } => format!("\"kind\":\"pinch\",\"scale\":{},\"rotation\":{}", scale, rotation),
+                    Gesture::Swipe { dx, dy, fingers } => format!("\"kind\":\"swipe\",\"dx\":{},\"dy\":{},\"fingers\":{}", dx, dy, fingers),
+                    Gesture::Hold => "\"kind\":\"hold\"".to_owned()
+                }),
+                WindowEvent::ImePreedit { ref text, cursor } => format!("\"type\":\"ime_preedit\",\"text\":{}{}",
+                    json::string(text), cursor.map_or_else(String::new, |c| format!(",\"cursor_start\":{},\"cursor_end\":{}", c.0, c.1))),
+                WindowEvent::ImeCommit(ref text) => format!("\"type\":\"ime_commit\",\"text\":{}", json::string(text))
+            })
+        }
+        line.push('}');
+        // flushed line by line to survive crashes
+        writeln!(self.sink, "{}", line)?;
+        self.sink.flush()
+    }
+}
+
+/// Reads records. Fails on the first line if the file is not a recording of a supported version
+pub struct Replay<R: BufRead> { source: R, line: usize }
+impl<R: BufRead> Replay<R>
+{
+    pub fn new(mut source: R) -> IOResult<Self>
+    {
+        let mut header = String::new();
+        source.read_line(&mut header)?;
+        let fields = parse_object(&header).ok_or_else(|| invalid(1, "malformed header"))?;
+        if field(&fields, "format") != Some("\"appframe-recording\"") { return Err(invalid(1, "not a recording")); }
+        match field(&fields, "version").and_then(|v| v.parse::<u32>().ok())
+        {
+            Some(FORMAT_VERSION) => Ok(Replay { source, line: 1 }),
+            Some(v) => Err(invalid(1, &format!("unsupported version {}", v))),
+            None => Err(invalid(1, "missing version"))
+        }
+    }
+    fn parse(&self, line: &str) -> IOResult<Record>
+    {
+        let n = self.line;
+        let fields = parse_object(line).ok_or_else(|| invalid(n, "malformed record"))?;
+        let num = |k: &str| field(&fields, k).ok_or_else(|| invalid(n, &format!("missing {}", k)));
+        macro_rules! get
+        {
+            ($k: expr) => { num($k)?.parse().map_err(|_| invalid(n, concat!("invalid ", $k)))? }
+        }
+        let text = || string_field(&fields, "text").and_then(json::unescape).ok_or_else(|| invalid(n, "missing or invalid text"));
+        let formats = || string_field(&fields, "formats").and_then(json::unescape)
+            .map(|f| if f.is_empty() { Vec::new() } else { f.split('\n').map(str::to_owned).collect() })
+            .ok_or_else(|| invalid(n, "missing or invalid formats"));
+        let ty = num("type")?;
+        let event = match ty
+        {
+            "\"frame\"" => RecordedEvent::Frame
+            {
+                predicted_present: match field(&fields, "predicted")
+                {
+                    Some(p) => Some(Duration::from_nanos(p.parse().map_err(|_| invalid(n, "invalid predicted"))?)),
+                    None => None
+                }
+            },
+            "\"resize\"" => RecordedEvent::Window(WindowEvent::Resize { width: get!("width"), height: get!("height") }),
+            "\"focus\"" => RecordedEvent::Window(WindowEvent::Focus(get!("focused"))),
+            "\"close_requested\"" => RecordedEvent::Window(WindowEvent::CloseRequested),
+            "\"key_down\"" => RecordedEvent::Window(WindowEvent::KeyDown(get!("keycode"))),
+            "\"key_up\"" => RecordedEvent::Window(WindowEvent::KeyUp(get!("keycode"))),
+            "\"pointer_moved\"" => RecordedEvent::Window(WindowEvent::PointerMoved { x: get!("x"), y: get!("y") }),
+            "\"pointer_button\"" => RecordedEvent::Window(WindowEvent::PointerButton
+            {
+                button: parse_button(num("button")?).ok_or_else(|| invalid(n, "invalid button"))?,
+                pressed: get!("pressed"), x: get!("x"), y: get!("y")
+            }),
+            "\"scroll\"" => RecordedEvent::Window(WindowEvent::Scroll
+            {
+                dx: get!("dx"), dy: get!("dy"),
+                // recorded before smooth scrolling
+                phase: field(&fields, "phase").map_or(Some(ScrollPhase::Moved), parse_scroll_phase)
+                    .ok_or_else(|| invalid(n, "invalid phase"))?,
+                source: field(&fields, "source").map_or(Some(ScrollSource::Wheel), parse_scroll_source)
+                    .ok_or_else(|| invalid(n, "invalid source"))?
+            }),
+            "\"raw_mouse_motion\"" => RecordedEvent::Window(WindowEvent::RawMouseMotion { dx: get!("dx"), dy: get!("dy") }),
+            "\"touch\"" => RecordedEvent::Window(WindowEvent::Touch
+            {
+                phase: parse_phase(num("phase")?).ok_or_else(|| invalid(n, "invalid phase"))?,
+                id: get!("id"), x: get!("x"), y: get!("y")
+            }),
+            "\"pen\"" => RecordedEvent::Window(WindowEvent::Pen
+            {
+                x: get!("x"), y: get!("y"), pressure: get!("pressure"), tilt_x: get!("tilt_x"), tilt_y: get!("tilt_y"),
+                buttons: get!("buttons"), eraser: get!("eraser")
+            }),
+            "\"gesture\"" => RecordedEvent::Window(WindowEvent::Gesture
+            {
+                gesture: match num("kind")?
+                {
+                    "\"pinch\"" => Gesture::Pinch { scale: get!("scale"), rotation: get!("rotation") },
+                    "\"swipe\"" => Gesture::Swipe { dx: get!("dx"), dy: get!("dy"), fingers: get!("fingers") },
+                    "\"hold\"" => Gesture::Hold,
+                    k => return Err(invalid(n, &format!("unknown gesture {}", k)))
+                },
+                phase: parse_phase(num("phase")?).ok_or_else(|| invalid(n, "invalid phase"))?
+            }),
+            "\"ime_preedit\"" => RecordedEvent::Window(WindowEvent::ImePreedit
+            {
+                text: text()?,
+                cursor: if field(&fields, "cursor_start").is_some() { Some((get!("cursor_start"), get!("cursor_end"))) } else { None }
+            }),
+            "\"ime_commit\"" => RecordedEvent::Window(WindowEvent::ImeCommit(text()?)),
+            "\"motion_history\"" => RecordedEvent::MotionHistory(string_field(&fields, "points").and_then(parse_points)
+                .ok_or_else(|| invalid(n, "missing or invalid points"))?),
+            "\"drag_entered\"" => RecordedEvent::DragEntered { formats: formats()?, x: get!("x"), y: get!("y") },
+            "\"drag_moved\"" => RecordedEvent::DragMoved { x: get!("x"), y: get!("y") },
+            "\"drag_left\"" => RecordedEvent::DragLeft,
+            "\"dropped\"" =>
+            {
+                let formats = formats()?;
+                let data = string_field(&fields, "data")
+                    .and_then(|d| if formats.is_empty() { Some(Vec::new()) } else { d.split(',').map(json::unbase64).collect() })
+                    .filter(|d: &Vec<_>| d.len() == formats.len()).ok_or_else(|| invalid(n, "missing or invalid data"))?;
+                RecordedEvent::Dropped(DropData { data: formats.into_iter().zip(data).collect() })
+            },
+            "\"drag_finished\"" => RecordedEvent::DragFinished(parse_effect(num("effect")?).ok_or_else(|| invalid(n, "invalid effect"))?),
+            t => return Err(invalid(n, &format!("unknown type {}", t)))
+        };
+        Ok(Record { timestamp: Duration::from_nanos(get!("t")), window: get!("window"), event })
+    }
+}
+impl<R: BufRead> Iterator for Replay<R>
+{
+    type Item = IOResult<Record>;
+    fn next(&mut self) -> Option<IOResult<Record>>
+    {
+        loop
+        {
+            let mut line = String::new();
+            match self.source.read_line(&mut line)
+            {
+                Ok(0) => return None,
+                Err(e) => return Some(Err(e)),
+                Ok(_) => ()
+            }
+            self.line += 1;
+            if !line.trim().is_empty() { return Some(self.parse(&line)); }
+        }
+    }
+}
+
+fn parse_points(s: &str) -> Option<Vec<(f32, f32)>>
+{
+    if s.is_empty() { return Some(Vec::new()); }
+    s.split(';').map(|p|
+    {
+        let mut xy = p.splitn(2, ',');
+        Some((xy.next()?.parse().ok()?, xy.next()?.parse().ok()?))
+    }).collect()
+}
+fn effect_name(e: DropEffect) -> &'static str
+{
+    match e
+    {
+        DropEffect::None => "\"none\"", DropEffect::Copy => "\"copy\"", DropEffect::Move => "\"move\"", DropEffect::Link => "\"link\""
+    }
+}
+fn parse_effect(s: &str) -> Option<DropEffect>
+{
+    match s
+    {
+        "\"none\"" => Some(DropEffect::None), "\"copy\"" => Some(DropEffect::Copy),
+        "\"move\"" => Some(DropEffect::Move), "\"link\"" => Some(DropEffect::Link), _ => None
+    }
+}
+fn nanos(d: Duration) -> u64 { d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64 }
+fn invalid(line: usize, msg: &str) -> IOError
+{
+    IOError::new(ErrorKind::InvalidData, format!("recording line {}: {}", line, msg))
+}
+pub(crate) fn button_name(b: MouseButton) -> String
+{
+    match b
+    {
+        MouseButton::Left => "\"left\"".to_owned(), MouseButton::Middle => "\"middle\"".to_owned(),
+        MouseButton::Right => "\"right\"".to_owned(), MouseButton::Other(n) => n.to_string()
+    }
+}
+pub(crate) fn parse_button(s: &str) -> Option<MouseButton>
+{
+    match s
+    {
+        "\"left\"" => Some(MouseButton::Left), "\"middle\"" => Some(MouseButton::Middle),
+        "\"right\"" => Some(MouseButton::Right), n => n.parse().ok().map(MouseButton::Other)
+    }
+}
+pub(crate) fn phase_name(p: TouchPhase) -> &'static str
+{
+    match p
+    {
+        TouchPhase::Started => "\"started\"", TouchPhase::Moved => "\"moved\"",
+        TouchPhase::Ended => "\"ended\"", TouchPhase::Cancelled => "\"cancelled\""
+    }
+}
+pub(crate) fn parse_phase(s: &str) -> Option<TouchPhase>
+{
+    match s
+    {
+        "\"started\"" => Some(TouchPhase::Started), "\"moved\"" => Some(TouchPhase::Moved),
+        "\"ended\"" => Some(TouchPhase::Ended), "\"cancelled\"" => Some(TouchPhase::Cancelled), _ => None
+    }
+}
+pub(crate) fn scroll_phase_name(p: ScrollPhase) -> &'static str
+{
+    match p
+    {
+        ScrollPhase::Started => "\"started\"", ScrollPhase::Moved => "\"moved\"",
+        ScrollPhase::Ended => "\"ended\"", ScrollPhase::Momentum => "\"momentum\""
+    }
+}
+pub(crate) fn parse_scroll_phase(s: &str) -> Option<ScrollPhase>
+{
+    match s
+    {
+        "\"started\"" => Some(ScrollPhase::Started), "\"moved\"" => Some(ScrollPhase::Moved),
+        "\"ended\"" => Some(ScrollPhase::Ended), "\"momentum\"" => Some(ScrollPhase::Momentum), _ => None
+    }
+}
+pub(crate) fn scroll_source_name(s: ScrollSource) -> &'static str
+{
+    match s { ScrollSource::Wheel => "\"wheel\"", ScrollSource::Touchpad => "\"touchpad\"", ScrollSource::Other => "\"other\"" }
+}
+pub(crate) fn parse_scroll_source(s: &str) -> Option<ScrollSource>
+{
+    match s
+    {
+        "\"wheel\"" => Some(ScrollSource::Wheel), "\"touchpad\"" => Some(ScrollSource::Touchpad),
+        "\"other\"" => Some(ScrollSource::Other), _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    /// Shares the written bytes with the test
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+    impl Write for Sink
+    {
+        fn write(&mut self, b: &[u8]) -> IOResult<usize> { self.0.borrow_mut().extend_from_slice(b); Ok(b.len()) }
+        fn flush(&mut self) -> IOResult<()> { Ok(()) }
+    }
+    fn replay(s: &str) -> IOResult<Vec<Record>> { Replay::new(Cursor::new(s.as_bytes()))?.collect() }
+
+    #[test]
+    fn every_event_round_trips()
+    {
+        let events = vec![
+            RecordedEvent::Window(WindowEvent::Resize { width: 640, height: 480 }),
+            RecordedEvent::Window(WindowEvent::Focus(true)),
+            RecordedEvent::Window(WindowEvent::CloseRequested),
+            RecordedEvent::Window(WindowEvent::KeyDown(38)),
+            RecordedEvent::Window(WindowEvent::KeyUp(38)),
+            RecordedEvent::Window(WindowEvent::PointerMoved { x: 1.5, y: -2.0 }),
+            RecordedEvent::Window(WindowEvent::PointerButton { button: MouseButton::Left, pressed: true, x: 3.0, y: 4.0 }),
+            RecordedEvent::Window(WindowEvent::PointerButton { button: MouseButton::Other(8), pressed: false, x: 0.0, y: 0.0 }),
+            RecordedEvent::Window(WindowEvent::Scroll { dx: 0.0, dy: -1.25, phase: ScrollPhase::Momentum, source: ScrollSource::Touchpad }),
+            RecordedEvent::Window(WindowEvent::RawMouseMotion { dx: 0.5, dy: 7.0 }),
+            RecordedEvent::Window(WindowEvent::Touch { phase: TouchPhase::Cancelled, id: 3, x: 10.0, y: 20.0 }),
+            RecordedEvent::Window(WindowEvent::Pen
+            {
+                x: 1.0, y: 2.0, pressure: 0.75, tilt_x: -0.5, tilt_y: 0.25, buttons: 2, eraser: true
+            }),
+            RecordedEvent::Window(WindowEvent::Gesture { gesture: Gesture::Pinch { scale: 1.5, rotation: -10.0 }, phase: TouchPhase::Moved }),
+            RecordedEvent::Window(WindowEvent::Gesture { gesture: Gesture::Swipe { dx: 4.0, dy: 0.0, fingers: 3 }, phase: TouchPhase::Started }),
+            RecordedEvent::Window(WindowEvent::Gesture { gesture: Gesture::Hold, phase: TouchPhase::Ended }),
+            RecordedEvent::Window(WindowEvent::ImePreedit { text: "\"に\\ほん\"\n".to_owned(), cursor: Some((1, 2)) }),
+            RecordedEvent::Window(WindowEvent::ImePreedit { text: String::new(), cursor: None }),
+            RecordedEvent::Window(WindowEvent::ImeCommit("日本".to_owned())),
+            RecordedEvent::Frame { predicted_present: Some(Duration::new(1, 5)) },
+            RecordedEvent::Frame { predicted_present: None },
+            RecordedEvent::MotionHistory(vec![(1.5, 2.0), (-3.0, 4.25)]),
+            RecordedEvent::DragEntered { formats: vec!["text/uri-list".to_owned(), "text/plain;charset=utf-8".to_owned()], x: 5.0, y: 6.5 },
+            RecordedEvent::DragEntered { formats: Vec::new(), x: 0.0, y: 0.0 },
+            RecordedEvent::DragMoved { x: 7.0, y: -8.0 },
+            RecordedEvent::DragLeft,
+            RecordedEvent::Dropped(DropData
+            {
+                data: vec![("image/png".to_owned(), vec![0x89, 0x50, 0x4e]), ("text/uri-list".to_owned(), b"file:///a\r\n".to_vec()),
+                    ("x-empty".to_owned(), Vec::new())].into_iter().collect()
+            }),
+            RecordedEvent::Dropped(DropData { data: Default::default() }),
+            RecordedEvent::DragFinished(DropEffect::Move)
+        ];
+        let records = events.into_iter().enumerate().map(|(i, event)| Record
+        {
+            timestamp: Duration::from_millis(i as u64 * 16), window: i as u32 % 2, event
+        }).collect::<Vec<_>>();
+        let bytes = Rc::new(RefCell::new(Vec::new()));
+        let mut recorder = Recorder::new(Sink(bytes.clone())).unwrap();
+        for r in &records { recorder.record(r).unwrap(); }
+
+        let text = String::from_utf8(bytes.borrow().clone()).unwrap();
+        assert_eq!(text.lines().count(), records.len() + 1);
+        assert_eq!(replay(&text).unwrap(), records);
+    }
+    #[test]
+    fn older_scroll_records_default_to_wheel_moves()
+    {
+        let r = replay("{\"format\":\"appframe-recording\",\"version\":1}\n\n{\"t\":5,\"window\":0,\"type\":\"scroll\",\"dx\":0,\"dy\":1}\n");
+        assert_eq!(r.unwrap(), vec![Record
+        {
+            timestamp: Duration::from_nanos(5), window: 0,
+            event: RecordedEvent::Window(WindowEvent::Scroll { dx: 0.0, dy: 1.0, phase: ScrollPhase::Moved, source: ScrollSource::Wheel })
+        }]);
+    }
+    #[test]
+    fn bad_headers_are_rejected()
+    {
+        for h in &["", "not json", "{\"format\":\"other\",\"version\":1}", "{\"format\":\"appframe-recording\"}",
+            "{\"format\":\"appframe-recording\",\"version\":2}"]
+        {
+            let e = Replay::new(Cursor::new(h.as_bytes())).err().expect(h);
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+    }
+    #[test]
+    fn bad_records_report_their_line()
+    {
+        let header = "{\"format\":\"appframe-recording\",\"version\":1}\n";
+        for (record, msg) in &[
+            ("{\"t\":0,\"window\":0,\"type\":\"key_down\",\"keycode\":1", "malformed record"),
+            ("{\"t\":0,\"window\":0,\"type\":\"teleport\"}", "unknown type"),
+            ("{\"t\":0,\"window\":0,\"type\":\"key_down\"}", "missing keycode"),
+            ("{\"t\":-1,\"window\":0,\"type\":\"close_requested\"}", "invalid t"),
+            ("{\"t\":0,\"window\":0,\"type\":\"touch\",\"phase\":\"hovering\",\"id\":0,\"x\":0,\"y\":0}", "invalid phase"),
+            ("{\"t\":0,\"window\":0,\"type\":\"gesture\",\"phase\":\"moved\",\"kind\":\"twist\"}", "unknown gesture"),
+            ("{\"t\":0,\"window\":0,\"type\":\"ime_commit\",\"text\":\"\\u12\"}", "invalid text"),
+            ("{\"t\":0,\"window\":0,\"type\":\"motion_history\",\"points\":\"1,2;3\"}", "invalid points"),
+            ("{\"t\":0,\"window\":0,\"type\":\"dropped\",\"formats\":\"a\\nb\",\"data\":\"AA==\"}", "invalid data"),
+            ("{\"t\":0,\"window\":0,\"type\":\"drag_finished\",\"effect\":\"teleport\"}", "invalid effect")
+        ]
+        {
+            let e = replay(&format!("{}{{\"t\":0,\"window\":0,\"type\":\"focus\",\"focused\":true}}\n{}\n", header, record)).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+            let text = e.to_string();
+            assert!(text.starts_with("recording line 3: ") && text.contains(msg), "{}", text);
+        }
+    }
+}
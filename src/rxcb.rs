@@ -7,7 +7,19 @@ extern crate xcb;
 use self::xcb::ffi::*;
 use std::ptr::{null, null_mut};
 use std::marker::PhantomData;
-use std::io::{Error as IOError, ErrorKind};
+use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use xproto;
+
+/// Layout of `xcb_extension_t` in libxcb, for the extensions whose bindings are declared here.
+/// The xcb crate declares it as an empty enum, which cannot be the type of a static
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_extension_id_t { name: *const ::libc::c_char, global_id: ::libc::c_int }
+macro_rules! extension_id
+{
+	($id: ident) => { ::std::ptr::addr_of_mut!($id) as *mut xcb_extension_t }
+}
 
 #[repr(C)] pub enum WindowIOClass
 {
@@ -15,8 +27,23 @@ use std::io::{Error as IOError, ErrorKind};
 	InputOutput = XCB_WINDOW_CLASS_INPUT_OUTPUT as _,
 	FromParent = XCB_WINDOW_CLASS_COPY_FROM_PARENT as _
 }
+/// Parameters of `Connection::create_window`. `None`s are copied from the parent, which defaults to the first root
+pub struct WindowParams<'v>
+{
+	pub depth: Option<u8>, pub parent: Option<xcb_window_t>, pub pos: (i16, i16), pub size: (u16, u16), pub border_width: u16,
+	pub class: WindowIOClass, pub visual: Option<VisualID>, pub values: &'v WindowValueList
+}
 
-pub struct Connection(*mut xcb_connection_t);
+/// Environment variable that makes requests wait for their results(for debugging)
+pub const CHECKED_ENV: &str = "APPFRAME_X11_CHECKED";
+/// Number of unchecked requests remembered for matching errors
+const PENDING_LOG_SIZE: usize = 256;
+
+/// A request sent without waiting for its result
+struct PendingRequest { sequence: u32, call: &'static str }
+/// Requests are unchecked and pipelined by default: errors arrive in the event queue and are matched to
+/// the requests by sequence number. Checked requests wait for the result of each request
+pub struct Connection(*mut xcb_connection_t, Cell<bool>, RefCell<VecDeque<PendingRequest>>);
 impl Connection
 {
 	pub fn new<S: UnivString + ?Sized>(display: Option<&S>) -> Option<Self>
@@ -26,9 +53,36 @@ impl Connection
 		{
 			xcb_connect(display_name.as_ref().map(|p| p.as_ptr()).unwrap_or(null()), null_mut())
 		};
-		if p.is_null() { None } else { Some(Connection(p)) }
+		// xcb_connect returns an errored connection instead of null on failure
+		if p.is_null() { return None; }
+		if unsafe { xcb_connection_has_error(p) } != 0 { unsafe { xcb_disconnect(p); } return None; }
+		let checked = ::std::env::var_os(CHECKED_ENV).is_some_and(|v| v != "0" && !v.is_empty());
+		Some(Connection(p, Cell::new(checked), RefCell::new(VecDeque::new())))
+	}
+	/// Whether requests wait for their results
+	pub fn is_checked(&self) -> bool { self.1.get() }
+	pub fn set_checked(&self, c: bool) { self.1.set(c); }
+	/// Wait for the result in checked mode, otherwise remember the request for matching its error later
+	fn issue(&self, cookie: xcb_void_cookie_t, call: &'static str) -> Result<(), GenericError>
+	{
+		if self.is_checked() { return CheckedCookie(cookie, self).check(); }
+		let mut log = self.2.borrow_mut();
+		if log.len() >= PENDING_LOG_SIZE { log.pop_front(); }
+		log.push_back(PendingRequest { sequence: cookie.sequence as _, call });
+		Ok(())
+	}
+	/// Forget the requests sent before the event of the sequence number. Their errors have been delivered already
+	pub fn settle(&self, seq: u32)
+	{
+		let mut log = self.2.borrow_mut();
+		while log.front().is_some_and(|r| (seq.wrapping_sub(r.sequence) as i32) > 0) { log.pop_front(); }
 	}
-	#[cfg(feature = "with_ferrite")]
+	/// The call that sent the request of the sequence number, if it is unchecked and not settled
+	fn pending_call(&self, sequence: u32) -> Option<&'static str>
+	{
+		self.2.borrow().iter().find(|r| r.sequence == sequence).map(|r| r.call)
+	}
+	#[cfg(feature = "with_bedrock")]
 	pub(crate) fn inner(&self) -> *mut xcb_connection_t { self.0 }
 	pub fn setup(&self) -> &Setup { unsafe { &*(xcb_get_setup(self.0) as *mut _) } }
 	pub fn new_id(&self) -> u32 { unsafe { xcb_generate_id(self.0) } }
@@ -38,32 +92,46 @@ impl Connection
 	{
 		AtomCookie(unsafe { xcb_intern_atom(self.0, 0, name.len() as _, name.as_ptr()) }, self)
 	}*/
-	pub fn intern(&self, name: &str) -> AtomCookie
+	pub fn intern(&self, name: &str) -> AtomCookie<'_>
 	{
 		AtomCookie(unsafe { xcb_intern_atom(self.0, 1, name.len() as _, name.as_ptr() as _) }, self)
 	}
 	pub fn flush(&self) { unsafe { xcb_flush(self.0); } }
 
-	pub fn create_window(&self, depth: Option<u8>, id: &Window, parent: Option<xcb_window_t>,
-		x: i16, y: i16, width: u16, height: u16, border_width: u16, class: WindowIOClass,
-		visual: Option<VisualID>, valuelist: &WindowValueList) -> Result<(), GenericError>
+	pub fn create_window(&self, id: &Window, p: WindowParams) -> Result<(), GenericError>
 	{
-		let serialized = valuelist.serialize();
-		unsafe
+		let serialized = p.values.serialize();
+		let (depth, parent) = (p.depth.unwrap_or(XCB_COPY_FROM_PARENT as _),
+			p.parent.unwrap_or_else(|| self.setup().iter_roots().next().unwrap().root()));
+		let visual = p.visual.unwrap_or(XCB_COPY_FROM_PARENT as _);
+		let ((x, y), (width, height)) = (p.pos, p.size);
+		let cookie = unsafe
 		{
-			CheckedCookie(xcb_create_window_checked(self.0, depth.unwrap_or(XCB_COPY_FROM_PARENT as _), id.0,
-				parent.unwrap_or_else(|| self.setup().iter_roots().next().unwrap().root()),
-				x, y, width, height, border_width, class as _, visual.unwrap_or(XCB_COPY_FROM_PARENT as _),
-				valuelist.0, serialized.0 as *const _), self).check()
-		}
+			if self.is_checked()
+			{
+				xcb_create_window_checked(self.0, depth, id.0, parent, x, y, width, height, p.border_width, p.class as _,
+					visual, p.values.0, serialized.0 as *const _)
+			}
+			else
+			{
+				xcb_create_window(self.0, depth, id.0, parent, x, y, width, height, p.border_width, p.class as _,
+					visual, p.values.0, serialized.0 as *const _)
+			}
+		};
+		self.issue(cookie, "create_window")
 	}
 	pub fn map_window(&self, w: &Window) -> Result<(), GenericError>
 	{
-		unsafe { CheckedCookie(xcb_map_window_checked(self.0, w.0), self).check() }
+		let cookie = unsafe { if self.is_checked() { xcb_map_window_checked(self.0, w.0) } else { xcb_map_window(self.0, w.0) } };
+		self.issue(cookie, "map_window")
 	}
 	pub fn destroy_window(&self, w: &Window) -> Result<(), GenericError>
 	{
-		unsafe { CheckedCookie(xcb_destroy_window_checked(self.0, w.0), self).check() }
+		let cookie = unsafe
+		{
+			if self.is_checked() { xcb_destroy_window_checked(self.0, w.0) } else { xcb_destroy_window(self.0, w.0) }
+		};
+		self.issue(cookie, "destroy_window")
 	}
 }
 impl Drop for Connection { fn drop(&mut self) { unsafe { xcb_disconnect(self.0) } } }
@@ -71,7 +139,7 @@ impl Drop for Connection { fn drop(&mut self) { unsafe { xcb_disconnect(self.0)
 pub struct Setup(xcb_setup_t);
 impl Setup
 {
-	pub fn iter_roots(&self) -> IterRootScreen { IterRootScreen(unsafe { xcb_setup_roots_iterator(&self.0) }) }
+	pub fn iter_roots(&self) -> IterRootScreen<'_> { IterRootScreen(unsafe { xcb_setup_roots_iterator(&self.0) }) }
 }
 #[repr(C)] pub struct Screen(xcb_screen_t);
 impl Screen
@@ -95,6 +163,7 @@ pub struct Window(WindowID);
 impl Window
 {
 	pub(crate) fn id(&self) -> WindowID { self.0 }
+	pub(crate) fn from_id(id: WindowID) -> Self { Window(id) }
 	pub fn replace_property<T: PropertyType + ?Sized>(&self, con: &Connection, property: Atom, value: &T)
 	{
 		value.change_property_of(con, self, property, XCB_PROP_MODE_REPLACE)
@@ -178,39 +247,141 @@ impl Connection
 		let p = unsafe { xcb_poll_for_event(self.0) };
 		if p.is_null() { None } else { Some(GenericEvent(MallocBox(p))) }
 	}
+	/// Wait for an event at most `timeout`(wait infinitely if None passed).
+	/// Returns None also when one of `others` becomes ready for its `events`
+	pub fn wait_event_timeout(&self, timeout: Option<Duration>, others: &[::libc::pollfd]) -> Option<GenericEvent>
+	{
+		if timeout.is_none() && others.is_empty() { return self.wait_event(); }
+		if let Some(e) = self.poll_event() { return Some(e); }
+		let connection = ::libc::pollfd { fd: unsafe { xcb_get_file_descriptor(self.0) }, events: ::libc::POLLIN, revents: 0 };
+		let mut fds = Some(connection).into_iter().chain(others.iter().cloned()).collect::<Vec<_>>();
+		let ms = timeout.map_or(-1, |t| t.as_secs() as i64 * 1000 + (t.subsec_nanos() as i64 + 999_999) / 1_000_000);
+		unsafe { ::libc::poll(fds.as_mut_ptr(), fds.len() as _, ms.min(::libc::c_int::MAX as _) as _) };
+		self.poll_event()
+	}
 }
 impl GenericEvent
 {
 	pub fn response_type(&self) -> u8 { self.0.response_type & !0x80 }
+	/// Sequence number of the last request processed by the server before the event
+	pub fn sequence(&self) -> u32 { self.0.full_sequence }
+	/// (major opcode of the extension, event type) of a GenericEvent(XGE)
+	pub fn ge_event_type(&self) -> Option<(u8, u16)>
+	{
+		if self.response_type() != XCB_GE_GENERIC { return None; }
+		let e = unsafe { &*(self.0 .0 as *const xcb_ge_generic_event_t) };
+		Some((e.extension, e.event_type))
+	}
+	/// Whole body of a GenericEvent(XGE). libxcb inserts `full_sequence` at offset 32
+	pub fn ge_data(&self) -> &[u8]
+	{
+		if self.response_type() != XCB_GE_GENERIC { return &[]; }
+		let e = unsafe { &*(self.0 .0 as *const xcb_ge_generic_event_t) };
+		unsafe { ::std::slice::from_raw_parts(self.0 .0 as *const u8, 36 + e.length as usize * 4) }
+	}
 }
 pub struct ClientMessageEvent(MallocBox<xcb_client_message_event_t>);
 impl ClientMessageEvent
 {
+	pub fn window(&self) -> WindowID { self.0.window }
 	pub fn msg_type(&self) -> xcb_atom_t { self.0.type_ }
 	pub fn data_as_u32(&self) -> u32 { unsafe { *(self.0.data.data.as_ptr() as *const u32) } }
+	pub fn format(&self) -> u8 { self.0.format }
+	/// Data of a 32-bit format message
+	pub fn data32(&self) -> [u32; 5] { unsafe { transmute(self.0.data.data) } }
+	/// Data of an 8-bit format message
+	pub fn data8(&self) -> [u8; 20] { self.0.data.data }
 }
 pub struct ExposeEvent(MallocBox<xcb_expose_event_t>);
+impl ExposeEvent
+{
+	pub fn window(&self) -> WindowID { self.0.window }
+	/// (x, y, width, height) of the exposed area
+	pub fn area(&self) -> (u16, u16, u16, u16) { (self.0.x, self.0.y, self.0.width, self.0.height) }
+	/// Number of Expose events following for the same window
+	pub fn count(&self) -> u16 { self.0.count }
+}
+/// MapNotify/UnmapNotify
+pub struct MapEvent(MallocBox<xcb_map_notify_event_t>);
+impl MapEvent
+{
+	pub fn window(&self) -> WindowID { self.0.window }
+}
+pub struct ConfigureNotifyEvent(MallocBox<xcb_configure_notify_event_t>);
+impl ConfigureNotifyEvent
+{
+	pub fn window(&self) -> WindowID { self.0.window }
+	pub fn size(&self) -> (u16, u16) { (self.0.width, self.0.height) }
+}
+/// KeyPress/KeyRelease
+pub struct KeyEvent(MallocBox<xcb_key_press_event_t>);
+impl KeyEvent
+{
+	pub fn window(&self) -> WindowID { self.0.event }
+	pub fn keycode(&self) -> u8 { self.0.detail }
+	pub fn state(&self) -> u16 { self.0.state }
+	pub fn time(&self) -> xcb_timestamp_t { self.0.time }
+	/// The event as sent by the server
+	pub fn raw(&self) -> [u8; 32] { unsafe { transmute(*self.0) } }
+}
+/// ButtonPress/ButtonRelease
+pub struct ButtonEvent(MallocBox<xcb_button_press_event_t>);
+impl ButtonEvent
+{
+	pub fn window(&self) -> WindowID { self.0.event }
+	pub fn button(&self) -> u8 { self.0.detail }
+	pub fn pos(&self) -> (i16, i16) { (self.0.event_x, self.0.event_y) }
+	pub fn root_pos(&self) -> (i16, i16) { (self.0.root_x, self.0.root_y) }
+	pub fn time(&self) -> xcb_timestamp_t { self.0.time }
+}
+pub struct MotionNotifyEvent(MallocBox<xcb_motion_notify_event_t>);
+impl MotionNotifyEvent
+{
+	pub fn window(&self) -> WindowID { self.0.event }
+	pub fn pos(&self) -> (i16, i16) { (self.0.event_x, self.0.event_y) }
+	pub fn root_pos(&self) -> (i16, i16) { (self.0.root_x, self.0.root_y) }
+	pub fn time(&self) -> xcb_timestamp_t { self.0.time }
+}
+/// FocusIn/FocusOut
+pub struct FocusEvent(MallocBox<xcb_focus_in_event_t>);
+impl FocusEvent
+{
+	pub fn window(&self) -> WindowID { self.0.event }
+	/// Focus changes caused by grabs are not the window's focus changes
+	pub fn is_grab(&self) -> bool
+	{
+		self.0.mode == XCB_NOTIFY_MODE_GRAB as u8 || self.0.mode == XCB_NOTIFY_MODE_UNGRAB as u8
+	}
+}
 pub struct GenericError(MallocBox<xcb_generic_error_t>);
 impl GenericError
 {
 	unsafe fn from_ptr(p: *mut xcb_generic_error_t) -> Self { GenericError(MallocBox(p)) }
+	pub fn error_code(&self) -> u8 { self.0.error_code }
+	pub fn major_code(&self) -> u8 { self.0.major_code }
+	pub fn minor_code(&self) -> u16 { self.0.minor_code }
+	pub fn resource_id(&self) -> u32 { self.0.resource_id }
+	pub fn sequence(&self) -> u32 { self.0.full_sequence }
 }
+/// Names are decoded for core errors and requests only. Use `Connection::decode_error` for extensions
 impl Debug for GenericError
 {
-	fn fmt(&self, fmt: &mut Formatter) -> FmtResult { write!(fmt, "GenericError(code={})", (*self.0).error_code) }
+	fn fmt(&self, fmt: &mut Formatter) -> FmtResult
+	{
+		let name = |table, i, ext| if ext { "Extension" } else { xproto::lookup(table, i) };
+		write!(fmt, "GenericError {{ error: {}({}), request: {}({}.{}), resource: 0x{:x}, sequence: {} }}",
+			name(xproto::CORE_ERRORS, self.error_code() as _, self.error_code() >= 128), self.error_code(),
+			name(xproto::CORE_REQUESTS, self.major_code() as _, self.major_code() >= 128),
+			self.major_code(), self.minor_code(), self.resource_id(), self.sequence())
+	}
 }
 impl Display for GenericError
 {
 	fn fmt(&self, fmt: &mut Formatter) -> FmtResult { <Self as Debug>::fmt(self, fmt) }
 }
-impl From<GenericError> for IOError
-{
-	fn from(v: GenericError) -> IOError { IOError::new(ErrorKind::Other, Box::new(v)) }
-}
 impl ::std::error::Error for GenericError
 {
 	fn description(&self) -> &str { "XCB Generic Error" }
-	fn cause(&self) -> Option<&::std::error::Error> { None }
 }
 unsafe impl Send for GenericError {}
 unsafe impl Sync for GenericError {}
@@ -229,9 +400,768 @@ impl Event for ExposeEvent
 	const RESPONSE_ENUM: u8 = XCB_EXPOSE;
 	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }	
 }
+impl Event for MapEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_MAP_NOTIFY;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for ConfigureNotifyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_CONFIGURE_NOTIFY;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for KeyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_KEY_PRESS;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for ButtonEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_BUTTON_PRESS;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for MotionNotifyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_MOTION_NOTIFY;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for FocusEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_FOCUS_IN;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
 impl Event for GenericError
 {
-	const RESPONSE_ENUM: u8 = 0; // unused
+	const RESPONSE_ENUM: u8 = 0;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+
+impl Connection
+{
+	/// Decode names of the error and the failed request, including the extensions used by appframe
+	pub fn decode_error(&self, e: &GenericError) -> ::ProtocolError
+	{
+		let mut d = ::ProtocolError
+		{
+			error_code: e.error_code(), error_name: "Unknown", major_opcode: e.major_code(), minor_opcode: e.minor_code(),
+			request_name: "Unknown", extension: None, resource_id: e.resource_id(), sequence: e.sequence(),
+			call: self.pending_call(e.sequence())
+		};
+		if e.error_code() < 128 { d.error_name = xproto::lookup(xproto::CORE_ERRORS, e.error_code() as _); }
+		if e.major_code() < 128 { d.request_name = xproto::lookup(xproto::CORE_REQUESTS, e.major_code() as _); }
+		let extensions = [
+			(extension_id!(xcb_present_id), &xproto::PRESENT), (::std::ptr::addr_of_mut!(xcb_randr_id), &xproto::RANDR),
+			(::std::ptr::addr_of_mut!(xcb_shm_id), &xproto::MIT_SHM), (::std::ptr::addr_of_mut!(xcb_composite_id), &xproto::COMPOSITE),
+			(::std::ptr::addr_of_mut!(xcb_render_id), &xproto::RENDER), (extension_id!(xcb_input_id), &xproto::XINPUT)
+		];
+		for &(id, names) in &extensions
+		{
+			let data = unsafe { xcb_get_extension_data(self.0, id) };
+			if data.is_null() || unsafe { (*data).present } == 0 { continue; }
+			let data = unsafe { &*data };
+			if data.major_opcode == e.major_code()
+			{
+				d.extension = Some(names.name);
+				d.request_name = xproto::lookup(names.requests, e.minor_code() as _);
+			}
+			let first = data.first_error as usize;
+			if (first .. first + names.errors.len()).contains(&(e.error_code() as usize))
+			{
+				d.error_name = names.errors[e.error_code() as usize - first];
+			}
+		}
+		d
+	}
+	pub fn error(&self, e: &GenericError) -> ::Error { ::Error::Protocol(self.decode_error(e)) }
+}
+
+// Present Extension //
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_present_query_version_cookie_t { sequence: ::libc::c_uint }
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_present_query_version_reply_t
+{
+	response_type: u8, pad0: u8, sequence: u16, length: u32, major_version: u32, minor_version: u32
+}
+#[repr(C, packed)] #[allow(non_camel_case_types)]
+pub struct xcb_present_complete_notify_event_t
+{
+	pub response_type: u8, pub extension: u8, pub sequence: u16, pub length: u32, pub event_type: u16,
+	pub kind: u8, pub mode: u8, pub event: u32, pub window: xcb_window_t, pub serial: u32, pub ust: u64,
+	pub full_sequence: u32, pub msc: u64
+}
+pub const XCB_PRESENT_EVENT_COMPLETE_NOTIFY: u16 = 1;
+pub const XCB_PRESENT_EVENT_MASK_COMPLETE_NOTIFY: u32 = 2;
+pub const XCB_PRESENT_COMPLETE_KIND_NOTIFY_MSC: u8 = 1;
+#[link(name = "xcb-present")]
+extern "C"
+{
+	static mut xcb_present_id: xcb_extension_id_t;
+	fn xcb_present_query_version(c: *mut xcb_connection_t, major_version: u32, minor_version: u32)
+		-> xcb_present_query_version_cookie_t;
+	fn xcb_present_query_version_reply(c: *mut xcb_connection_t, cookie: xcb_present_query_version_cookie_t,
+		e: *mut *mut xcb_generic_error_t) -> *mut xcb_present_query_version_reply_t;
+	fn xcb_present_select_input(c: *mut xcb_connection_t, eid: u32, window: xcb_window_t, event_mask: u32)
+		-> xcb_void_cookie_t;
+	fn xcb_present_notify_msc(c: *mut xcb_connection_t, window: xcb_window_t, serial: u32,
+		target_msc: u64, divisor: u64, remainder: u64) -> xcb_void_cookie_t;
+}
+/// Present extension is available on the server
+pub struct PresentExtension { major_opcode: u8 }
+impl Connection
+{
+	pub fn present_extension(&self) -> Option<PresentExtension>
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, extension_id!(xcb_present_id)) };
+		if data.is_null() || unsafe { (*data).present } == 0 { return None; }
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_present_query_version_reply(self.0, xcb_present_query_version(self.0, 1, 0), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		drop(MallocBox(r));
+		Some(PresentExtension { major_opcode: unsafe { (*data).major_opcode } })
+	}
+	pub fn present_select_input(&self, eid: u32, w: &Window, event_mask: u32)
+	{
+		unsafe { xcb_present_select_input(self.0, eid, w.0, event_mask); }
+	}
+	/// Request a CompleteNotify(kind = NotifyMSC) event at the next vblank
+	pub fn present_notify_next_msc(&self, w: &Window, serial: u32)
+	{
+		unsafe { xcb_present_notify_msc(self.0, w.0, serial, 0, 1, 0); }
+	}
+}
+impl PresentExtension
+{
+	/// Retrieve a CompleteNotify event if the event is it
+	pub fn complete_notify<'e>(&self, e: &'e GenericEvent) -> Option<&'e PresentCompleteNotifyEvent>
+	{
+		match e.ge_event_type()
+		{
+			Some((ext, XCB_PRESENT_EVENT_COMPLETE_NOTIFY)) if ext == self.major_opcode =>
+				Some(unsafe { PresentCompleteNotifyEvent::from_ref(e) }),
+			_ => None
+		}
+	}
+}
+pub struct PresentCompleteNotifyEvent(MallocBox<xcb_present_complete_notify_event_t>);
+impl PresentCompleteNotifyEvent
+{
+	pub fn window(&self) -> WindowID { self.0.window }
+	pub fn kind(&self) -> u8 { self.0.kind }
+	/// Time of the vblank in microseconds(CLOCK_MONOTONIC)
+	pub fn ust(&self) -> u64 { self.0.ust }
+	pub fn msc(&self) -> u64 { self.0.msc }
+}
+impl Event for PresentCompleteNotifyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_GE_GENERIC;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+
+// RandR Extension //
+use self::xcb::ffi::randr::*;
+impl Connection
+{
+	/// Refresh rate(in Hz) of the CRTC showing the point(x, y) of the root window
+	pub fn refresh_rate_at(&self, root: WindowID, x: i16, y: i16) -> Option<f64>
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, ::std::ptr::addr_of_mut!(xcb_randr_id)) };
+		if data.is_null() || unsafe { (*data).present } == 0 { return None; }
+		let mut _eptr = null_mut();
+		let res = unsafe
+		{
+			xcb_randr_get_screen_resources_current_reply(self.0, xcb_randr_get_screen_resources_current(self.0, root), &mut _eptr)
+		};
+		if res.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let res = MallocBox(res);
+		let crtcs = unsafe
+		{
+			::std::slice::from_raw_parts(xcb_randr_get_screen_resources_current_crtcs(&*res),
+				xcb_randr_get_screen_resources_current_crtcs_length(&*res) as _)
+		};
+		let modes = unsafe
+		{
+			::std::slice::from_raw_parts(xcb_randr_get_screen_resources_current_modes(&*res),
+				xcb_randr_get_screen_resources_current_modes_length(&*res) as _)
+		};
+		let mut fallback = None;
+		for &c in crtcs
+		{
+			let mut _eptr = null_mut();
+			let info = unsafe { xcb_randr_get_crtc_info_reply(self.0, xcb_randr_get_crtc_info(self.0, c, res.config_timestamp), &mut _eptr) };
+			if info.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); continue; }
+			let info = MallocBox(info);
+			let rate = match modes.iter().find(|m| m.id == info.mode)
+			{
+				Some(m) if m.htotal != 0 && m.vtotal != 0 =>
+				{
+					let mut vtotal = m.vtotal as f64;
+					if (m.mode_flags & XCB_RANDR_MODE_FLAG_DOUBLE_SCAN) != 0 { vtotal *= 2.0; }
+					if (m.mode_flags & XCB_RANDR_MODE_FLAG_INTERLACE) != 0 { vtotal /= 2.0; }
+					m.dot_clock as f64 / (m.htotal as f64 * vtotal)
+				},
+				_ => continue
+			};
+			let (x, y) = (x as i32, y as i32);
+			if info.x as i32 <= x && x < info.x as i32 + info.width as i32 && info.y as i32 <= y && y < info.y as i32 + info.height as i32
+			{
+				return Some(rate);
+			}
+			if fallback.is_none() { fallback = Some(rate); }
+		}
+		fallback
+	}
+}
+
+// Image Transfer //
+pub struct GContext(xcb_gcontext_t);
+impl GContext
+{
+	pub fn new(con: &Connection, drawable: WindowID) -> Self
+	{
+		let id = con.new_id();
+		unsafe { xcb_create_gc(con.0, id, drawable, 0, null()) }; GContext(id)
+	}
+	pub fn id(&self) -> xcb_gcontext_t { self.0 }
+	pub fn free(&self, con: &Connection) { unsafe { xcb_free_gc(con.0, self.0) }; }
+}
+impl Connection
+{
+	/// Maximum length of a request in bytes
+	pub fn maximum_request_bytes(&self) -> usize { unsafe { xcb_get_maximum_request_length(self.0) as usize * 4 } }
+	/// Upload ZPixmap-formatted pixels. `data` must fit in a request
+	pub fn put_image(&self, drawable: WindowID, gc: &GContext, size: (u16, u16), dst: (i16, i16), depth: u8, data: &[u8])
+	{
+		unsafe
+		{
+			xcb_put_image(self.0, XCB_IMAGE_FORMAT_Z_PIXMAP as _, drawable, gc.0, size.0, size.1, dst.0, dst.1, 0, depth,
+				data.len() as _, data.as_ptr());
+		}
+	}
+	/// Waits until the server processes all the requests sent before
+	pub fn sync(&self)
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_get_input_focus_reply(self.0, xcb_get_input_focus(self.0), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); } else { drop(MallocBox(r)); }
+	}
+}
+
+impl Connection
+{
+	/// Position of the origin of the window in the root window
+	pub fn root_position(&self, w: WindowID, root: WindowID) -> Option<(i16, i16)>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_translate_coordinates_reply(self.0, xcb_translate_coordinates(self.0, w, root, 0, 0), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let r = MallocBox(r);
+		Some((r.dst_x, r.dst_y))
+	}
+	/// Whether the window and all its ancestors are mapped
+	pub fn is_viewable(&self, w: WindowID) -> bool
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_get_window_attributes_reply(self.0, xcb_get_window_attributes(self.0, w), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return false; }
+		MallocBox(r).map_state == XCB_MAP_STATE_VIEWABLE as _
+	}
+}
+pub struct ImageReply(MallocBox<xcb_get_image_reply_t>);
+impl ImageReply
+{
+	pub fn depth(&self) -> u8 { self.0.depth }
+	pub fn data(&self) -> &[u8]
+	{
+		unsafe { ::std::slice::from_raw_parts(xcb_get_image_data(&*self.0), xcb_get_image_data_length(&*self.0) as _) }
+	}
+}
+impl Connection
+{
+	/// Read pixels of the drawable in the ZPixmap format
+	pub fn get_image(&self, drawable: WindowID, x: i16, y: i16, width: u16, height: u16) -> Result<ImageReply, GenericError>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe
+		{
+			xcb_get_image_reply(self.0, xcb_get_image(self.0, XCB_IMAGE_FORMAT_Z_PIXMAP as _, drawable, x, y, width, height, !0),
+				&mut _eptr)
+		};
+		if r.is_null() { Err(unsafe { GenericError::from_ptr(_eptr) }) } else { Ok(ImageReply(MallocBox(r))) }
+	}
+	pub fn free_pixmap(&self, p: xcb_pixmap_t) { unsafe { xcb_free_pixmap(self.0, p); } }
+}
+impl Setup
+{
+	/// Whether pixels in images are stored least significant byte first
+	pub fn is_image_lsb_first(&self) -> bool { self.0.image_byte_order == XCB_IMAGE_ORDER_LSB_FIRST as _ }
+}
+
+// Composite Extension //
+use self::xcb::ffi::composite::*;
+impl Connection
+{
+	pub fn composite_available(&self) -> bool
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, ::std::ptr::addr_of_mut!(xcb_composite_id)) };
+		!data.is_null() && unsafe { (*data).present } != 0
+	}
+	/// Pixmap holding the contents of a window redirected by the Composite extension.
+	/// Fails if the window is not redirected
+	pub fn name_window_pixmap(&self, w: WindowID) -> Result<xcb_pixmap_t, GenericError>
+	{
+		let id = self.new_id();
+		unsafe { CheckedCookie(xcb_composite_name_window_pixmap_checked(self.0, w, id), self).check() }.map(|_| id)
+	}
+}
+
+// Render Extension //
+use self::xcb::ffi::render::*;
+pub type PictFormat = xcb_render_pictformat_t;
+impl Connection
+{
+	/// The 32-bit direct format with 8-bit channels in ARGB order, if RENDER 0.5 or later is available
+	pub fn argb_picture_format(&self) -> Option<PictFormat>
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, ::std::ptr::addr_of_mut!(xcb_render_id)) };
+		if data.is_null() || unsafe { (*data).present } == 0 { return None; }
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_render_query_version_reply(self.0, xcb_render_query_version(self.0, 0, 11), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let r = MallocBox(r);
+		if r.major_version == 0 && r.minor_version < 5 { return None; }
+		let r = unsafe { xcb_render_query_pict_formats_reply(self.0, xcb_render_query_pict_formats(self.0), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let r = MallocBox(r);
+		let mut iter = unsafe { xcb_render_query_pict_formats_formats_iterator(&*r) };
+		while iter.rem > 0
+		{
+			let f = unsafe { &*iter.data };
+			let d = &f.direct;
+			if f.type_ == XCB_RENDER_PICT_TYPE_DIRECT as _ && f.depth == 32 &&
+				(d.alpha_shift, d.red_shift, d.green_shift, d.blue_shift) == (24, 16, 8, 0) &&
+				(d.alpha_mask, d.red_mask, d.green_mask, d.blue_mask) == (0xff, 0xff, 0xff, 0xff)
+			{
+				return Some(f.id);
+			}
+			unsafe { xcb_render_pictforminfo_next(&mut iter); }
+		}
+		None
+	}
+	/// Cursor from premultiplied ARGB pixels, row by row
+	pub fn create_argb_cursor(&self, root: WindowID, format: PictFormat, size: (u16, u16), hotspot: (u16, u16),
+		pixels: &[u32]) -> CursorID
+	{
+		let lsb = self.setup().is_image_lsb_first();
+		let data: Vec<u8> = pixels.iter().flat_map(|&p| if lsb { p.to_le_bytes() } else { p.to_be_bytes() }.to_vec()).collect();
+		let (pixmap, picture, cursor) = (self.new_id(), self.new_id(), self.new_id());
+		unsafe { xcb_create_pixmap(self.0, 32, pixmap, root, size.0, size.1); }
+		let gc = GContext::new(self, pixmap);
+		self.put_image(pixmap, &gc, size, (0, 0), 32, &data);
+		gc.free(self);
+		unsafe
+		{
+			xcb_render_create_picture(self.0, picture, pixmap, format, 0, null());
+			xcb_render_create_cursor(self.0, cursor, picture, hotspot.0, hotspot.1);
+			xcb_render_free_picture(self.0, picture);
+		}
+		self.free_pixmap(pixmap);
+		cursor
+	}
+}
+
+// Cursors //
+pub type CursorID = xcb_cursor_t;
+impl Connection
+{
+	/// Cursor from the core cursor font. `glyph` is an even index(e.g. 68 for `left_ptr`)
+	pub fn create_font_cursor(&self, glyph: u16) -> CursorID
+	{
+		let (font, cursor) = (self.new_id(), self.new_id());
+		unsafe
+		{
+			xcb_open_font(self.0, font, 6, b"cursor".as_ptr() as _);
+			xcb_create_glyph_cursor(self.0, cursor, font, font, glyph, glyph + 1, 0, 0, 0, 0xffff, 0xffff, 0xffff);
+			xcb_close_font(self.0, font);
+		}
+		cursor
+	}
+	/// Fully transparent cursor
+	pub fn create_blank_cursor(&self, root: WindowID) -> CursorID
+	{
+		let (pixmap, cursor) = (self.new_id(), self.new_id());
+		unsafe { xcb_create_pixmap(self.0, 1, pixmap, root, 1, 1); }
+		let gc = self.new_id();
+		let rect = xcb_rectangle_t { x: 0, y: 0, width: 1, height: 1 };
+		unsafe
+		{
+			xcb_create_gc(self.0, gc, pixmap, XCB_GC_FOREGROUND, &0);
+			xcb_poly_fill_rectangle(self.0, pixmap, gc, 1, &rect);
+			xcb_free_gc(self.0, gc);
+			xcb_create_cursor(self.0, cursor, pixmap, pixmap, 0, 0, 0, 0, 0, 0, 0, 0);
+		}
+		self.free_pixmap(pixmap);
+		cursor
+	}
+	pub fn free_cursor(&self, c: CursorID) { unsafe { xcb_free_cursor(self.0, c); } }
+	/// Change the cursor shown over the window
+	pub fn set_window_cursor(&self, w: WindowID, c: CursorID)
+	{
+		unsafe { xcb_change_window_attributes(self.0, w, XCB_CW_CURSOR, &c); }
+	}
+}
+
+// XInput Extension //
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_input_xi_query_version_cookie_t { sequence: ::libc::c_uint }
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_input_xi_query_version_reply_t
+{
+	response_type: u8, pad0: u8, sequence: u16, length: u32, major_version: u16, minor_version: u16, pad1: [u8; 20]
+}
+#[link(name = "xcb-xinput")]
+extern "C"
+{
+	static mut xcb_input_id: xcb_extension_id_t;
+	fn xcb_input_xi_query_version(c: *mut xcb_connection_t, major_version: u16, minor_version: u16)
+		-> xcb_input_xi_query_version_cookie_t;
+	fn xcb_input_xi_query_version_reply(c: *mut xcb_connection_t, cookie: xcb_input_xi_query_version_cookie_t,
+		e: *mut *mut xcb_generic_error_t) -> *mut xcb_input_xi_query_version_reply_t;
+	/// `masks` are pairs of (deviceid, mask_len) and `mask_len` words of the mask
+	fn xcb_input_xi_select_events(c: *mut xcb_connection_t, window: xcb_window_t, num_mask: u16, masks: *const u16)
+		-> xcb_void_cookie_t;
+	fn xcb_input_xi_query_device(c: *mut xcb_connection_t, deviceid: u16) -> xcb_input_xi_query_device_cookie_t;
+	fn xcb_input_xi_query_device_reply(c: *mut xcb_connection_t, cookie: xcb_input_xi_query_device_cookie_t,
+		e: *mut *mut xcb_generic_error_t) -> *mut xcb_generic_reply_t;
+}
+#[repr(C)] #[allow(non_camel_case_types)]
+struct xcb_input_xi_query_device_cookie_t { sequence: ::libc::c_uint }
+pub const XI_ALL_DEVICES: u16 = 0;
+pub const XI_ALL_MASTER_DEVICES: u16 = 1;
+/// XInput 2 is available on the server
+pub struct XInputExtension { major_opcode: u8, version: (u16, u16) }
+impl Connection
+{
+	/// Negotiate XInput up to 2.4. None if the server does not support XInput 2
+	pub fn xinput_extension(&self) -> Option<XInputExtension>
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, extension_id!(xcb_input_id)) };
+		if data.is_null() || unsafe { (*data).present } == 0 { return None; }
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_input_xi_query_version_reply(self.0, xcb_input_xi_query_version(self.0, 2, 4), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let r = MallocBox(r);
+		if r.major_version < 2 { return None; }
+		Some(XInputExtension { major_opcode: unsafe { (*data).major_opcode }, version: (r.major_version, r.minor_version) })
+	}
+	/// Replace the XI2 events selected on the window for each (deviceid, mask). Bit n of a mask selects the event type n
+	pub fn xi_select_events(&self, w: WindowID, masks: &[(u16, &[u32])])
+	{
+		let mut m = Vec::new();
+		for &(deviceid, mask) in masks
+		{
+			// deviceid and mask_len are native-endian u16s
+			let header = if cfg!(target_endian = "little") { u32::from(deviceid) | u32::from(mask.len() as u16) << 16 }
+				else { u32::from(deviceid) << 16 | u32::from(mask.len() as u16) };
+			m.push(header);
+			// the mask is a byte array on the wire
+			m.extend(mask.iter().map(|w| w.to_le()));
+		}
+		unsafe { xcb_input_xi_select_events(self.0, w, masks.len() as _, m.as_ptr() as *const u16); }
+	}
+	/// Devices and their classes(XIQueryDevice). `XI_ALL_DEVICES` for all of them
+	pub fn xi_query_device(&self, deviceid: u16) -> Result<RawReply, GenericError>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_input_xi_query_device_reply(self.0, xcb_input_xi_query_device(self.0, deviceid), &mut _eptr) };
+		if r.is_null() { Err(unsafe { GenericError::from_ptr(_eptr) }) } else { Ok(RawReply(MallocBox(r))) }
+	}
+}
+/// A reply decoded by the caller
+pub struct RawReply(MallocBox<xcb_generic_reply_t>);
+impl RawReply
+{
+	/// The whole reply including the 32 byte header
+	pub fn data(&self) -> &[u8]
+	{
+		unsafe { ::std::slice::from_raw_parts(self.0 .0 as *const u8, 32 + self.0.length as usize * 4) }
+	}
+}
+impl XInputExtension
+{
+	/// Negotiated (major, minor) version
+	pub fn version(&self) -> (u16, u16) { self.version }
+	/// Type of an XI2 event if the event is it
+	pub fn event_type(&self, e: &GenericEvent) -> Option<u16>
+	{
+		match e.ge_event_type() { Some((ext, t)) if ext == self.major_opcode => Some(t), _ => None }
+	}
+}
+
+// MIT-SHM Extension //
+use self::xcb::ffi::shm::*;
+/// A System V shared memory segment attached to the server
+pub struct ShmSegment { id: xcb_shm_seg_t, addr: *mut u8, size: usize }
+impl ShmSegment
+{
+	pub fn id(&self) -> xcb_shm_seg_t { self.id }
+	pub fn size(&self) -> usize { self.size }
+	pub fn data_mut(&mut self) -> &mut [u8] { unsafe { ::std::slice::from_raw_parts_mut(self.addr, self.size) } }
+}
+/// MIT-SHM is available on the server
+pub struct ShmExtension { first_event: u8 }
+impl Connection
+{
+	pub fn shm_extension(&self) -> Option<ShmExtension>
+	{
+		let data = unsafe { xcb_get_extension_data(self.0, ::std::ptr::addr_of_mut!(xcb_shm_id)) };
+		if data.is_null() || unsafe { (*data).present } == 0 { return None; }
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_shm_query_version_reply(self.0, xcb_shm_query_version(self.0), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		drop(MallocBox(r));
+		Some(ShmExtension { first_event: unsafe { (*data).first_event } })
+	}
+	/// Fails if the server cannot attach the segment(e.g. connected remotely)
+	pub fn create_shm_segment(&self, size: usize) -> Option<ShmSegment>
+	{
+		let shmid = unsafe { ::libc::shmget(::libc::IPC_PRIVATE, size, ::libc::IPC_CREAT | 0o600) };
+		if shmid < 0 { return None; }
+		let addr = unsafe { ::libc::shmat(shmid, null(), 0) };
+		let id = self.new_id();
+		let attached = addr as isize != -1 &&
+			unsafe { CheckedCookie(xcb_shm_attach_checked(self.0, id, shmid as _, 1), self).check() }.is_ok();
+		// the segment is destroyed once both sides detach
+		unsafe { ::libc::shmctl(shmid, ::libc::IPC_RMID, null_mut()); }
+		if attached { Some(ShmSegment { id, addr: addr as _, size }) }
+		else
+		{
+			if addr as isize != -1 { unsafe { ::libc::shmdt(addr); } }
+			None
+		}
+	}
+	pub fn destroy_shm_segment(&self, seg: ShmSegment)
+	{
+		unsafe { xcb_shm_detach(self.0, seg.id); ::libc::shmdt(seg.addr as _); }
+	}
+	/// Upload the area `src`(x, y, width, height) of a ZPixmap image of `size` stored at the head of `seg`.
+	/// The server reads the segment asynchronously, and sends a Completion event when done if `send_event` is set
+	#[allow(clippy::too_many_arguments)]
+	pub fn shm_put_image(&self, drawable: WindowID, gc: &GContext, size: (u16, u16),
+		src: (u16, u16, u16, u16), dst: (i16, i16), depth: u8, seg: &ShmSegment, send_event: bool)
+	{
+		unsafe
+		{
+			xcb_shm_put_image(self.0, drawable, gc.0, size.0, size.1, src.0, src.1, src.2, src.3,
+				dst.0, dst.1, depth, XCB_IMAGE_FORMAT_Z_PIXMAP as _, send_event as _, seg.id, 0);
+		}
+	}
+}
+impl ShmExtension
+{
+	/// Retrieve a Completion event if the event is it
+	pub fn completion<'e>(&self, e: &'e GenericEvent) -> Option<&'e ShmCompletionEvent>
+	{
+		if e.response_type() == self.first_event + ShmCompletionEvent::RESPONSE_ENUM
+		{
+			Some(unsafe { ShmCompletionEvent::from_ref(e) })
+		}
+		else { None }
+	}
+}
+pub struct ShmCompletionEvent(MallocBox<xcb_shm_completion_event_t>);
+impl ShmCompletionEvent
+{
+	pub fn drawable(&self) -> WindowID { self.0.drawable }
+}
+impl Event for ShmCompletionEvent
+{
+	/// Relative to the first event of the extension
+	const RESPONSE_ENUM: u8 = XCB_SHM_COMPLETION;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+
+// Selections and Properties //
+pub type Timestamp = xcb_timestamp_t;
+impl Connection
+{
+	/// Intern an atom, creating it if it does not exist
+	pub fn intern_create(&self, name: &str) -> AtomCookie<'_>
+	{
+		AtomCookie(unsafe { xcb_intern_atom(self.0, 0, name.len() as _, name.as_ptr() as _) }, self)
+	}
+	pub fn set_selection_owner(&self, owner: WindowID, selection: Atom, time: Timestamp)
+	{
+		unsafe { xcb_set_selection_owner(self.0, owner, selection, time); }
+	}
+	/// `XCB_WINDOW_NONE` if nobody owns the selection
+	pub fn selection_owner(&self, selection: Atom) -> Result<WindowID, GenericError>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_get_selection_owner_reply(self.0, xcb_get_selection_owner(self.0, selection), &mut _eptr) };
+		if r.is_null() { Err(unsafe { GenericError::from_ptr(_eptr) }) } else { Ok(MallocBox(r).owner) }
+	}
+	/// Ask the owner of the selection to store it into the property of the requestor in the format `target`
+	pub fn convert_selection(&self, requestor: WindowID, selection: Atom, target: Atom, property: Atom, time: Timestamp)
+	{
+		unsafe { xcb_convert_selection(self.0, requestor, selection, target, property, time); }
+	}
+	/// Tell the requestor the result of a conversion. `property` is `XCB_ATOM_NONE` if refused
+	pub fn send_selection_notify(&self, requestor: WindowID, selection: Atom, target: Atom, property: Atom, time: Timestamp)
+	{
+		let e = xcb_selection_notify_event_t
+		{
+			response_type: XCB_SELECTION_NOTIFY, pad0: 0, sequence: 0, time, requestor, selection, target, property
+		};
+		self.send_event(requestor, 0, &e);
+	}
+	/// Send an event(at most 32 bytes) to a window
+	pub fn send_event<T: Copy>(&self, destination: WindowID, event_mask: u32, event: &T)
+	{
+		assert!(::std::mem::size_of::<T>() <= 32, "events are 32 bytes long");
+		let mut buf = [0u8; 32];
+		unsafe
+		{
+			::std::ptr::copy_nonoverlapping(event as *const T as *const u8, buf.as_mut_ptr(), ::std::mem::size_of::<T>());
+			xcb_send_event(self.0, 0, destination, event_mask, buf.as_ptr() as _);
+		}
+	}
+	/// Send a 32-bit format ClientMessage about `window` to `destination`
+	pub fn send_client_message(&self, destination: WindowID, window: WindowID, type_: Atom, data: [u32; 5])
+	{
+		let e = xcb_client_message_event_t
+		{
+			response_type: XCB_CLIENT_MESSAGE, format: 32, sequence: 0, window, type_,
+			data: xcb_client_message_data_t { data: unsafe { transmute::<[u32; 5], [u8; 20]>(data) } }
+		};
+		self.send_event(destination, 0, &e);
+	}
+	/// Send an 8-bit format ClientMessage about `window` to `destination`
+	pub fn send_client_message8(&self, destination: WindowID, window: WindowID, type_: Atom, data: [u8; 20])
+	{
+		let e = xcb_client_message_event_t
+		{
+			response_type: XCB_CLIENT_MESSAGE, format: 8, sequence: 0, window, type_,
+			data: xcb_client_message_data_t { data }
+		};
+		self.send_event(destination, 0, &e);
+	}
+	pub fn atom_name(&self, a: Atom) -> Result<String, GenericError>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_get_atom_name_reply(self.0, xcb_get_atom_name(self.0, a), &mut _eptr) };
+		if r.is_null() { return Err(unsafe { GenericError::from_ptr(_eptr) }); }
+		let r = MallocBox(r);
+		let name = unsafe
+		{
+			::std::slice::from_raw_parts(xcb_get_atom_name_name(&*r) as *const u8, xcb_get_atom_name_name_length(&*r) as _)
+		};
+		Ok(String::from_utf8_lossy(name).into_owned())
+	}
+	/// Replace a property with `data`, a list of `format`-bit items
+	pub fn change_property(&self, w: WindowID, property: Atom, type_: Atom, format: u8, data: &[u8])
+	{
+		unsafe
+		{
+			xcb_change_property(self.0, XCB_PROP_MODE_REPLACE as _, w, property, type_, format,
+				(data.len() * 8 / format as usize) as _, data.as_ptr() as _);
+		}
+	}
+	pub fn change_property32(&self, w: WindowID, property: Atom, type_: Atom, data: &[u32])
+	{
+		unsafe { xcb_change_property(self.0, XCB_PROP_MODE_REPLACE as _, w, property, type_, 32, data.len() as _, data.as_ptr() as _); }
+	}
+	/// Read a whole property, deleting it afterwards if `delete` is true
+	pub fn get_property(&self, w: WindowID, property: Atom, delete: bool) -> Result<PropertyReply, GenericError>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe
+		{
+			xcb_get_property_reply(self.0,
+				xcb_get_property(self.0, delete as _, w, property, XCB_GET_PROPERTY_TYPE_ANY, 0, 0x1fff_ffff), &mut _eptr)
+		};
+		if r.is_null() { Err(unsafe { GenericError::from_ptr(_eptr) }) } else { Ok(PropertyReply(MallocBox(r))) }
+	}
+	pub fn delete_property(&self, w: WindowID, property: Atom) { unsafe { xcb_delete_property(self.0, w, property); } }
+	/// Replace the events selected on a window(which may be owned by another client)
+	pub fn select_input(&self, w: WindowID, event_mask: u32)
+	{
+		unsafe { xcb_change_window_attributes(self.0, w, XCB_CW_EVENT_MASK, &event_mask); }
+	}
+}
+pub struct PropertyReply(MallocBox<xcb_get_property_reply_t>);
+impl PropertyReply
+{
+	/// `XCB_ATOM_NONE` if the property does not exist
+	pub fn type_(&self) -> Atom { self.0.type_ }
+	pub fn format(&self) -> u8 { self.0.format }
+	pub fn value(&self) -> &[u8]
+	{
+		unsafe
+		{
+			::std::slice::from_raw_parts(xcb_get_property_value(&*self.0) as *const u8,
+				xcb_get_property_value_length(&*self.0) as _)
+		}
+	}
+	/// Items of a 32-bit property(e.g. atoms)
+	pub fn value32(&self) -> &[u32]
+	{
+		if self.format() != 32 { return &[]; }
+		let v = self.value();
+		unsafe { ::std::slice::from_raw_parts(v.as_ptr() as *const u32, v.len() / 4) }
+	}
+}
+pub struct SelectionRequestEvent(MallocBox<xcb_selection_request_event_t>);
+impl SelectionRequestEvent
+{
+	pub fn time(&self) -> Timestamp { self.0.time }
+	pub fn owner(&self) -> WindowID { self.0.owner }
+	pub fn requestor(&self) -> WindowID { self.0.requestor }
+	pub fn selection(&self) -> Atom { self.0.selection }
+	pub fn target(&self) -> Atom { self.0.target }
+	pub fn property(&self) -> Atom { self.0.property }
+}
+pub struct SelectionNotifyEvent(MallocBox<xcb_selection_notify_event_t>);
+impl SelectionNotifyEvent
+{
+	pub fn requestor(&self) -> WindowID { self.0.requestor }
+	pub fn selection(&self) -> Atom { self.0.selection }
+	pub fn target(&self) -> Atom { self.0.target }
+	/// `XCB_ATOM_NONE` if the conversion is refused
+	pub fn property(&self) -> Atom { self.0.property }
+}
+pub struct SelectionClearEvent(MallocBox<xcb_selection_clear_event_t>);
+impl SelectionClearEvent
+{
+	pub fn owner(&self) -> WindowID { self.0.owner }
+	pub fn selection(&self) -> Atom { self.0.selection }
+}
+pub struct PropertyNotifyEvent(MallocBox<xcb_property_notify_event_t>);
+impl PropertyNotifyEvent
+{
+	pub fn window(&self) -> WindowID { self.0.window }
+	pub fn atom(&self) -> Atom { self.0.atom }
+	pub fn time(&self) -> Timestamp { self.0.time }
+	pub fn is_deleted(&self) -> bool { self.0.state == XCB_PROPERTY_DELETE as u8 }
+}
+impl Event for SelectionRequestEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_SELECTION_REQUEST;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for SelectionNotifyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_SELECTION_NOTIFY;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for SelectionClearEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_SELECTION_CLEAR;
+	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
+}
+impl Event for PropertyNotifyEvent
+{
+	const RESPONSE_ENUM: u8 = XCB_PROPERTY_NOTIFY;
 	unsafe fn from_ref(g: &GenericEvent) -> &Self { transmute(g) }
 }
 
@@ -253,7 +1183,7 @@ impl<'c> Iterator for IterDepths<'c>
 }
 impl Screen
 {
-	pub fn iter_allowed_depths(&self) -> IterDepths { IterDepths(unsafe { xcb_screen_allowed_depths_iterator(&self.0) }) }
+	pub fn iter_allowed_depths(&self) -> IterDepths<'_> { IterDepths(unsafe { xcb_screen_allowed_depths_iterator(&self.0) }) }
 }
 pub type VisualID = xcb_visualid_t;
 #[repr(C)] pub struct VisualType(xcb_visualtype_t);
@@ -261,6 +1191,8 @@ impl VisualType
 {
 	pub fn id(&self) -> VisualID { self.0.visual_id }
 	pub fn is_truecolor(&self) -> bool { self.0.class == XCB_VISUAL_CLASS_TRUE_COLOR as _ }
+	/// Masks of the red, green and blue bits in a pixel value
+	pub fn color_masks(&self) -> (u32, u32, u32) { (self.0.red_mask, self.0.green_mask, self.0.blue_mask) }
 }
 pub struct IterVisualTypes<'c>(xcb_visualtype_iterator_t, PhantomData<&'c Connection>);
 impl<'c> Iterator for IterVisualTypes<'c>
@@ -274,7 +1206,7 @@ impl<'c> Iterator for IterVisualTypes<'c>
 }
 impl Depth
 {
-	pub fn iter_visuals(&self) -> IterVisualTypes
+	pub fn iter_visuals(&self) -> IterVisualTypes<'_>
 	{
 		IterVisualTypes(unsafe { xcb_depth_visuals_iterator(&self.0) }, PhantomData)
 	}
@@ -317,6 +1249,10 @@ impl WindowValueList
 	{
 		self.0 |= XCB_CW_EVENT_MASK; self.1.event_mask = m; self
 	}
+	pub fn cursor(&mut self, c: CursorID) -> &mut Self
+	{
+		self.0 |= XCB_CW_CURSOR; self.1.cursor = c; self
+	}
 	
 	pub fn serialize(&self) -> MallocBox<::libc::c_void>
 	{
@@ -335,8 +1271,43 @@ impl Colormap
 	}
 	pub fn id(&self) -> xcb_colormap_t { self.0 }
 }
+// Pointer Grabs //
+impl Connection
+{
+	/// Actively grab the pointer to `w`, keeping it in `confine_to` if specified. `XCB_CURSOR_NONE` keeps the cursor of the windows.
+	/// Returns false if another client holds a grab, `time` is too old or `confine_to` is not viewable
+	pub fn grab_pointer(&self, w: WindowID, event_mask: u16, confine_to: Option<WindowID>, cursor: CursorID, time: Timestamp) -> bool
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe
+		{
+			xcb_grab_pointer_reply(self.0, xcb_grab_pointer(self.0, 0, w, event_mask,
+				XCB_GRAB_MODE_ASYNC as _, XCB_GRAB_MODE_ASYNC as _, confine_to.unwrap_or(XCB_WINDOW_NONE), cursor, time), &mut _eptr)
+		};
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); false } else { MallocBox(r).status == XCB_GRAB_STATUS_SUCCESS as u8 }
+	}
+	pub fn ungrab_pointer(&self, time: Timestamp) { unsafe { xcb_ungrab_pointer(self.0, time); } }
+	/// Move the pointer to (x, y) of `w`
+	pub fn warp_pointer(&self, w: WindowID, x: i16, y: i16) { unsafe { xcb_warp_pointer(self.0, XCB_WINDOW_NONE, w, 0, 0, 0, 0, x, y); } }
+	/// The child of `w` containing the pointer, if any
+	pub fn pointer_child(&self, w: WindowID) -> Option<WindowID>
+	{
+		let mut _eptr = null_mut();
+		let r = unsafe { xcb_query_pointer_reply(self.0, xcb_query_pointer(self.0, w), &mut _eptr) };
+		if r.is_null() { drop(unsafe { GenericError::from_ptr(_eptr) }); return None; }
+		let child = MallocBox(r).child;
+		if child == XCB_WINDOW_NONE { None } else { Some(child) }
+	}
+}
+
 pub use self::xcb::ffi::{
-	XCB_EVENT_MASK_EXPOSURE
+	XCB_EVENT_MASK_EXPOSURE, XCB_EVENT_MASK_STRUCTURE_NOTIFY, XCB_EVENT_MASK_KEY_PRESS, XCB_EVENT_MASK_KEY_RELEASE,
+	XCB_EVENT_MASK_BUTTON_PRESS, XCB_EVENT_MASK_BUTTON_RELEASE, XCB_EVENT_MASK_POINTER_MOTION,
+	XCB_EVENT_MASK_FOCUS_CHANGE,
+	XCB_KEY_PRESS, XCB_KEY_RELEASE, XCB_BUTTON_PRESS, XCB_BUTTON_RELEASE, XCB_FOCUS_IN, XCB_FOCUS_OUT,
+	XCB_MAP_NOTIFY, XCB_UNMAP_NOTIFY,
+	XCB_EVENT_MASK_PROPERTY_CHANGE, XCB_ATOM_NONE, XCB_ATOM_ATOM, XCB_ATOM_INTEGER, XCB_ATOM_STRING, XCB_ATOM_PRIMARY, XCB_WINDOW_NONE,
+	XCB_TIME_CURRENT_TIME, XCB_ATOM_RESOURCE_MANAGER, XCB_CURSOR_NONE
 };
 
 use std::ops::{Deref, DerefMut};
@@ -351,5 +1322,5 @@ impl<T: ?Sized> Drop for MallocBox<T>
 }
 impl<T: ?Sized> Debug for MallocBox<T> where T: Debug
 {
-	fn fmt(&self, fmt: &mut Formatter) -> FmtResult { <T as Debug>::fmt(&self, fmt) }
+	fn fmt(&self, fmt: &mut Formatter) -> FmtResult { <T as Debug>::fmt(self, fmt) }
 }
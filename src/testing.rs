@@ -0,0 +1,261 @@
+//! Golden image tests on the headless backend
+//!
+//! A test launches the application, renders its first window at fixed virtual timestamps and compares the
+//! captured frame with a stored PNG. Set `APPFRAME_BLESS=1` to overwrite the stored images with the captured frames.
+
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error as IOError, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use png;
+use {EventDelegate, GUIApplication, Image, PixelFormat, RenderMode, Error};
+use headless::REFRESH_RATE;
+
+/// Environment variable that makes `GoldenTest` store captured frames instead of comparing
+pub const BLESS_ENV: &str = "APPFRAME_BLESS";
+
+#[derive(Debug)]
+pub enum GoldenError
+{
+    Io(IOError),
+    /// The application opened no window
+    NoWindow,
+    /// The frame could not be captured
+    Capture(Error),
+    /// The stored image does not exist. Run with `APPFRAME_BLESS=1` to create it
+    Missing(PathBuf),
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32), actual_path: PathBuf },
+    /// Some pixels differ by more than the tolerance
+    Mismatch { pixels: usize, max_difference: u8, actual_path: PathBuf, diff_path: PathBuf }
+}
+impl From<IOError> for GoldenError { fn from(e: IOError) -> Self { GoldenError::Io(e) } }
+impl From<Error> for GoldenError { fn from(e: Error) -> Self { GoldenError::Capture(e) } }
+impl Display for GoldenError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult
+    {
+        match *self
+        {
+            GoldenError::Io(ref e) => write!(fmt, "I/O error: {}", e),
+            GoldenError::NoWindow => write!(fmt, "No window has been opened"),
+            GoldenError::Capture(ref e) => write!(fmt, "Failed to capture the frame: {}", e),
+            GoldenError::Missing(ref p) => write!(fmt, "Golden image {} does not exist(set {}=1 to create)", p.display(), BLESS_ENV),
+            GoldenError::SizeMismatch { expected, actual, ref actual_path } => write!(fmt,
+                "Size mismatch: expected {}x{}, got {}x{}(written to {})",
+                expected.0, expected.1, actual.0, actual.1, actual_path.display()),
+            GoldenError::Mismatch { pixels, max_difference, ref actual_path, ref diff_path } => write!(fmt,
+                "{} pixels differ(max difference {}). Actual: {}, diff: {}",
+                pixels, max_difference, actual_path.display(), diff_path.display())
+        }
+    }
+}
+impl ::std::error::Error for GoldenError { fn description(&self) -> &str { "golden image mismatch" } }
+
+/// A golden image test
+pub struct GoldenTest { path: PathBuf, frames: u32, interval: Duration, tolerance: u8 }
+impl GoldenTest
+{
+    /// Compare with the PNG at `path`. Renders one frame with no tolerance by default
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self
+    {
+        GoldenTest
+        {
+            path: path.into(), frames: 1, interval: RenderMode::FixedRate(REFRESH_RATE).interval().unwrap(), tolerance: 0
+        }
+    }
+    /// Number of frames rendered before capturing
+    pub fn frames(&mut self, n: u32) -> &mut Self { self.frames = n; self }
+    /// Virtual time between frames. Frame `i` is rendered at `i * interval`
+    pub fn frame_interval(&mut self, d: Duration) -> &mut Self { self.interval = d; self }
+    /// Maximum difference allowed per channel
+    pub fn tolerance(&mut self, t: u8) -> &mut Self { self.tolerance = t; self }
+
+    /// Launch the application, render its first window and compare the captured frame
+    pub fn run<E: EventDelegate>(&self, delegate: E) -> Result<(), GoldenError>
+    {
+        let app = GUIApplication::launch(delegate);
+        let id = *app.windows().first().ok_or(GoldenError::NoWindow)?;
+        for i in 0 .. self.frames { app.render_at(id, self.interval * i); }
+        let r = app.capture(id);
+        self.compare(&r?)
+    }
+    /// Compare `image` with the stored image, or store it if blessing is requested
+    pub fn compare(&self, image: &Image) -> Result<(), GoldenError>
+    {
+        let actual = rgba(image);
+        if bless_requested()
+        {
+            if let Some(d) = self.path.parent() { fs::create_dir_all(d)?; }
+            return write_png(&self.path, &actual).map_err(From::from);
+        }
+        let expected = match read_png(&self.path)
+        {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Err(GoldenError::Missing(self.path.clone())),
+            r => r?
+        };
+        let actual_path = self.sibling("actual");
+        if (expected.width, expected.height) != (actual.width, actual.height)
+        {
+            write_png(&actual_path, &actual)?;
+            return Err(GoldenError::SizeMismatch
+            {
+                expected: (expected.width, expected.height), actual: (actual.width, actual.height), actual_path
+            });
+        }
+
+        let (mut pixels, mut max_difference) = (0, 0);
+        let mut diff = Image { data: Vec::with_capacity(actual.data.len()), width: actual.width, height: actual.height,
+            format: PixelFormat::Rgba8, has_alpha: true };
+        for (e, a) in expected.data.chunks(4).zip(actual.data.chunks(4))
+        {
+            let d = e.iter().zip(a).map(|(&e, &a)| e.abs_diff(a)).max().unwrap_or(0);
+            max_difference = max_difference.max(d);
+            if d > self.tolerance { pixels += 1; diff.data.extend_from_slice(&[255, 0, 0, 255]); }
+            else
+            {
+                // matching pixels are dimmed to make differences stand out
+                let l = ((e[0] as u32 * 3 + e[1] as u32 * 6 + e[2] as u32) / 40) as u8;
+                diff.data.extend_from_slice(&[l, l, l, 255]);
+            }
+        }
+        if pixels == 0 { return Ok(()); }
+        let diff_path = self.sibling("diff");
+        write_png(&actual_path, &actual)?; write_png(&diff_path, &diff)?;
+        Err(GoldenError::Mismatch { pixels, max_difference, actual_path, diff_path })
+    }
+
+    /// `name.png` -> `name.<suffix>.png`
+    fn sibling(&self, suffix: &str) -> PathBuf
+    {
+        let stem = self.path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        self.path.with_file_name(format!("{}.{}.png", stem, suffix))
+    }
+}
+
+fn bless_requested() -> bool { ::std::env::var_os(BLESS_ENV).is_some_and(|v| v != "0" && !v.is_empty()) }
+
+/// Read a PNG as 8-bit RGBA
+pub fn read_png<P: AsRef<Path>>(path: P) -> Result<Image, IOError>
+{
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+    if info.bit_depth != png::BitDepth::Eight
+    {
+        return Err(IOError::new(ErrorKind::InvalidData, "Only 8-bit PNGs are supported"));
+    }
+    let data = match info.color_type
+    {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => buf.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&l| vec![l, l, l, 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Indexed => return Err(IOError::new(ErrorKind::InvalidData, "Indexed PNGs are not supported"))
+    };
+    Ok(Image { width: info.width, height: info.height, format: PixelFormat::Rgba8, has_alpha: true, data })
+}
+/// The image itself if it is RGBA already
+fn rgba(image: &Image) -> Cow<'_, Image>
+{
+    if image.format == PixelFormat::Rgba8 { Cow::Borrowed(image) } else { Cow::Owned(image.clone().into_rgba()) }
+}
+/// Write an image as an 8-bit RGBA PNG
+pub fn write_png<P: AsRef<Path>>(path: P, image: &Image) -> Result<(), IOError>
+{
+    let image = rgba(image);
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), image.width, image.height);
+    encoder.set_color(png::ColorType::RGBA); encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&image.data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use {GUIApplication, NativeWindow, NativeWindowBuilder, WindowBuilder, WindowEventDelegate, Window, View, FrameInfo};
+
+    const SIZE: (u32, u32) = (16, 8);
+    /// A gradient shifted by the frame number
+    fn gradient(frame: u64) -> Image
+    {
+        let data = (0 .. SIZE.1).flat_map(|y| (0 .. SIZE.0).map(move |x| (x, y)))
+            .flat_map(|(x, y)| vec![(x * 8) as u8 + frame as u8, (y * 16) as u8, 128, 255]).collect();
+        Image { width: SIZE.0, height: SIZE.1, format: PixelFormat::Rgba8, has_alpha: false, data }
+    }
+
+    struct App(RefCell<Option<Rc<Painter>>>);
+    impl EventDelegate for App
+    {
+        fn postinit(&self, app: &Rc<GUIApplication<Self>>)
+        {
+            let p = Rc::new(Painter(RefCell::new(None)));
+            let w = NativeWindowBuilder::new(SIZE.0 as _, SIZE.1 as _, "golden").render_mode(RenderMode::OnDemand)
+                .create(app, &p).unwrap();
+            w.show();
+            *p.0.borrow_mut() = Some(w);
+            *self.0.borrow_mut() = Some(p);
+        }
+    }
+    struct Painter(RefCell<Option<NativeWindow<Painter>>>);
+    impl WindowEventDelegate for Painter
+    {
+        type ClientDelegate = App;
+        fn render_frame(&self, frame: &FrameInfo)
+        {
+            let w = self.0.borrow();
+            let w = w.as_ref().unwrap();
+            w.pixel_buffer().data_mut().copy_from_slice(&gradient(frame.frame_number).data);
+            w.present(&[]).unwrap();
+        }
+    }
+
+    fn golden_path(name: &str) -> PathBuf
+    {
+        let dir = ::std::env::temp_dir().join(format!("appframe-golden-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn matching_frames_pass()
+    {
+        let path = golden_path("match.png");
+        write_png(&path, &gradient(2)).unwrap();
+        GoldenTest::new(&path).frames(3).run(App(RefCell::new(None))).unwrap();
+    }
+    #[test]
+    fn differences_beyond_the_tolerance_fail()
+    {
+        let path = golden_path("mismatch.png");
+        let mut expected = gradient(0);
+        expected.data[4] += 10;
+        write_png(&path, &expected).unwrap();
+        match GoldenTest::new(&path).run(App(RefCell::new(None)))
+        {
+            Err(GoldenError::Mismatch { pixels, max_difference, actual_path, diff_path }) =>
+            {
+                assert_eq!((pixels, max_difference), (1, 10));
+                assert_eq!(read_png(&actual_path).unwrap().data, gradient(0).data);
+                assert_eq!(read_png(&diff_path).unwrap().rgba_at(1, 0), [255, 0, 0, 255]);
+            },
+            r => panic!("unexpected result: {:?}", r)
+        }
+        GoldenTest::new(&path).tolerance(10).run(App(RefCell::new(None))).unwrap();
+    }
+    #[test]
+    fn missing_images_are_reported()
+    {
+        let path = golden_path("missing.png");
+        match GoldenTest::new(&path).run(App(RefCell::new(None)))
+        {
+            Err(GoldenError::Missing(p)) => assert_eq!(p, path),
+            r => panic!("unexpected result: {:?}", r)
+        }
+    }
+}
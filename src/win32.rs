@@ -2,19 +2,19 @@
 
 #![allow(unused_imports)]
 
-#[cfg(all(feature = "with_bedrock", not(feature = "manual_rendering")))]
+#[cfg(feature = "with_bedrock")]
 extern crate comdrive;
-#[cfg(all(feature = "with_bedrock", not(feature = "manual_rendering")))]
+#[cfg(feature = "with_bedrock")]
 use self::comdrive::*;
 
 use std::io::{Result as IOResult, Error as IOError};
 use std::mem::{uninitialized, zeroed, size_of};
 use std::ptr::{null_mut, null};
 use std::ffi::{CString, /*CStr*/};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell, RefMut};
 use winapi::ctypes::c_char;
 use winapi::shared::basetsd::LONG_PTR;
-use winapi::shared::windef::{HWND, RECT};
+use winapi::shared::windef::{HWND, RECT, POINT, HCURSOR};
 use winapi::shared::minwindef::*;
 use winapi::shared::rpc::RPC_STATUS;
 use winapi::shared::rpcdce::{UUID, RPC_CSTR};
@@ -26,17 +26,22 @@ use self::{
     WNDCLASSEXA as WNDCLASSEX, SetWindowLongPtrA as SetWindowLongPtr, GetWindowLongPtrA as GetWindowLongPtr
 };
 use winapi::um::libloaderapi::GetModuleHandleA as GetModuleHandle;
+use winapi::um::wingdi::{SetDIBitsToDevice, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, CreateDIBSection, CreateBitmap};
+use winapi::um::wingdi::{CreateCompatibleDC, CreateCompatibleBitmap, SelectObject, BitBlt, GetDIBits, DeleteObject, DeleteDC, SRCCOPY};
 use winapi::um::combaseapi::{CoInitializeEx, CoUninitialize};
 use winapi::um::objbase::COINIT_MULTITHREADED;
 use std::rc::*;
-use {EventDelegate, WindowEventDelegate, GUIApplicationRunner, Window, View, WindowBuilder};
+use {EventDelegate, WindowEventDelegate, GUIApplicationRunner, Window, View, WindowBuilder, RenderMode, FrameInfo};
+use {WindowEvent, MouseButton, ScrollPhase, ScrollSource, PixelBuffer, PixelFormat, Rect, Image, Error, Result, CursorIcon};
+use frame::FrameClock;
+use std::time::Instant;
 
 #[cfg(feature = "with_bedrock")] use bedrock as fe;
 
 pub struct GUIApplication<E: EventDelegate>(Option<E>);
 impl<E: EventDelegate> GUIApplicationRunner<E> for GUIApplication<E>
 {
-    fn run(delegate: E) -> i32
+    fn try_run(delegate: E) -> Result<i32>
     {
         unsafe { CoInitializeEx(null_mut(), COINIT_MULTITHREADED); }
         let app = Rc::new(GUIApplication(Some(delegate)));
@@ -47,7 +52,7 @@ impl<E: EventDelegate> GUIApplicationRunner<E> for GUIApplication<E>
         {
             unsafe { TranslateMessage(&mut msg); DispatchMessage(&mut msg); }
         }
-        return msg.wParam as _;
+        return Ok(msg.wParam as _);
     }
     fn event_delegate(&self) -> &E { self.0.as_ref().unwrap() }
 }
@@ -66,18 +71,160 @@ impl<E: EventDelegate> ::BedrockRenderingServer for GUIApplication<E>
         adapter.win32_presentation_support(rendered_qf)
     }
     fn create_surface<WE: WindowEventDelegate>(&self, w: &NativeView<WE>, instance: &fe::Instance)
-        -> fe::Result<fe::Surface>
+        -> Result<fe::Surface>
     {
         fe::Surface::new_win32(&instance, unsafe { GetModuleHandle(null_mut()) }, w.handle)
+            .map_err(|e| Error::SurfaceCreation(format!("{:?}", e)))
     }
 }
 
-pub struct CallbackSet<WE: WindowEventDelegate> { w: Weak<WE> }
-pub struct NativeWindow<WE: WindowEventDelegate> { handle: HWND, controller: NativeWindowController<WE> }
+pub struct CallbackSet<WE: WindowEventDelegate>
+{
+    w: Weak<WE>, render_mode: Cell<RenderMode>, timing: Rc<FrameTiming>,
+    /// Shown over the client area on WM_SETCURSOR. Destroyed when replaced if `cursor_owned`
+    cursor: Cell<HCURSOR>, cursor_owned: Cell<bool>, cursor_visible: Cell<bool>
+}
+impl<WE: WindowEventDelegate> CallbackSet<WE>
+{
+    fn new(w: Weak<WE>, timing: Rc<FrameTiming>) -> Self
+    {
+        CallbackSet
+        {
+            w, render_mode: Cell::new(RenderMode::OnDemand), timing,
+            cursor: Cell::new(unsafe { LoadCursor(null_mut(), IDC_ARROW as _) }), cursor_owned: Cell::new(false),
+            cursor_visible: Cell::new(true)
+        }
+    }
+    fn replace_cursor(&self, c: HCURSOR, owned: bool)
+    {
+        let old = self.cursor.replace(c);
+        if self.cursor_owned.replace(owned) { unsafe { DestroyCursor(old); } }
+    }
+    fn current_cursor(&self) -> HCURSOR { if self.cursor_visible.get() { self.cursor.get() } else { null_mut() } }
+}
+impl<WE: WindowEventDelegate> Drop for CallbackSet<WE>
+{
+    fn drop(&mut self) { if self.cursor_owned.get() { unsafe { DestroyCursor(self.cursor.get()); } } }
+}
+/// Frame counter shared by WM_PAINT/WM_TIMER and the animation timer
+pub struct FrameTiming { clock: FrameClock, epoch: Instant }
+impl FrameTiming
+{
+    fn new() -> Self { FrameTiming { clock: FrameClock::new(), epoch: Instant::now() } }
+    fn tick(&self, dirty_rects: Vec<Rect>) -> FrameInfo { self.clock.tick(self.epoch.elapsed(), None, dirty_rects) }
+}
+pub struct NativeWindow<WE: WindowEventDelegate>
+{
+    handle: HWND, controller: NativeWindowController<WE>, pixels: RefCell<PixelBuffer>
+}
 impl<WE: WindowEventDelegate> Window for NativeWindow<WE> {
     fn show(&self) { unsafe { ShowWindow(self.handle, SW_SHOWNORMAL); } }
     #[cfg(feature = "with_bedrock")]
     fn mark_dirty(&self) { unsafe { InvalidateRect(self.handle, null(), false as _); } }
+    /// Accumulated by the update region of the window
+    fn mark_dirty_rect(&self, r: Rect)
+    {
+        let rc = RECT { left: r.x, top: r.y, right: r.right(), bottom: r.bottom() };
+        unsafe { InvalidateRect(self.handle, &rc, false as _); }
+    }
+    fn set_render_mode(&self, mode: RenderMode) { self.controller.apply_render_mode(self.handle, mode); }
+    fn capture(&self) -> Result<Image>
+    {
+        let (w, h) = self.size();
+        let mut bi: BITMAPINFO = unsafe { zeroed() };
+        bi.bmiHeader = BITMAPINFOHEADER
+        {
+            biSize: size_of::<BITMAPINFOHEADER>() as _, biWidth: w as _, biHeight: -(h as i32),
+            biPlanes: 1, biBitCount: 32, biCompression: BI_RGB, .. unsafe { zeroed() }
+        };
+        let mut data = vec![0u8; w as usize * h as usize * 4];
+        let copied = unsafe
+        {
+            let dc = GetDC(self.handle);
+            if dc.is_null() { return Err(IOError::last_os_error().into()); }
+            let (memdc, bmp) = (CreateCompatibleDC(dc), CreateCompatibleBitmap(dc, w as _, h as _));
+            let old = SelectObject(memdc, bmp as _);
+            let r = BitBlt(memdc, 0, 0, w as _, h as _, dc, 0, 0, SRCCOPY) != 0 &&
+                GetDIBits(memdc, bmp, 0, h as _, data.as_mut_ptr() as _, &mut bi, DIB_RGB_COLORS) != 0;
+            SelectObject(memdc, old); DeleteObject(bmp as _); DeleteDC(memdc); ReleaseDC(self.handle, dc);
+            r
+        };
+        if !copied { return Err(IOError::last_os_error().into()); }
+        // the fourth byte of 32-bit DIBs is unused
+        for p in data.chunks_mut(4) { p[3] = 0xff; }
+        Ok(Image { width: w as _, height: h as _, format: PixelFormat::Bgra8, has_alpha: false, data })
+    }
+    fn set_cursor(&self, icon: CursorIcon)
+    {
+        let id = match icon
+        {
+            CursorIcon::Default => IDC_ARROW, CursorIcon::Text => IDC_IBEAM, CursorIcon::Hand | CursorIcon::Grab => IDC_HAND,
+            CursorIcon::Crosshair => IDC_CROSS, CursorIcon::Move | CursorIcon::Grabbing => IDC_SIZEALL,
+            CursorIcon::Wait => IDC_WAIT, CursorIcon::Progress => IDC_APPSTARTING, CursorIcon::Help => IDC_HELP,
+            CursorIcon::NotAllowed => IDC_NO, CursorIcon::ResizeNS => IDC_SIZENS, CursorIcon::ResizeEW => IDC_SIZEWE,
+            CursorIcon::ResizeNESW => IDC_SIZENESW, CursorIcon::ResizeNWSE => IDC_SIZENWSE
+        };
+        self.controller.callbox.replace_cursor(unsafe { LoadCursor(null_mut(), id as _) }, false);
+        self.refresh_cursor();
+    }
+    fn set_custom_cursor(&self, image: &Image, hotspot: (u32, u32)) -> Result<()>
+    {
+        let (w, h) = (image.width, image.height);
+        // a hotspot outside the image fails CreateIconIndirect
+        let hotspot = (hotspot.0.min(w.saturating_sub(1)), hotspot.1.min(h.saturating_sub(1)));
+        let mut bi: BITMAPINFO = unsafe { zeroed() };
+        bi.bmiHeader = BITMAPINFOHEADER
+        {
+            biSize: size_of::<BITMAPINFOHEADER>() as _, biWidth: w as _, biHeight: -(h as i32),
+            biPlanes: 1, biBitCount: 32, biCompression: BI_RGB, .. unsafe { zeroed() }
+        };
+        let cursor = unsafe
+        {
+            let mut bits = null_mut();
+            let color = CreateDIBSection(null_mut(), &bi, DIB_RGB_COLORS, &mut bits, null_mut(), 0);
+            if color.is_null() { return Err(IOError::last_os_error().into()); }
+            let dst = ::std::slice::from_raw_parts_mut(bits as *mut u8, w as usize * h as usize * 4);
+            for (i, p) in dst.chunks_mut(4).enumerate()
+            {
+                let [r, g, b, a] = image.rgba_at(i as u32 % w, i as u32 / w);
+                let a = if image.has_alpha { a } else { 0xff };
+                // cursors take straight alpha
+                let s = |c: u8| if a == 0 { 0 } else { (c as u32 * 255 / a as u32).min(255) as u8 };
+                p.copy_from_slice(&[s(b), s(g), s(r), a]);
+            }
+            let mask = CreateBitmap(w as _, h as _, 1, 1, null());
+            let mut ii = ICONINFO { fIcon: FALSE, xHotspot: hotspot.0, yHotspot: hotspot.1, hbmMask: mask, hbmColor: color };
+            let c = CreateIconIndirect(&mut ii);
+            DeleteObject(color as _); DeleteObject(mask as _);
+            c
+        };
+        if cursor.is_null() { return Err(IOError::last_os_error().into()); }
+        self.controller.callbox.replace_cursor(cursor, true);
+        self.refresh_cursor();
+        Ok(())
+    }
+    fn set_cursor_visible(&self, visible: bool)
+    {
+        self.controller.callbox.cursor_visible.set(visible);
+        self.refresh_cursor();
+    }
+}
+impl<WE: WindowEventDelegate> NativeWindow<WE>
+{
+    /// Show the cursor now if the pointer is in the client area, instead of at the next WM_SETCURSOR
+    fn refresh_cursor(&self)
+    {
+        unsafe
+        {
+            let (mut p, mut rc) = (POINT { x: 0, y: 0 }, zeroed::<RECT>());
+            if GetCursorPos(&mut p) == 0 || WindowFromPoint(p) != self.handle { return; }
+            ScreenToClient(self.handle, &mut p); GetClientRect(self.handle, &mut rc);
+            if p.x >= rc.left && p.x < rc.right && p.y >= rc.top && p.y < rc.bottom
+            {
+                SetCursor(self.controller.callbox.current_cursor());
+            }
+        }
+    }
 }
 pub type NativeView<WE> = NativeWindow<WE>;
 impl<WE: WindowEventDelegate> View for NativeView<WE>
@@ -88,11 +235,40 @@ impl<WE: WindowEventDelegate> View for NativeView<WE>
         unsafe { GetClientRect(self.handle, &mut r); }
         return ((r.right - r.left) as _, (r.bottom - r.top) as _);
     }
+    /// Pixels are in the DIB layout(BGRA)
+    fn pixel_buffer(&self) -> RefMut<'_, PixelBuffer>
+    {
+        let mut b = self.pixels.borrow_mut();
+        b.fit(self.width() as _, self.height() as _); b
+    }
+    fn present(&self, damage: &[Rect]) -> Result<()>
+    {
+        let b = self.pixel_buffer();
+        let dc = unsafe { GetDC(self.handle) };
+        if dc.is_null() { return Err(IOError::last_os_error().into()); }
+        for r in b.clip_damage(damage)
+        {
+            // a top-down DIB made of the damaged rows
+            let mut bi: BITMAPINFO = unsafe { zeroed() };
+            bi.bmiHeader = BITMAPINFOHEADER
+            {
+                biSize: size_of::<BITMAPINFOHEADER>() as _, biWidth: b.width() as _, biHeight: -(r.height as i32),
+                biPlanes: 1, biBitCount: 32, biCompression: BI_RGB, .. unsafe { zeroed() }
+            };
+            unsafe
+            {
+                SetDIBitsToDevice(dc, r.x, r.y, r.width, r.height, r.x, 0, 0, r.height,
+                    b.data()[r.y as usize * b.stride()..].as_ptr() as _, &bi, DIB_RGB_COLORS);
+            }
+        }
+        unsafe { ReleaseDC(self.handle, dc); }
+        Ok(())
+    }
 }
 
 pub struct NativeWindowBuilder<'c>
 {
-    style: DWORD, cstyle: DWORD, width: u16, height: u16, caption: &'c str
+    style: DWORD, cstyle: DWORD, width: u16, height: u16, caption: &'c str, render_mode: RenderMode
 }
 impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
 {
@@ -101,7 +277,7 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
         NativeWindowBuilder
         {
             style: WS_CAPTION | WS_BORDER | WS_SYSMENU | WS_MINIMIZEBOX | WS_MAXIMIZEBOX | WS_THICKFRAME,
-            cstyle: CS_OWNDC, width, height, caption
+            cstyle: CS_OWNDC, width, height, caption, render_mode: RenderMode::default()
         }
     }
     fn closable(&mut self, c: bool) -> &mut Self
@@ -118,9 +294,10 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
         println!("** Transparent Window support is incomplete for windows **");
         self
     }
+    fn render_mode(&mut self, mode: RenderMode) -> &mut Self { self.render_mode = mode; self }
 
     fn create<WE: WindowEventDelegate>(&self, _server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>>
+        -> Result<NativeWindow<WE>>
     {
         let cname = UniqueString::generate();
         let wcap = CString::new(self.caption).unwrap();
@@ -133,7 +310,7 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
             .. unsafe { zeroed() }
         };
         let atom = unsafe { RegisterClassEx(&wc) };
-        if atom == 0 { return Err(IOError::last_os_error()); }
+        if atom == 0 { return Err(IOError::last_os_error().into()); }
         let r = self.adjusted_window_rect();
         let hw = unsafe
         {
@@ -141,15 +318,17 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
                 CW_USEDEFAULT, CW_USEDEFAULT, r.right - r.left, r.bottom - r.top,
                 null_mut(), null_mut(), wc.hInstance, null_mut())
         };
-        if hw.is_null() { return Err(IOError::last_os_error()); }
+        if hw.is_null() { return Err(IOError::last_os_error().into()); }
 
         let controller = NativeWindowController::new(event)?;
         unsafe { SetWindowLongPtr(hw, GWL_USERDATA, (&*controller.callbox) as *const _ as LONG_PTR); }
-        return Ok(NativeWindow { handle: hw, controller });
+        controller.apply_render_mode(hw, self.render_mode);
+        let pixels = RefCell::new(PixelBuffer::new(self.width as _, self.height as _, PixelFormat::Bgra8));
+        return Ok(NativeWindow { handle: hw, controller, pixels });
     }
     #[cfg(feature = "with_bedrock")] #[allow(unused_mut)]
     fn create_renderable<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
-        -> IOResult<NativeWindow<WE>> where WE::ClientDelegate: 'static
+        -> Result<NativeWindow<WE>> where WE::ClientDelegate: 'static
     {
         let mut w = self.create(server, event)?;
         w.controller.callbox.w.upgrade().unwrap().init_view(&w);
@@ -174,26 +353,46 @@ extern
     fn RpcStringFreeA(string: *mut RPC_CSTR) -> RPC_STATUS;
 }
 
+/// Timer ID used for `RenderMode::FixedRate`
+const FIXED_RATE_TIMER_ID: usize = 1;
+
 struct NativeWindowController<WE: WindowEventDelegate> {
     callbox: Box<CallbackSet<WE>>,
-    #[cfg(all(feature = "with_bedrock", not(feature = "manual_rendering")))]
-    _autotimer: (uianimation::Timer, UpdateTimerHandlerCell)
+    #[cfg(feature = "with_bedrock")]
+    autotimer: (RefCell<uianimation::Timer>, UpdateTimerHandlerCell)
 }
 impl<WE: WindowEventDelegate> NativeWindowController<WE> {
-    #[cfg(all(feature = "with_bedrock", not(feature = "manual_rendering")))]
+    #[cfg(feature = "with_bedrock")]
     pub fn new(event: &Rc<WE>) -> IOResult<Self> {
         let mut timer = uianimation::Timer::new()?;
-        let update_handler = UpdateTimerHandlerCell(UpdateTimerHandler::create(event));
+        let timing = Rc::new(FrameTiming::new());
+        let update_handler = UpdateTimerHandlerCell(UpdateTimerHandler::create(event, &timing));
         timer.set_update_handler(Some(&update_handler), uianimation::IdleBehavior::Disable)?;
-        timer.enable()?;
         return Ok(NativeWindowController {
-            callbox: Box::new(CallbackSet { w: Rc::downgrade(event) }),
-            _autotimer: (timer, update_handler)
+            callbox: Box::new(CallbackSet::new(Rc::downgrade(event), timing)),
+            autotimer: (RefCell::new(timer), update_handler)
         });
     }
-    #[cfg(any(not(feature = "with_bedrock"), feature = "manual_rendering"))]
+    #[cfg(not(feature = "with_bedrock"))]
     pub fn new(event: &Rc<WE>) -> IOResult<Self> {
-        Ok(NativeWindowController { callbox: Box::new(CallbackSet { w: Rc::downgrade(event) }) })
+        Ok(NativeWindowController {
+            callbox: Box::new(CallbackSet::new(Rc::downgrade(event), Rc::new(FrameTiming::new())))
+        })
+    }
+    /// Switch the frame source: animation timer for Continuous, WM_TIMER for FixedRate, WM_PAINT only for OnDemand
+    fn apply_render_mode(&self, hwnd: HWND, mode: RenderMode) {
+        let old = self.callbox.render_mode.replace(mode);
+        if let RenderMode::FixedRate(_) = old { unsafe { KillTimer(hwnd, FIXED_RATE_TIMER_ID); } }
+        #[cfg(feature = "with_bedrock")] {
+            let mut timer = self.autotimer.0.borrow_mut();
+            let r = if mode == RenderMode::Continuous { timer.enable() } else { timer.disable() };
+            if let Err(e) = r { println!("Failed to switch the animation timer: {:?}", e); }
+        }
+        if let Some(interval) = mode.interval() {
+            let ms = interval.as_secs() as u32 * 1000 + interval.subsec_nanos() / 1_000_000;
+            unsafe { SetTimer(hwnd, FIXED_RATE_TIMER_ID, ms.max(USER_TIMER_MINIMUM), None); }
+        }
+        unsafe { InvalidateRect(hwnd, null(), false as _); }
     }
 
     unsafe fn extract_callset_ref<'a>(h: HWND) -> &'a CallbackSet<WE> {
@@ -202,13 +401,20 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE> {
     extern "system" fn wndproc(hwnd: HWND, msg: UINT, wp: WPARAM, lp: LPARAM) -> LRESULT {
         match msg {
             WM_DESTROY => unsafe { PostQuitMessage(0); return 0; },
-            #[cfg(all(feature = "with_bedrock", feature = "manual_rendering"))]
+            WM_TIMER if wp == FIXED_RATE_TIMER_ID => {
+                let cs = unsafe { Self::extract_callset_ref(hwnd) };
+                if let Some(cb) = cs.w.upgrade() { cb.render_frame(&cs.timing.tick(Vec::new())); }
+                return 0;
+            },
             WM_PAINT => {
-                if let Some(cb) = unsafe { Self::extract_callset_ref(hwnd).w.upgrade() } {
+                let cs = unsafe { Self::extract_callset_ref(hwnd) };
+                if let Some(cb) = cs.w.upgrade() {
                     unsafe {
                         let mut ps = uninitialized();
                         BeginPaint(hwnd, &mut ps);
-                        cb.render();
+                        let p = ps.rcPaint;
+                        let dirty = Rect::new(p.left, p.top, (p.right - p.left) as _, (p.bottom - p.top) as _);
+                        cb.render_frame(&cs.timing.tick(if dirty.is_empty() { Vec::new() } else { vec![dirty] }));
                         EndPaint(hwnd, &ps);
                     }
                 }
@@ -217,29 +423,71 @@ impl<WE: WindowEventDelegate> NativeWindowController<WE> {
             WM_SIZE => if let Some(cb) = unsafe { Self::extract_callset_ref(hwnd).w.upgrade() } {
                 cb.resize(LOWORD(lp as _) as _, HIWORD(lp as _) as _, false);
             },
+            // DefWindowProc destroys the window(and quits the application) on WM_CLOSE
+            WM_CLOSE => if !unsafe { Self::dispatch(hwnd, WindowEvent::CloseRequested) } { return 0; },
+            WM_SETFOCUS | WM_KILLFOCUS => unsafe { Self::dispatch(hwnd, WindowEvent::Focus(msg == WM_SETFOCUS)); },
+            // the class cursor applies only until the callback set is attached
+            WM_SETCURSOR if LOWORD(lp as _) == HTCLIENT as _ => {
+                if let Some(cs) = unsafe { (GetWindowLongPtr(hwnd, GWL_USERDATA) as *const CallbackSet<WE>).as_ref() } {
+                    unsafe { SetCursor(cs.current_cursor()); }
+                    return TRUE as _;
+                }
+            },
+            WM_KEYDOWN => unsafe { Self::dispatch(hwnd, WindowEvent::KeyDown(wp as _)); },
+            WM_KEYUP => unsafe { Self::dispatch(hwnd, WindowEvent::KeyUp(wp as _)); },
+            WM_MOUSEMOVE => unsafe {
+                let (x, y) = Self::client_pos(lp);
+                Self::dispatch(hwnd, WindowEvent::PointerMoved { x, y });
+            },
+            WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONUP => {
+                let button = match msg {
+                    WM_LBUTTONDOWN | WM_LBUTTONUP => MouseButton::Left,
+                    WM_RBUTTONDOWN | WM_RBUTTONUP => MouseButton::Right,
+                    _ => MouseButton::Middle
+                };
+                let pressed = msg == WM_LBUTTONDOWN || msg == WM_RBUTTONDOWN || msg == WM_MBUTTONDOWN;
+                let (x, y) = Self::client_pos(lp);
+                unsafe { Self::dispatch(hwnd, WindowEvent::PointerButton { button, pressed, x, y }); }
+                return 0;
+            },
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+                let d = HIWORD(wp as _) as i16 as f32 / WHEEL_DELTA as f32;
+                let (dx, dy) = if msg == WM_MOUSEWHEEL { (0.0, d) } else { (d, 0.0) };
+                let e = WindowEvent::Scroll { dx, dy, phase: ScrollPhase::Moved, source: ScrollSource::Wheel };
+                unsafe { Self::dispatch(hwnd, e); }
+                return 0;
+            },
             _ => (/* nothing to do */)
         }
         return unsafe { DefWindowProc(hwnd, msg, wp, lp) };
     }
+    /// Messages may arrive before the callback set is attached
+    unsafe fn dispatch(hwnd: HWND, e: WindowEvent) -> bool {
+        (GetWindowLongPtr(hwnd, GWL_USERDATA) as *const CallbackSet<WE>).as_ref().and_then(|cs| cs.w.upgrade())
+            .is_none_or(|cb| e.dispatch_to(&*cb))
+    }
+    fn client_pos(lp: LPARAM) -> (f32, f32) {
+        (LOWORD(lp as _) as i16 as f32, HIWORD(lp as _) as i16 as f32)
+    }
 }
 
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 use winapi::shared::winerror::*;
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 use winapi::ctypes::c_void;
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 use winapi::shared::guiddef::REFIID;
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 use winapi::um::unknwnbase::IUnknown;
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 use winapi::Interface;
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 #[repr(C)] pub struct UpdateTimerHandler<WE: WindowEventDelegate>
 {
     vtbl: *const uianimation::IUIAnimationTimerUpdateHandlerVtbl, refcount: ULONG,
-    client_handler: Option<TimerClientEventHandler>, callback: Weak<WE>
+    client_handler: Option<TimerClientEventHandler>, callback: Weak<WE>, timing: Rc<FrameTiming>
 }
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 impl<WE: WindowEventDelegate> UpdateTimerHandler<WE>
 {
     const UPDATE_TIMER_HANDLER_VTBL: &'static uianimation::IUIAnimationTimerUpdateHandlerVtbl =
@@ -251,11 +499,12 @@ impl<WE: WindowEventDelegate> UpdateTimerHandler<WE>
             ClearTimerClientEventHandler: Self::clear_timer_client_event_handler
         };
     
-    pub fn create(callback: &Rc<WE>) -> *mut uianimation::IUIAnimationTimerUpdateHandler
+    pub fn create(callback: &Rc<WE>, timing: &Rc<FrameTiming>) -> *mut uianimation::IUIAnimationTimerUpdateHandler
     {
         Box::into_raw(Box::new(UpdateTimerHandler
         {
-            vtbl: Self::UPDATE_TIMER_HANDLER_VTBL, refcount: 1, client_handler: None, callback: Rc::downgrade(callback)
+            vtbl: Self::UPDATE_TIMER_HANDLER_VTBL, refcount: 1, client_handler: None, callback: Rc::downgrade(callback),
+            timing: timing.clone()
         })) as _
     }
     unsafe fn refptr<'a>(ptr: *const uianimation::IUIAnimationTimerUpdateHandler) -> &'a Self { &*(ptr as *const Self) }
@@ -291,7 +540,8 @@ impl<WE: WindowEventDelegate> UpdateTimerHandler<WE>
     extern "system" fn on_update(this: *mut uianimation::IUIAnimationTimerUpdateHandler,
         _time: uianimation::Seconds, result: *mut uianimation::UpdateResult) -> HRESULT
     {
-        if let Some(e) = unsafe { Self::refptr(this).callback.upgrade() } { e.render(); }
+        let h = unsafe { Self::refptr(this) };
+        if let Some(e) = h.callback.upgrade() { e.render_frame(&h.timing.tick(Vec::new())); }
         // println!("Update: {}", time);
         unsafe { *result = uianimation::UpdateResult::NoChange; }
         S_OK
@@ -312,18 +562,18 @@ impl<WE: WindowEventDelegate> UpdateTimerHandler<WE>
         unsafe { Self::refmut(this).client_handler = None; S_OK }
     }
 }
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 pub struct UpdateTimerHandlerCell(*mut uianimation::IUIAnimationTimerUpdateHandler);
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 impl Drop for UpdateTimerHandlerCell { fn drop(&mut self) { unsafe { (*self.0).Release(); } } }
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 unsafe impl AsRawHandle<uianimation::IUIAnimationTimerUpdateHandler> for UpdateTimerHandlerCell
 {
     fn as_raw_handle(&self) -> *mut uianimation::IUIAnimationTimerUpdateHandler { self.0 }
 }
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 pub struct TimerClientEventHandler(*mut uianimation::IUIAnimationTimerClientEventHandler);
-#[cfg(feature = "with_bedrock")] #[cfg(not(feature = "manual_rendering"))]
+#[cfg(feature = "with_bedrock")]
 impl Drop for TimerClientEventHandler { fn drop(&mut self) { unsafe { (*self.0).Release(); } } }
 
 // use std::str::Utf8Error;
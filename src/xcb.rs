@@ -2,11 +2,27 @@
 
 use rxcb; use rxcb::Event;
 use std::rc::*;
-use {GUIApplicationRunner, Window, WindowBuilder, EventDelegate};
-#[cfg(feature = "with_ferrite")] use ferrite as fe;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use {GUIApplicationRunner, Window, View, WindowBuilder, EventDelegate, WindowEventDelegate, RenderMode};
+use {WindowEvent, MouseButton, ScrollPhase, ScrollSource, PixelBuffer, PixelFormat, Rect, Image};
+use frame::{FrameSchedule, FrameClock, FrameInfo, Pacing};
+use recording::{self, Recorder, Replay, Record, RecordedEvent};
+use automation::{self, AutomationServer, Command, Response, WindowInfo};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+#[cfg(feature = "with_bedrock")] use bedrock as br;
 use std::io::Result as IOResult;
+use {Error, Result, Clipboard, MimeData, ReceiveCallback, DropEffect, DropData, DragData, CursorIcon, GrabMode};
+use clipboard::{Selection, SelectionBackend};
+use xselection::Selections;
+use xdnd::{self, Xdnd};
+use xim::{Xim, ImeEvent};
+use xcursor;
+use xinput::{XInput, XIEvent};
 
-/// 透明を利用したい場合は32にする
+/// Depth of opaque windows. Transparent windows use a 32-bit ARGB visual
 pub const BITDEPTH: u32 = 24;
 
 pub struct GUIApplication<E: EventDelegate>
@@ -14,92 +30,1048 @@ pub struct GUIApplication<E: EventDelegate>
 	srv: Rc<rxcb::Connection>, dg: E, root_id: rxcb::WindowID,
 	wm_protocols: rxcb::Atom, wm_delete_window: rxcb::Atom,
 	desired_visualid: rxcb::VisualID, colormap: rxcb::Colormap,
-	action_atoms: ActionAtoms
+	/// Layout of pixels of the visual in ZPixmap images
+	pixel_format: PixelFormat,
+	/// Visual, colormap and pixel layout of transparent windows. None if the server has no 32-bit TrueColor visual
+	argb_visual: Option<(rxcb::VisualID, rxcb::Colormap, PixelFormat)>,
+	action_atoms: ActionAtoms, windows: RefCell<HashMap<rxcb::WindowID, Rc<WindowEntry>>>, epoch: Instant,
+	/// CLOCK_MONOTONIC at `epoch` in microseconds(the time base of Present's UST)
+	epoch_ust: u64, present: Option<rxcb::PresentExtension>,
+	/// MIT-SHM is usable(cleared when the server cannot attach segments)
+	shm: Cell<bool>, shm_ext: Option<rxcb::ShmExtension>, composite: bool,
+	/// Windows in creation order, indexed by recordings
+	created: RefCell<Vec<rxcb::WindowID>>, recorder: RefCell<Option<Recorder>>,
+	/// Live input is dropped while a recording is replayed
+	replaying: Cell<bool>,
+	automation: RefCell<Option<AutomationServer>>, selections: Selections,
+	xdnd: Xdnd, drop_session: RefCell<Option<DropSession>>, pending_drop: RefCell<Option<PendingDrop>>,
+	drag_source: RefCell<Option<xdnd::DragSource>>,
+	/// None if no input method server is running
+	xim: Option<Xim>,
+	/// Cursors of icons, created on first use
+	cursor_theme: xcursor::Theme, cursors: RefCell<HashMap<CursorIcon, rxcb::CursorID>>, blank_cursor: Cell<Option<rxcb::CursorID>>,
+	/// None if RENDER cannot make ARGB cursors
+	argb_format: Option<rxcb::PictFormat>,
+	/// None if the server lacks XInput 2
+	xinput: Option<XInput>, pointer_grab: Cell<Option<(rxcb::WindowID, GrabMode)>>
 }
 impl<E: EventDelegate> GUIApplicationRunner<E> for GUIApplication<E>
 {
-    fn run(_appname: &str, delegate: E) -> i32
+    fn try_run(delegate: E) -> Result<i32>
 	{
-		let srv = rxcb::Connection::new::<str>(None).expect("Failed to connect to X11 server");
-		let (visualid, colormap, root_id);
+		let display_error = || Error::DisplayConnection(::std::env::var("DISPLAY").ok());
+		let srv = rxcb::Connection::new::<str>(None).ok_or_else(display_error)?;
+		let (visualid, pixel_format, colormap, argb_visual, root_id);
 		{
-			let scrn = srv.setup().iter_roots().next().expect("There is no available screen");
-			let depth = scrn.iter_allowed_depths().find(|x| x.depth() == BITDEPTH as _).expect("There is no 32bpp support");
-			let visual = depth.iter_visuals().find(|x| x.is_truecolor()).expect("There is no TrueColor support");
+			let scrn = srv.setup().iter_roots().next().ok_or_else(display_error)?;
+			let lsb_first = srv.setup().is_image_lsb_first();
+			// PixelBuffer supports 8 bits per channel in RGBA or BGRA order
+			let find_visual = |depth: u8| scrn.iter_allowed_depths().find(|x| x.depth() == depth)
+				.and_then(|d| d.iter_visuals().filter(|x| x.is_truecolor())
+					.filter_map(|v| zpixmap_format(v.color_masks(), lsb_first).map(|f| (v.id(), f))).next());
+			let (visual, format) = find_visual(BITDEPTH as _)
+				.ok_or(Error::UnsupportedVisual { depth: BITDEPTH as _, class: "TrueColor" })?;
 			root_id = scrn.root();
-			colormap = rxcb::Colormap::new(&srv, visual.id(), root_id);
-			visualid = visual.id();
+			colormap = rxcb::Colormap::new(&srv, visual, root_id);
+			visualid = visual; pixel_format = format;
+			argb_visual = find_visual(32).map(|(v, f)| (v, rxcb::Colormap::new(&srv, v, root_id), f));
 		}
+		let resources = srv.get_property(root_id, rxcb::XCB_ATOM_RESOURCE_MANAGER, false)
+			.map(|r| String::from_utf8_lossy(r.value()).into_owned()).unwrap_or_default();
 		let app = Rc::new(GUIApplication
 		{
-			wm_protocols: srv.intern("WM_PROTOCOLS").reply().unwrap(),
-			wm_delete_window: srv.intern("WM_DELETE_WINDOW").reply().unwrap(),
-			desired_visualid: visualid, colormap, root_id,
-			action_atoms: ActionAtoms::init(&srv).unwrap(),
+			wm_protocols: srv.intern("WM_PROTOCOLS").reply().map_err(|e| srv.error(&e))?,
+			wm_delete_window: srv.intern("WM_DELETE_WINDOW").reply().map_err(|e| srv.error(&e))?,
+			desired_visualid: visualid, pixel_format, argb_visual, colormap, root_id,
+			action_atoms: ActionAtoms::init(&srv).map_err(|e| srv.error(&e))?,
+			windows: RefCell::new(HashMap::new()), epoch: Instant::now(), epoch_ust: monotonic_us(),
+			present: srv.present_extension(), shm: Cell::new(true), shm_ext: srv.shm_extension(),
+			composite: srv.composite_available(),
+			created: RefCell::new(Vec::new()), recorder: RefCell::new(None), replaying: Cell::new(false), automation: RefCell::new(None),
+			selections: Selections::new(&srv, root_id).map_err(|e| srv.error(&e))?,
+			xdnd: Xdnd::new(&srv).map_err(|e| srv.error(&e))?, drop_session: RefCell::new(None), pending_drop: RefCell::new(None),
+			drag_source: RefCell::new(None), xim: Xim::connect(&srv, root_id),
+			cursor_theme: xcursor::Theme::new(&resources), cursors: RefCell::new(HashMap::new()), blank_cursor: Cell::new(None),
+			argb_format: srv.argb_picture_format(), xinput: XInput::new(&srv, root_id), pointer_grab: Cell::new(None),
 			srv: Rc::new(srv), dg: delegate
 		});
+		if let Some(p) = ::std::env::var_os(recording::RECORD_ENV)
+		{
+			*app.recorder.borrow_mut() = Some(Recorder::new(BufWriter::new(File::create(p)?))?);
+		}
+		if let Some(p) = ::std::env::var_os(automation::AUTOMATION_ENV)
+		{
+			*app.automation.borrow_mut() = Some(AutomationServer::bind(p)?);
+		}
         app.dg.postinit(&app);
 
 		app.srv.flush();
-		if cfg!(all(not(feature = "manual_rendering"), feature = "with_ferrite"))
+		if let Some(p) = ::std::env::var_os(recording::REPLAY_ENV)
 		{
-			loop
+			if !app.replay(BufReader::new(File::open(p)?))? { return Ok(0); }
+		}
+		app.process_events();
+		app.selections.hand_over(&app.srv);
+		Ok(0)
+	}
+	fn event_delegate(&self) -> &E { &self.dg }
+}
+impl<E: EventDelegate> GUIApplication<E>
+{
+	/// The CLIPBOARD selection
+	pub fn clipboard(&self) -> Clipboard<'_> { Clipboard::new(self, Selection::Clipboard) }
+	/// The PRIMARY selection(set by selecting text, pasted by middle click). None if the delegate opts out
+	pub fn primary_selection(&self) -> Option<Clipboard<'_>>
+	{
+		if self.dg.uses_primary_selection() { Some(Clipboard::new(self, Selection::Primary)) } else { None }
+	}
+	/// Whether touchpad pinches and swipes are reported to `WindowEventDelegate::gesture`(XInput 2.4). Holds are not reported
+	pub fn gestures_available(&self) -> bool { self.xinput.as_ref().is_some_and(|x| x.gestures_available()) }
+
+	fn process_events(&self)
+	{
+		loop
+		{
+			// Continuous windows never let the loop sleep
+			let timeout = self.next_frame_deadline().map(|t| t.checked_sub(self.epoch.elapsed()).unwrap_or_default());
+			let drag_timeout = self.drag_source.borrow().as_ref().and_then(|s| s.deadline())
+				.map(|t| t.checked_duration_since(Instant::now()).unwrap_or_default());
+			let scroll_timeout = self.xinput.as_ref().and_then(|x| x.scroll_deadline())
+				.map(|t| t.checked_duration_since(Instant::now()).unwrap_or_default());
+			let timeout = [timeout, self.selections.timeout(), drag_timeout, scroll_timeout].iter().filter_map(|&t| t).min();
+			let fds = self.automation.borrow().as_ref().map_or_else(Vec::new, |a| a.fds());
+			if !self.dispatch_batch(self.srv.wait_event_timeout(timeout, &fds)) { return; }
+			self.selections.expire(&self.srv);
+			self.expire_drag();
+			if let Some(ref x) = self.xinput { for e in x.expire_scrolls(Instant::now()) { self.xinput_event(e); } }
+			self.selections.deliver_ready();
+			self.complete_drop();
+			self.render_due_windows();
+			if !self.serve_automation() { return; }
+			self.request_vblank_notifications();
+			self.srv.flush();
+		}
+	}
+	/// Dispatch `first` and the events already queued after it. Returns false if the application should quit
+	fn dispatch_batch(&self, first: Option<rxcb::GenericEvent>) -> bool
+	{
+		let mut batch = Vec::new();
+		let mut next = first;
+		while let Some(e) = next { batch.push(e); next = self.srv.poll_event(); }
+		let last_sequence = batch.last().map(|e| e.sequence());
+		for e in coalesce(batch)
+		{
+			let go_on = match e
 			{
-				if let Some(e) = app.srv.poll_event()
+				Coalesced::Event(e) => self.dispatch(&e),
+				Coalesced::Motion(e, history) =>
 				{
-					if e.response_type() == rxcb::ClientMessageEvent::RESPONSE_ENUM
+					let window = unsafe { rxcb::MotionNotifyEvent::from_ref(&e) }.window();
+					let locked = self.pointer_grab.get() == Some((window, GrabMode::Locked));
+					if !history.is_empty() && !locked { self.notify_window(window, RecordedEvent::MotionHistory(history)); }
+					self.dispatch(&e)
+				},
+				Coalesced::Expose { window, rects, complete } =>
+				{
+					if let Some(w) = self.window_entry(window)
 					{
-						let e = unsafe { rxcb::ClientMessageEvent::from_ref(&e) };
-						if e.msg_type() == app.wm_protocols && e.data_as_u32() == app.wm_delete_window { break; }
+						for r in rects { w.schedule.add_damage(r); }
+						// redraw once the last of a series arrives
+						if complete { w.schedule.request_redraw(); }
 					}
+					true
+				}
+			};
+			if !go_on { return false; }
+		}
+		if let Some(s) = last_sequence { self.srv.settle(s); }
+		true
+	}
+	/// Returns false if the application should quit
+	fn dispatch(&self, e: &rxcb::GenericEvent) -> bool
+	{
+		match e.response_type()
+		{
+			// errors of requests whose replies or checks nobody waits for
+			rxcb::GenericError::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::GenericError::from_ref(e) };
+				self.dg.protocol_error(&self.srv.decode_error(e));
+			},
+			rxcb::ClientMessageEvent::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::ClientMessageEvent::from_ref(e) };
+				if let Some(events) = self.xim.as_ref().and_then(|x| x.handle_event(&self.srv, e))
+				{
+					for ie in events { self.ime_event(ie); }
+				}
+				else if let Some(m) = self.xdnd.decode(&self.srv, e) { self.handle_drag(m); }
+				else if let Some(m) = self.xdnd.decode_source(e) { self.drag_message(m); }
+				else if e.msg_type() == self.wm_protocols && e.data_as_u32() == self.wm_delete_window
+				{
+					// closing a window quits the application. Live close requests are dropped during replays
+					return self.replaying.get() || self.window_entry(e.window())
+						.is_some_and(|w| !self.deliver(e.window(), &w, self.epoch.elapsed(), &WindowEvent::CloseRequested));
+				}
+			},
+			rxcb::XCB_KEY_PRESS | rxcb::XCB_KEY_RELEASE =>
+			{
+				let pressed = e.response_type() == rxcb::XCB_KEY_PRESS;
+				let e = unsafe { rxcb::KeyEvent::from_ref(e) };
+				self.selections.set_user_time(e.time());
+				// delivered when the input method sends it back
+				if self.xim.as_ref().is_some_and(|x| x.filter_key(&self.srv, e, pressed)) { return true; }
+				let we = if pressed { WindowEvent::KeyDown(e.keycode() as _) } else { WindowEvent::KeyUp(e.keycode() as _) };
+				self.dispatch_to_window(e.window(), &we);
+			},
+			rxcb::XCB_BUTTON_PRESS | rxcb::XCB_BUTTON_RELEASE =>
+			{
+				let pressed = e.response_type() == rxcb::XCB_BUTTON_PRESS;
+				let e = unsafe { rxcb::ButtonEvent::from_ref(e) };
+				self.selections.set_user_time(e.time());
+				if !pressed && (e.button() < 4 || e.button() > 7) { self.drag_release(e.time()); }
+				let (x, y) = (e.pos().0 as f32, e.pos().1 as f32);
+				let (phase, source) = (ScrollPhase::Moved, ScrollSource::Wheel);
+				let we = match e.button()
+				{
+					// wheel: a press per notch, releases are ignored. XI2 reports scrolls with their devices
+					4 ..= 7 if !pressed || self.xinput.as_ref().is_some_and(|x| x.smooth_scroll_available()) => return true,
+					4 => WindowEvent::Scroll { dx: 0.0, dy: 1.0, phase, source },
+					5 => WindowEvent::Scroll { dx: 0.0, dy: -1.0, phase, source },
+					6 => WindowEvent::Scroll { dx: 1.0, dy: 0.0, phase, source },
+					7 => WindowEvent::Scroll { dx: -1.0, dy: 0.0, phase, source },
+					b => WindowEvent::PointerButton { button: x11_button(b), pressed, x, y }
+				};
+				self.dispatch_to_window(e.window(), &we);
+			},
+			rxcb::MotionNotifyEvent::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::MotionNotifyEvent::from_ref(e) };
+				// a locked pointer stays at the center and reports raw motions only
+				if self.pointer_grab.get() == Some((e.window(), GrabMode::Locked))
+				{
+					self.center_pointer(e.window(), Some(e.pos()));
+					return true;
+				}
+				if let Some(ref mut s) = *self.drag_source.borrow_mut()
+				{
+					self.xdnd.drag_motion(&self.srv, self.root_id, s, e.root_pos(), e.time());
+				}
+				self.dispatch_to_window(e.window(), &WindowEvent::PointerMoved { x: e.pos().0 as _, y: e.pos().1 as _ });
+			},
+			rxcb::SelectionRequestEvent::RESPONSE_ENUM | rxcb::SelectionClearEvent::RESPONSE_ENUM |
+			rxcb::SelectionNotifyEvent::RESPONSE_ENUM | rxcb::PropertyNotifyEvent::RESPONSE_ENUM =>
+				self.selections.handle_event(&self.srv, e),
+			rxcb::XCB_FOCUS_IN | rxcb::XCB_FOCUS_OUT =>
+			{
+				let focused = e.response_type() == rxcb::XCB_FOCUS_IN;
+				let e = unsafe { rxcb::FocusEvent::from_ref(e) };
+				if !e.is_grab()
+				{
+					if let Some(ref x) = self.xim { x.set_focus(&self.srv, e.window(), focused); }
+					if !focused && self.pointer_grab.get().is_some_and(|(w, _)| w == e.window()) { self.release_cursor_grab(); }
+					self.dispatch_to_window(e.window(), &WindowEvent::Focus(focused));
+				}
+			},
+			rxcb::XCB_MAP_NOTIFY | rxcb::XCB_UNMAP_NOTIFY =>
+			{
+				let mapped = e.response_type() == rxcb::XCB_MAP_NOTIFY;
+				let e = unsafe { rxcb::MapEvent::from_ref(e) };
+				if let Some(w) = self.window_entry(e.window())
+				{
+					w.mapped.set(mapped);
+					// vblank notifications are requested again when mapped
+					if !mapped { w.vsync.pending.set(false); }
+					else { self.update_refresh_pacing(e.window(), &w); }
+				}
+			},
+			rxcb::ConfigureNotifyEvent::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::ConfigureNotifyEvent::from_ref(e) };
+				if let Some(w) = self.window_entry(e.window())
+				{
+					// the window may have moved to another monitor
+					if w.mapped.get() { self.update_refresh_pacing(e.window(), &w); }
+					// a replay resizes the window as recorded
+					if w.size.get() != e.size() && !self.replaying.get()
+					{
+						let we = WindowEvent::Resize { width: e.size().0 as _, height: e.size().1 as _ };
+						self.deliver(e.window(), &w, self.epoch.elapsed(), &we);
+					}
+				}
+			},
+			_ => if let Some(xe) = self.xinput.as_ref().and_then(|x| x.decode(&self.srv, e)) { self.xinput_event(xe); }
+			else if let Some(e) = self.shm_ext.as_ref().and_then(|s| s.completion(e))
+			{
+				// the server finished reading the segment of the window
+				if let Some(w) = self.window_entry(e.drawable()) { w.shm_busy.set(false); }
+			}
+			else if let Some(e) = self.present.as_ref().and_then(|p| p.complete_notify(e))
+			{
+				if e.kind() != rxcb::XCB_PRESENT_COMPLETE_KIND_NOTIFY_MSC { return true; }
+				if let Some(w) = self.window_entry(e.window())
+				{
+					w.vsync.vblank(self.ust_to_duration(e.ust()), e.msc());
+					w.schedule.mark_dirty();
 				}
-				else { app.dg.on_render_period(); }
 			}
 		}
-		else
+		true
+	}
+	/// XDND target side: drags over the windows are delegated to them
+	fn handle_drag(&self, m: xdnd::TargetMessage)
+	{
+		match m
 		{
-			while let Some(e) = app.srv.wait_event()
+			xdnd::TargetMessage::Enter { source, window, types } =>
+			{
+				let formats = types.into_iter().filter_map(|t| self.xdnd.format_name(&self.srv, t)).collect();
+				*self.drop_session.borrow_mut() = Some(DropSession
+				{
+					source, window, formats, effect: DropEffect::None, entered: false
+				});
+			},
+			xdnd::TargetMessage::Position { source, window, root } =>
 			{
-				if e.response_type() == rxcb::ClientMessageEvent::RESPONSE_ENUM
+				// XdndEnter carries no position, so the delegate sees the drag enter at the first XdndPosition
+				let (enter_formats, effect) = match *self.drop_session.borrow_mut()
+				{
+					Some(ref mut s) if s.source == source && s.window == window =>
+						(if s.entered { None } else { s.entered = true; Some(s.formats.clone()) }, s.effect),
+					_ => return
+				};
+				let origin = self.srv.root_position(window, self.root_id).unwrap_or((0, 0));
+				let (x, y) = ((root.0 as i32 - origin.0 as i32) as f32, (root.1 as i32 - origin.1 as i32) as f32);
+				// the session is not borrowed while the delegate runs
+				let effect = match enter_formats
+				{
+					Some(formats) => self.notify_window(window, RecordedEvent::DragEntered { formats, x, y }).unwrap_or(DropEffect::None),
+					None => self.notify_window(window, RecordedEvent::DragMoved { x, y }).unwrap_or(effect)
+				};
+				match *self.drop_session.borrow_mut()
 				{
-					let e = unsafe { rxcb::ClientMessageEvent::from_ref(&e) };
-					if e.msg_type() == app.wm_protocols && e.data_as_u32() == app.wm_delete_window { break; }
+					Some(ref mut s) if s.source == source && s.window == window => s.effect = effect,
+					_ => return
 				}
-				#[cfg(feature = "with_ferrite")] {
-					if e.response_type() == rxcb::ExposeEvent::RESPONSE_ENUM { app.dg.on_render_period(); }
+				self.xdnd.send_status(&self.srv, source, window, effect);
+			},
+			xdnd::TargetMessage::Leave { source, window } =>
+			{
+				let s = self.take_drop_session(source, window);
+				if s.is_some_and(|s| s.entered) { self.notify_window(window, RecordedEvent::DragLeft); }
+			},
+			xdnd::TargetMessage::Drop { source, window, time } =>
+			{
+				let s = match self.take_drop_session(source, window) { Some(s) => s, None => return };
+				let w = match self.window_entry(window)
+				{
+					// XdndSelection holds the data of one drop at a time, so drops during the transfer of another are refused
+					Some(w) if s.effect != DropEffect::None && self.pending_drop.borrow().is_none() => w,
+					w =>
+					{
+						if w.is_some() && s.entered { self.notify_window(window, RecordedEvent::DragLeft); }
+						self.xdnd.send_finished(&self.srv, source, window, DropEffect::None);
+						return;
+					}
+				};
+				let formats = w.callbacks.drop_formats(&s.formats).into_iter().filter(|f| s.formats.contains(f)).collect::<Vec<_>>();
+				// the data is read from XdndSelection as of the drop
+				self.selections.set_user_time(time);
+				let fetch = Rc::new(RefCell::new((formats.len(), MimeData::new())));
+				*self.pending_drop.borrow_mut() = Some(PendingDrop { source, window, effect: s.effect, fetch: fetch.clone() });
+				for f in formats
+				{
+					let fetch = fetch.clone();
+					self.selections.get(&self.srv, Selection::Dnd, &f.clone(), Box::new(move |d|
+					{
+						let mut fetch = fetch.borrow_mut();
+						fetch.0 -= 1;
+						if let Some(d) = d { fetch.1.insert(f, d); }
+					}));
 				}
 			}
 		}
-		0
+	}
+	fn take_drop_session(&self, source: rxcb::WindowID, window: rxcb::WindowID) -> Option<DropSession>
+	{
+		let mut session = self.drop_session.borrow_mut();
+		if session.as_ref().is_some_and(|s| s.source == source && s.window == window) { session.take() } else { None }
+	}
+	/// Deliver a drop once all of its formats are read
+	fn complete_drop(&self)
+	{
+		if self.pending_drop.borrow().as_ref().is_none_or(|p| p.fetch.borrow().0 > 0) { return; }
+		let p = self.pending_drop.borrow_mut().take().unwrap();
+		let data = DropData { data: ::std::mem::take(&mut p.fetch.borrow_mut().1) };
+		self.notify_window(p.window, RecordedEvent::Dropped(data));
+		self.xdnd.send_finished(&self.srv, p.source, p.window, p.effect);
+	}
+	/// Cursor of the icon from the theme, or from the core cursor font if the theme lacks it
+	fn cursor(&self, icon: CursorIcon) -> rxcb::CursorID
+	{
+		if let Some(&c) = self.cursors.borrow().get(&icon) { return c; }
+		let c = match (self.argb_format, self.cursor_theme.load(icon))
+		{
+			(Some(f), Some(img)) => self.srv.create_argb_cursor(self.root_id, f, (img.width, img.height), img.hotspot, &img.pixels),
+			_ => self.srv.create_font_cursor(xcursor::core_glyph(icon))
+		};
+		self.cursors.borrow_mut().insert(icon, c);
+		c
+	}
+	fn blank_cursor(&self) -> rxcb::CursorID
+	{
+		self.blank_cursor.get().unwrap_or_else(|| { let c = self.srv.create_blank_cursor(self.root_id); self.blank_cursor.set(Some(c)); c })
+	}
+	fn apply_cursor(&self, w: rxcb::WindowID, e: &WindowEntry)
+	{
+		let c = if !e.cursor_visible.get() { self.blank_cursor() }
+			else { e.custom_cursor.get().unwrap_or_else(|| self.cursor(e.cursor.get())) };
+		self.srv.set_window_cursor(w, c);
+		self.srv.flush();
+	}
+	/// Grabs the pointer confined to the window. A locked pointer is hidden and kept at the center of the window
+	fn set_cursor_grab(&self, w: rxcb::WindowID, mode: GrabMode) -> bool
+	{
+		// a drag holds its own grab
+		if self.drag_source.borrow().is_some() { return false; }
+		// a locked pointer does not move, so its movements are reported by raw motions only
+		if mode == GrabMode::Locked && !self.xinput.as_ref().is_some_and(|x| x.raw_motion_available()) { return false; }
+		self.release_cursor_grab();
+		if mode == GrabMode::None { return true; }
+		let cursor = if mode == GrabMode::Locked { self.blank_cursor() } else { rxcb::XCB_CURSOR_NONE };
+		let mask = rxcb::XCB_EVENT_MASK_BUTTON_PRESS | rxcb::XCB_EVENT_MASK_BUTTON_RELEASE | rxcb::XCB_EVENT_MASK_POINTER_MOTION;
+		if !self.srv.grab_pointer(w, mask as _, Some(w), cursor, rxcb::XCB_TIME_CURRENT_TIME) { return false; }
+		if let Some(ref x) = self.xinput { x.select_raw_motion(&self.srv, self.root_id, true); }
+		self.pointer_grab.set(Some((w, mode)));
+		if mode == GrabMode::Locked { self.center_pointer(w, None); }
+		self.srv.flush();
+		true
+	}
+	fn release_cursor_grab(&self)
+	{
+		if self.pointer_grab.take().is_none() { return; }
+		self.srv.ungrab_pointer(rxcb::XCB_TIME_CURRENT_TIME);
+		if let Some(ref x) = self.xinput { x.select_raw_motion(&self.srv, self.root_id, false); }
+		self.srv.flush();
+	}
+	/// Warp the pointer to the center of the window unless it is at `pos` already
+	fn center_pointer(&self, w: rxcb::WindowID, pos: Option<(i16, i16)>)
+	{
+		let size = match self.window_entry(w) { Some(e) => e.size.get(), None => return };
+		let center = ((size.0 / 2) as i16, (size.1 / 2) as i16);
+		if pos != Some(center) { self.srv.warp_pointer(w, center.0, center.1); self.srv.flush(); }
+	}
+	fn xinput_event(&self, e: XIEvent)
+	{
+		match e
+		{
+			XIEvent::RawMotion { dx, dy } => if let Some((w, _)) = self.pointer_grab.get()
+			{
+				self.dispatch_to_window(w, &WindowEvent::RawMouseMotion { dx, dy });
+			},
+			XIEvent::Touch { window, phase, id, x, y } => self.dispatch_to_window(window, &WindowEvent::Touch { phase, id, x, y }),
+			XIEvent::Pen { window, x, y, pressure, tilt, buttons, eraser } => self.dispatch_to_window(window, &WindowEvent::Pen
+			{
+				x, y, pressure, tilt_x: tilt.0, tilt_y: tilt.1, buttons, eraser
+			}),
+			XIEvent::Gesture { window, gesture, phase } => self.dispatch_to_window(window, &WindowEvent::Gesture { gesture, phase }),
+			XIEvent::Scroll { window, dx, dy, phase, source } => self.dispatch_to_window(window, &WindowEvent::Scroll { dx, dy, phase, source }),
+			XIEvent::DevicesChanged { ended } => if let Some(ref x) = self.xinput
+			{
+				for e in ended { self.xinput_event(e); }
+				for &w in self.windows.borrow().keys() { x.select_window(&self.srv, w); }
+				self.srv.flush();
+			}
+		}
+	}
+	fn ime_event(&self, e: ImeEvent)
+	{
+		match e
+		{
+			ImeEvent::Preedit { window, text, cursor } => self.dispatch_to_window(window, &WindowEvent::ImePreedit { text, cursor }),
+			ImeEvent::Commit { window, text } => self.dispatch_to_window(window, &WindowEvent::ImeCommit(text)),
+			ImeEvent::Key { window, keycode, pressed } =>
+			{
+				let we = if pressed { WindowEvent::KeyDown(keycode as _) } else { WindowEvent::KeyUp(keycode as _) };
+				self.dispatch_to_window(window, &we);
+			}
+		}
+	}
+	/// XDND source side: grab the pointer and offer the data as XdndSelection
+	fn start_drag(&self, window: rxcb::WindowID, data: DragData, allowed: &[DropEffect]) -> bool
+	{
+		if self.drag_source.borrow().is_some() || allowed.is_empty() || data.formats.is_empty() { return false; }
+		let time = self.selections.user_time();
+		let mask = rxcb::XCB_EVENT_MASK_BUTTON_RELEASE | rxcb::XCB_EVENT_MASK_POINTER_MOTION;
+		if !self.srv.grab_pointer(window, mask as _, None, rxcb::XCB_CURSOR_NONE, time) { return false; }
+		let types = match self.selections.set_provider(&self.srv, Selection::Dnd, &data.formats, Rc::from(data.provider))
+		{
+			Some(t) => t, None => { self.srv.ungrab_pointer(time); self.srv.flush(); return false; }
+		};
+		*self.drag_source.borrow_mut() = Some(self.xdnd.begin(&self.srv, window, types, allowed));
+		self.srv.flush();
+		true
+	}
+	fn drag_release(&self, time: rxcb::Timestamp)
+	{
+		let end = match *self.drag_source.borrow_mut()
+		{
+			Some(ref mut s) if !s.is_dropped() => { self.srv.ungrab_pointer(time); self.xdnd.drag_release(&self.srv, s, time) },
+			_ => return
+		};
+		if let Some(effect) = end { self.end_drag(effect); }
+	}
+	fn drag_message(&self, m: xdnd::SourceMessage)
+	{
+		let end = match *self.drag_source.borrow_mut()
+		{
+			Some(ref mut s) => self.xdnd.drag_message(&self.srv, s, m), None => return
+		};
+		if let Some(effect) = end { self.end_drag(effect); }
+	}
+	/// Give up a drop the target does not finish
+	fn expire_drag(&self)
+	{
+		let expired = self.drag_source.borrow().as_ref().and_then(|s| s.deadline()).is_some_and(|t| t <= Instant::now());
+		if expired { self.end_drag(DropEffect::None); }
+	}
+	fn end_drag(&self, effect: DropEffect)
+	{
+		let s = match self.drag_source.borrow_mut().take() { Some(s) => s, None => return };
+		self.selections.release(&self.srv, Selection::Dnd);
+		self.notify_window(s.window(), RecordedEvent::DragFinished(effect));
+	}
+	fn ust_to_duration(&self, ust: u64) -> Duration { Duration::from_micros(ust.saturating_sub(self.epoch_ust)) }
+	fn window_entry(&self, id: rxcb::WindowID) -> Option<Rc<WindowEntry>> { self.windows.borrow().get(&id).cloned() }
+	/// Live events are dropped while a recording is replayed
+	fn dispatch_to_window(&self, id: rxcb::WindowID, e: &WindowEvent)
+	{
+		if self.replaying.get() { return; }
+		if let Some(w) = self.window_entry(id) { self.deliver(id, &w, self.epoch.elapsed(), e); }
+	}
+	/// Like `dispatch_to_window` for callbacks other than events. Returns the effect `drag_entered` or `drag_moved` accepted
+	fn notify_window(&self, id: rxcb::WindowID, e: RecordedEvent) -> Option<DropEffect>
+	{
+		if self.replaying.get() { return None; }
+		self.window_entry(id).and_then(|w| self.notify(id, &w, self.epoch.elapsed(), e))
+	}
+	/// Record and call a callback other than an event
+	fn notify(&self, id: rxcb::WindowID, w: &WindowEntry, timestamp: Duration, e: RecordedEvent) -> Option<DropEffect>
+	{
+		self.record(id, timestamp, e.clone());
+		w.callbacks.notify(&e)
+	}
+	/// Record and delegate an event. Returns whether the close is accepted for `CloseRequested`, otherwise true
+	fn deliver(&self, id: rxcb::WindowID, w: &WindowEntry, timestamp: Duration, e: &WindowEvent) -> bool
+	{
+		self.record(id, timestamp, RecordedEvent::Window(e.clone()));
+		match *e
+		{
+			WindowEvent::Resize { width, height } => { w.size.set((width as _, height as _)); w.schedule.mark_dirty(); },
+			WindowEvent::Focus(f) => w.focused.set(f),
+			_ => ()
+		}
+		w.callbacks.dispatch(e)
+	}
+	fn render_window(&self, id: rxcb::WindowID, w: &WindowEntry, timestamp: Duration, predicted_present: Option<Duration>)
+	{
+		self.record(id, timestamp, RecordedEvent::Frame { predicted_present });
+		w.callbacks.render(&w.clock.tick(timestamp, predicted_present, w.schedule.take_damage()));
+	}
+	fn record(&self, id: rxcb::WindowID, timestamp: Duration, event: RecordedEvent)
+	{
+		let mut rec = self.recorder.borrow_mut();
+		if rec.is_none() { return; }
+		let window = match self.created.borrow().iter().position(|&c| c == id) { Some(i) => i as u32, None => return };
+		// a broken sink stops recording rather than the application
+		if rec.as_mut().unwrap().record(&Record { timestamp, window, event }).is_err() { *rec = None; }
+	}
+	/// Handle requests from automation clients. Returns false if the application should quit
+	fn serve_automation(&self) -> bool
+	{
+		let requests = match *self.automation.borrow_mut() { Some(ref mut a) => a.poll_requests(), None => return true };
+		let mut quit = false;
+		for mut r in requests
+		{
+			let response = match ::std::mem::replace(&mut r.command, Ok(Command::WaitIdle))
+			{
+				Err(e) => Response::Error(e),
+				Ok(Command::ListWindows) => Response::Windows(self.window_infos()),
+				Ok(Command::Inject { window, event }) => match self.created_window(window)
+				{
+					Some((id, w)) =>
+					{
+						let accepted = self.deliver(id, &w, self.epoch.elapsed(), &event);
+						// closing a window quits the application
+						if let WindowEvent::CloseRequested = event { quit |= accepted; Response::Closed(accepted) }
+						else { Response::Ok }
+					},
+					None => Response::Error(format!("no window {}", window))
+				},
+				Ok(Command::Capture { window }) => match self.created_window(window)
+				{
+					Some((id, w)) => self.capture_window(id, &w).map(Response::Image)
+						.unwrap_or_else(|e| Response::Error(e.to_string())),
+					None => Response::Error(format!("no window {}", window))
+				},
+				Ok(Command::WaitIdle) => Response::Ok
+			};
+			if let Some(ref mut a) = *self.automation.borrow_mut() { a.respond(r, response); }
+		}
+		if let Some(ref mut a) = *self.automation.borrow_mut()
+		{
+			if a.has_idle_waiters() && self.windows.borrow().values().all(|w| !w.schedule.is_dirty()) { a.notify_idle(); }
+		}
+		!quit
+	}
+	fn created_window(&self, index: u32) -> Option<(rxcb::WindowID, Rc<WindowEntry>)>
+	{
+		let id = *self.created.borrow().get(index as usize)?;
+		self.window_entry(id).map(|w| (id, w))
+	}
+	fn window_infos(&self) -> Vec<WindowInfo>
+	{
+		let created = self.created.borrow();
+		created.iter().enumerate().filter_map(|(i, &id)| self.window_entry(id).map(|w| WindowInfo
+		{
+			id: i as _, title: w.caption.clone(),
+			position: self.srv.root_position(id, self.root_id).map_or((0, 0), |(x, y)| (x as _, y as _)),
+			size: (w.size.get().0 as _, w.size.get().1 as _), visible: self.srv.is_viewable(id), focused: w.focused.get()
+		})).collect()
+	}
+	/// Reads the composite pixmap if the Composite extension redirects the window, otherwise the window itself
+	fn capture_window(&self, id: rxcb::WindowID, entry: &WindowEntry) -> Result<Image>
+	{
+		let (w, h) = entry.size.get();
+		let pixmap = if self.composite { self.srv.name_window_pixmap(id).ok() } else { None };
+		let r = self.srv.get_image(pixmap.unwrap_or(id), 0, 0, w, h);
+		if let Some(p) = pixmap { self.srv.free_pixmap(p); }
+		let r = r.map_err(|e| self.srv.error(&e))?;
+		// depth 24 and 32 are stored in 32 bits per pixel; the padding byte of depth 24 is undefined
+		let has_alpha = r.depth() == 32;
+		let mut data = r.data().to_owned();
+		if !has_alpha { for p in data.chunks_mut(4) { p[3] = 0xff; } }
+		Ok(Image { width: w as _, height: h as _, format: entry.format, has_alpha, data })
+	}
+	/// Feed a recording through the dispatcher with the recorded timestamps, each at its recorded time after the epoch.
+	/// Server events are handled meanwhile but live input is dropped. Returns false if the application should quit
+	fn replay<R: BufRead>(&self, source: R) -> IOResult<bool>
+	{
+		self.replaying.set(true);
+		let r = self.replay_records(source);
+		self.replaying.set(false);
+		r
+	}
+	fn replay_records<R: BufRead>(&self, source: R) -> IOResult<bool>
+	{
+		for r in Replay::new(source)?
+		{
+			let r = r?;
+			loop
+			{
+				if !self.dispatch_batch(self.srv.poll_event()) { return Ok(false); }
+				let elapsed = self.epoch.elapsed();
+				if elapsed >= r.timestamp { break; }
+				self.srv.flush();
+				if !self.dispatch_batch(self.srv.wait_event_timeout(Some(r.timestamp - elapsed), &[])) { return Ok(false); }
+			}
+			let id = match self.created.borrow().get(r.window as usize) { Some(&id) => id, None => continue };
+			let w = match self.window_entry(id) { Some(w) => w, None => continue };
+			match r.event
+			{
+				RecordedEvent::Window(WindowEvent::CloseRequested) =>
+					if self.deliver(id, &w, r.timestamp, &WindowEvent::CloseRequested) { return Ok(false); },
+				RecordedEvent::Window(e) => { self.deliver(id, &w, r.timestamp, &e); },
+				RecordedEvent::Frame { predicted_present } => self.render_window(id, &w, r.timestamp, predicted_present),
+				e => { self.notify(id, &w, r.timestamp, e); }
+			}
+			self.srv.flush();
+		}
+		Ok(true)
+	}
+	/// Without Present, continuous windows are paced by the refresh rate of the monitor at the center of the window
+	fn update_refresh_pacing(&self, id: rxcb::WindowID, w: &WindowEntry)
+	{
+		if self.present.is_some() { return; }
+		let origin = match self.srv.root_position(id, self.root_id) { Some(p) => p, None => return };
+		let size = w.size.get();
+		let center = (origin.0.saturating_add((size.0 / 2) as i16), origin.1.saturating_add((size.1 / 2) as i16));
+		let hz = self.srv.refresh_rate_at(self.root_id, center.0, center.1).unwrap_or(DEFAULT_REFRESH_RATE);
+		let pacing = Pacing::Interval(refresh_interval(hz));
+		if w.schedule.pacing() != pacing { w.schedule.set_pacing(pacing); }
+	}
+	fn next_frame_deadline(&self) -> Option<Duration>
+	{
+		self.windows.borrow().values().filter_map(|w| w.schedule.deadline()).min()
+	}
+	fn render_due_windows(&self)
+	{
+		let now = self.epoch.elapsed();
+		// callbacks may create or drop windows
+		let due = self.windows.borrow().iter().filter(|&(_, w)| w.schedule.due(now))
+			.map(|(&id, w)| (id, w.clone())).collect::<Vec<_>>();
+		for (id, w) in due { self.render_window(id, &w, now, w.vsync.predicted_present()); }
+	}
+	/// Continuous windows paced by Present wait for the next vblank after each frame. Unmapped windows get no vblanks
+	fn request_vblank_notifications(&self)
+	{
+		if self.present.is_none() { return; }
+		for (&id, w) in self.windows.borrow().iter()
+		{
+			if w.mapped.get() && w.schedule.mode() == RenderMode::Continuous && !w.vsync.pending.replace(true)
+			{
+				self.srv.present_notify_next_msc(&rxcb::Window::from_id(id), 0);
+			}
+		}
 	}
 }
-#[cfg(feature = "with_ferrite")]
-impl<E: EventDelegate> ::FerriteRenderingServer for GUIApplication<E>
+impl<E: EventDelegate> SelectionBackend for GUIApplication<E>
 {
-    type SurfaceSource = NativeWindow<E>;
-
-    fn presentation_support(&self, adapter: &fe::PhysicalDevice, rendered_qf: u32) -> bool
+	fn set_selection(&self, which: Selection, data: MimeData)
+	{
+		self.selections.set(&self.srv, which, data); self.srv.flush();
+	}
+	fn get_selection(&self, which: Selection, mime: &str, callback: ReceiveCallback)
+	{
+		self.selections.get(&self.srv, which, mime, callback); self.srv.flush();
+	}
+}
+#[cfg(feature = "with_bedrock")]
+impl<E: EventDelegate> ::BedrockRenderingServer for GUIApplication<E>
+{
+    fn presentation_support(&self, adapter: &br::PhysicalDevice, rendered_qf: u32) -> bool
 	{
 		adapter.xcb_presentation_support(rendered_qf, self.srv.inner(), self.desired_visualid)
 	}
-    fn create_surface(&self, w: &NativeWindow<E>, instance: &fe::Instance) -> fe::Result<fe::Surface>
+    fn create_surface<WE: WindowEventDelegate>(&self, w: &NativeView<WE>, instance: &br::Instance)
+		-> Result<br::Surface>
+	{
+		br::Surface::new_xcb(instance, self.srv.inner(), w.handle.id()).map_err(|e| Error::SurfaceCreation(format!("{:?}", e)))
+	}
+}
+
+/// Events of a batch after coalescing
+enum Coalesced
+{
+	Event(rxcb::GenericEvent),
+	/// The last of consecutive MotionNotify events of a window, with the positions of the preceding ones
+	Motion(rxcb::GenericEvent, Vec<(f32, f32)>),
+	/// Expose events of a window, merged into a redraw. `complete` if the last one ends a series(count = 0)
+	Expose { window: rxcb::WindowID, rects: Vec<Rect>, complete: bool }
+}
+/// Merge consecutive motions, keep only the last ConfigureNotify of each window and merge Expose events
+/// into the position of the first one of each window. The other events keep their order
+fn coalesce(events: Vec<rxcb::GenericEvent>) -> Vec<Coalesced>
+{
+	let mut last_configure = HashMap::new();
+	for (i, e) in events.iter().enumerate()
+	{
+		if e.response_type() == rxcb::ConfigureNotifyEvent::RESPONSE_ENUM
+		{
+			last_configure.insert(unsafe { rxcb::ConfigureNotifyEvent::from_ref(e) }.window(), i);
+		}
+	}
+	let mut exposed = HashMap::new();
+	let mut out = Vec::with_capacity(events.len());
+	for (i, e) in events.into_iter().enumerate()
+	{
+		match e.response_type()
+		{
+			rxcb::MotionNotifyEvent::RESPONSE_ENUM =>
+			{
+				let window = unsafe { rxcb::MotionNotifyEvent::from_ref(&e) }.window();
+				if let Some(&mut Coalesced::Motion(ref mut last, ref mut history)) = out.last_mut()
+				{
+					let (lw, lp) = { let m = unsafe { rxcb::MotionNotifyEvent::from_ref(last) }; (m.window(), m.pos()) };
+					if lw == window { history.push((lp.0 as _, lp.1 as _)); *last = e; continue; }
+				}
+				out.push(Coalesced::Motion(e, Vec::new()));
+			},
+			rxcb::ConfigureNotifyEvent::RESPONSE_ENUM =>
+				if last_configure.get(&unsafe { rxcb::ConfigureNotifyEvent::from_ref(&e) }.window()) == Some(&i)
+				{
+					out.push(Coalesced::Event(e));
+				},
+			rxcb::ExposeEvent::RESPONSE_ENUM =>
+			{
+				let x = unsafe { rxcb::ExposeEvent::from_ref(&e) };
+				let (ax, ay, aw, ah) = x.area();
+				let (r, complete) = (Rect::new(ax as _, ay as _, aw as _, ah as _), x.count() == 0);
+				match exposed.get(&x.window()).cloned()
+				{
+					Some(i) => if let Coalesced::Expose { ref mut rects, complete: ref mut c, .. } = out[i]
+					{
+						rects.push(r); *c = complete;
+					},
+					None =>
+					{
+						exposed.insert(x.window(), out.len());
+						out.push(Coalesced::Expose { window: x.window(), rects: vec![r], complete });
+					}
+				}
+			},
+			_ => out.push(Coalesced::Event(e))
+		}
+	}
+	out
+}
+
+/// A drag over one of the windows
+struct DropSession
+{
+	source: rxcb::WindowID, window: rxcb::WindowID, formats: Vec<String>, effect: DropEffect,
+	/// `drag_entered` has been called
+	entered: bool
+}
+/// A drop whose data is being read from the source
+struct PendingDrop
+{
+	source: rxcb::WindowID, window: rxcb::WindowID, effect: DropEffect,
+	/// (formats not read yet, data read)
+	fetch: Rc<RefCell<(usize, MimeData)>>
+}
+
+/// Type-erased window callbacks used by the dispatcher
+trait WindowCallbacks
+{
+	fn render(&self, frame: &FrameInfo);
+	fn dispatch(&self, e: &WindowEvent) -> bool;
+	fn notify(&self, e: &RecordedEvent) -> Option<DropEffect>;
+	fn drop_formats(&self, formats: &[String]) -> Vec<String>;
+}
+impl<WE: WindowEventDelegate> WindowCallbacks for Weak<WE>
+{
+	fn render(&self, frame: &FrameInfo) { if let Some(e) = self.upgrade() { e.render_frame(frame); } }
+	fn dispatch(&self, e: &WindowEvent) -> bool { self.upgrade().is_none_or(|d| e.dispatch_to(&*d)) }
+	fn notify(&self, e: &RecordedEvent) -> Option<DropEffect> { self.upgrade().and_then(|d| e.notify(&*d)) }
+	fn drop_formats(&self, formats: &[String]) -> Vec<String> { self.upgrade().map_or_else(Vec::new, |e| e.drop_formats(formats)) }
+}
+/// Byte order of a 32-bit pixel with the channel masks in an image of the byte order.
+/// None unless the channels are 8 bits in RGB or BGR order followed by the unused(or alpha) byte
+fn zpixmap_format((red, green, blue): (u32, u32, u32), lsb_first: bool) -> Option<PixelFormat>
+{
+	let byte = |mask: u32| match mask
+	{
+		0xff | 0xff00 | 0xff_0000 | 0xff00_0000 =>
+		{
+			let b = mask.trailing_zeros() / 8;
+			Some(if lsb_first { b } else { 3 - b })
+		},
+		_ => None
+	};
+	match (byte(red)?, byte(green)?, byte(blue)?)
+	{
+		(0, 1, 2) => Some(PixelFormat::Rgba8),
+		(2, 1, 0) => Some(PixelFormat::Bgra8),
+		_ => None
+	}
+}
+fn x11_button(b: u8) -> MouseButton
+{
+	match b { 1 => MouseButton::Left, 2 => MouseButton::Middle, 3 => MouseButton::Right, b => MouseButton::Other(b) }
+}
+struct WindowEntry
+{
+	schedule: FrameSchedule, clock: FrameClock, vsync: VsyncState, size: Cell<(u16, u16)>, mapped: Cell<bool>,
+	caption: String, focused: Cell<bool>, callbacks: Box<dyn WindowCallbacks>,
+	cursor: Cell<CursorIcon>, custom_cursor: Cell<Option<rxcb::CursorID>>, cursor_visible: Cell<bool>,
+	/// The server has not finished reading the MIT-SHM segment of the window(until a Completion event arrives)
+	shm_busy: Cell<bool>,
+	/// Channel order of the visual the window was created with
+	format: PixelFormat
+}
+/// Vblank timing observed through Present CompleteNotify events
+struct VsyncState { pending: Cell<bool>, last: Cell<Option<(Duration, u64)>>, period: Cell<Option<Duration>> }
+impl VsyncState
+{
+	fn new() -> Self { VsyncState { pending: Cell::new(false), last: Cell::new(None), period: Cell::new(None) } }
+	fn vblank(&self, time: Duration, msc: u64)
+	{
+		self.pending.set(false);
+		if let Some((lt, lmsc)) = self.last.replace(Some((time, msc)))
+		{
+			if msc > lmsc && time > lt { self.period.set(Some((time - lt) / (msc - lmsc) as u32)); }
+		}
+	}
+	/// The frame rendered now will be shown at the next vblank
+	fn predicted_present(&self) -> Option<Duration>
 	{
-		fe::Surface::new_xcb(instance, self.srv.inner(), w.0.id())
+		match (self.last.get(), self.period.get()) { (Some((t, _)), Some(p)) => Some(t + p), _ => None }
 	}
 }
+/// Assumed when RandR cannot tell the refresh rate
+const DEFAULT_REFRESH_RATE: f64 = 60.0;
+fn refresh_interval(hz: f64) -> Duration { Duration::from_nanos((1_000_000_000.0 / hz) as u64) }
+fn monotonic_us() -> u64
+{
+	let mut t = ::libc::timespec { tv_sec: 0, tv_nsec: 0 };
+	unsafe { ::libc::clock_gettime(::libc::CLOCK_MONOTONIC, &mut t); }
+	t.tv_sec as u64 * 1_000_000 + t.tv_nsec as u64 / 1000
+}
 
-#[cfg(feature = "with_ferrite")]
-pub struct NativeWindow<E: EventDelegate>(rxcb::Window, Rc<GUIApplication<E>>, Option<FeViewController<E>>);
-#[cfg(not(feature = "with_ferrite"))]
-pub struct NativeWindow<E: EventDelegate>(rxcb::Window, Rc<GUIApplication<E>>);
-impl<E: EventDelegate> Window for NativeWindow<E>
+pub struct NativeWindow<WE: WindowEventDelegate>
+{
+	handle: rxcb::Window, server: Rc<GUIApplication<WE::ClientDelegate>>, entry: Rc<WindowEntry>, blitter: Blitter
+}
+/// Uploads the pixel buffer of a window
+struct Blitter
+{
+	gc: rxcb::GContext, pixels: RefCell<PixelBuffer>, depth: u8,
+	shm: RefCell<Option<rxcb::ShmSegment>>
+}
+impl<WE: WindowEventDelegate> NativeWindow<WE>
+{
+	/// Returns false if MIT-SHM is not usable, or the server is still reading the previous frame
+	fn upload_shm(&self, b: &PixelBuffer, rects: &[Rect]) -> bool
+	{
+		let srv = &self.server.srv;
+		if self.server.shm_ext.is_none() || !self.server.shm.get() || self.entry.shm_busy.get() { return false; }
+		let mut seg = self.blitter.shm.borrow_mut();
+		let size = b.stride() * b.height() as usize;
+		if seg.as_ref().is_none_or(|s| s.size() < size)
+		{
+			if let Some(s) = seg.take() { srv.destroy_shm_segment(s); }
+			*seg = srv.create_shm_segment(size);
+			if seg.is_none() { self.server.shm.set(false); return false; }
+		}
+		let seg = seg.as_mut().unwrap();
+		{
+			let data = seg.data_mut();
+			for r in rects
+			{
+				for y in r.y as u32 .. r.bottom() as u32
+				{
+					let o = y as usize * b.stride() + r.x as usize * 4;
+					data[o .. o + r.width as usize * 4].copy_from_slice(b.row_span(r, y));
+				}
+			}
+		}
+		// requests are processed in order, so the completion of the last one frees the segment
+		for (i, r) in rects.iter().enumerate()
+		{
+			srv.shm_put_image(self.handle.id(), &self.blitter.gc, (b.width() as _, b.height() as _),
+				(r.x as _, r.y as _, r.width as _, r.height as _), (r.x as _, r.y as _), self.blitter.depth, seg, i + 1 == rects.len());
+		}
+		self.entry.shm_busy.set(true);
+		true
+	}
+	/// Split into PutImage requests fitting in the maximum request length
+	fn upload_put_image(&self, b: &PixelBuffer, rects: &[Rect])
+	{
+		let srv = &self.server.srv;
+		// PutImage header is 24 bytes
+		let max_bytes = srv.maximum_request_bytes() - 24;
+		let mut chunk = Vec::new();
+		for r in rects
+		{
+			let max_rows = (max_bytes / (r.width as usize * 4)).max(1) as i32;
+			let mut y = r.y;
+			while y < r.bottom()
+			{
+				let rows = max_rows.min(r.bottom() - y);
+				chunk.clear();
+				for yy in y .. y + rows { chunk.extend_from_slice(b.row_span(r, yy as _)); }
+				srv.put_image(self.handle.id(), &self.blitter.gc, (r.width as _, rows as _), (r.x as _, y as _),
+					self.blitter.depth, &chunk);
+				y += rows;
+			}
+		}
+	}
+}
+pub type NativeView<WE> = NativeWindow<WE>;
+impl<WE: WindowEventDelegate> Window for NativeWindow<WE>
 {
-    fn show(&self) { self.1.srv.map_window(&self.0).unwrap(); }
+	fn show(&self)
+	{
+		if let Err(e) = self.server.srv.map_window(&self.handle) { self.server.dg.protocol_error(&self.server.srv.decode_error(&e)); }
+	}
+	#[cfg(feature = "with_bedrock")]
+	fn mark_dirty(&self) { self.entry.schedule.mark_dirty(); }
+	fn mark_dirty_rect(&self, r: Rect) { self.entry.schedule.mark_dirty_rect(r); }
+	fn set_render_mode(&self, mode: RenderMode) { self.entry.schedule.set_mode(mode); }
+	fn capture(&self) -> Result<Image> { self.server.capture_window(self.handle.id(), &self.entry) }
+	fn start_drag(&self, data: DragData, allowed_effects: &[DropEffect]) -> bool
+	{
+		self.server.start_drag(self.handle.id(), data, allowed_effects)
+	}
+	/// The spot of the input context is the bottom-left corner of the area
+	fn set_ime_cursor_area(&self, area: Rect)
+	{
+		if let Some(ref x) = self.server.xim
+		{
+			x.set_spot(&self.server.srv, self.handle.id(), (area.x as i16, (area.y + area.height as i32) as i16));
+			self.server.srv.flush();
+		}
+	}
+	fn set_cursor(&self, icon: CursorIcon)
+	{
+		if let Some(c) = self.entry.custom_cursor.take() { self.server.srv.free_cursor(c); }
+		self.entry.cursor.set(icon);
+		self.server.apply_cursor(self.handle.id(), &self.entry);
+	}
+	/// Needs RENDER 0.5. Fails with `Error::ExtensionMissing` otherwise
+	fn set_custom_cursor(&self, image: &Image, hotspot: (u32, u32)) -> Result<()>
+	{
+		let format = self.server.argb_format.ok_or(Error::ExtensionMissing("RENDER"))?;
+		let pixels = (0 .. image.height).flat_map(|y| (0 .. image.width).map(move |x| (x, y))).map(|(x, y)|
+		{
+			let [r, g, b, a] = image.rgba_at(x, y);
+			let a = if image.has_alpha { a } else { 0xff };
+			(a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+		}).collect::<Vec<_>>();
+		let hotspot = (hotspot.0.min(image.width.saturating_sub(1)) as u16, hotspot.1.min(image.height.saturating_sub(1)) as u16);
+		let c = self.server.srv.create_argb_cursor(self.server.root_id, format, (image.width as _, image.height as _), hotspot, &pixels);
+		if let Some(old) = self.entry.custom_cursor.replace(Some(c)) { self.server.srv.free_cursor(old); }
+		self.server.apply_cursor(self.handle.id(), &self.entry);
+		Ok(())
+	}
+	fn set_cursor_visible(&self, visible: bool)
+	{
+		self.entry.cursor_visible.set(visible);
+		self.server.apply_cursor(self.handle.id(), &self.entry);
+	}
+	/// Raw motions need XInput 2.1
+	fn set_cursor_grab(&self, mode: GrabMode) -> bool { self.server.set_cursor_grab(self.handle.id(), mode) }
+}
+impl<WE: WindowEventDelegate> View for NativeView<WE>
+{
+	fn size(&self) -> (u16, u16) { self.entry.size.get() }
+	/// Pixels are laid out as in ZPixmap images of the TrueColor visual(usually `PixelFormat::Bgra8`)
+	fn pixel_buffer(&self) -> RefMut<'_, PixelBuffer>
+	{
+		let mut b = self.blitter.pixels.borrow_mut();
+		b.fit(self.width() as _, self.height() as _); b
+	}
+	/// Uploads through MIT-SHM if available, otherwise by PutImage. PutImage is used also while the server is still
+	/// reading the previous frame from the shared segment
+	fn present(&self, damage: &[Rect]) -> Result<()>
+	{
+		let b = self.pixel_buffer();
+		let rects = b.clip_damage(damage);
+		if rects.is_empty() { return Ok(()); }
+		if !self.upload_shm(&b, &rects) { self.upload_put_image(&b, &rects); }
+		self.server.srv.flush();
+		Ok(())
+	}
+}
+impl<WE: WindowEventDelegate> Drop for NativeWindow<WE>
+{
+	fn drop(&mut self)
+	{
+		self.server.windows.borrow_mut().remove(&self.handle.id());
+		if self.server.pointer_grab.get().is_some_and(|(w, _)| w == self.handle.id()) { self.server.release_cursor_grab(); }
+		if let Some(ref x) = self.server.xim { x.remove_window(&self.server.srv, self.handle.id()); }
+		if let Some(s) = self.blitter.shm.borrow_mut().take() { self.server.srv.destroy_shm_segment(s); }
+		self.blitter.gc.free(&self.server.srv);
+		// the server may have destroyed the window already(BadWindow in checked mode)
+		let _ = self.server.srv.destroy_window(&self.handle);
+		if let Some(c) = self.entry.custom_cursor.take() { self.server.srv.free_cursor(c); }
+	}
 }
-impl<E: EventDelegate> Drop for NativeWindow<E> { fn drop(&mut self) { self.1.srv.destroy_window(&self.0).unwrap(); } }
 pub struct NativeWindowBuilder<'c>
 {
-	pos: (i16, i16), size: (u16, u16), caption: &'c str, closable_: bool, resizable_: bool
+	pos: (i16, i16), size: (u16, u16), caption: &'c str, closable_: bool, resizable_: bool, transparent_: bool,
+	render_mode_: RenderMode
 }
 impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
 {
@@ -107,20 +1079,36 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
 	{
 		NativeWindowBuilder
 		{
-			pos: (0, 0), size: (width, height), caption, closable_: true, resizable_: true
+			pos: (0, 0), size: (width, height), caption, closable_: true, resizable_: true, transparent_: false,
+			render_mode_: RenderMode::default()
 		}
 	}
     /// Set window as closable(if true passed, default) or unclosable(if false passed)
     fn closable(&mut self, c: bool) -> &mut Self { self.closable_ = c; self }
     /// Set window as resizable(if true passed, default) or unresizable(if false passed)
     fn resizable(&mut self, c: bool) -> &mut Self { self.resizable_ = c; self }
+	/// Uses the 32-bit ARGB visual if the server has one. A compositing manager blends the window with the desktop;
+	/// the alpha of `pixel_buffer` is the 4th byte and colors are premultiplied by it
+	fn transparent(&mut self, c: bool) -> &mut Self { self.transparent_ = c; self }
+	fn render_mode(&mut self, mode: RenderMode) -> &mut Self { self.render_mode_ = mode; self }
 
     /// Create a window
-    fn create<E: EventDelegate>(&self, server: &Rc<GUIApplication<E>>) -> IOResult<NativeWindow<E>>
+    fn create<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
+		-> Result<NativeWindow<WE>>
 	{
+		let (depth, visual, colormap, format) = match server.argb_visual
+		{
+			Some((v, ref c, f)) if self.transparent_ => (32, v, c, f),
+			_ => (BITDEPTH as u8, server.desired_visualid, &server.colormap, server.pixel_format)
+		};
 		let mut vlist = rxcb::WindowValueList::new();
-		vlist/*.back_pixel(0).border_pixel(0)*/.colormap(&server.colormap);
-		if cfg!(feature = "manual_rendering") { vlist.eventmask(rxcb::XCB_EVENT_MASK_EXPOSURE); }
+		// windows of a depth other than the parent's need their own border. The background starts transparent
+		if depth != BITDEPTH as u8 { vlist.back_pixel(0).border_pixel(0); }
+		vlist.colormap(colormap).cursor(server.cursor(CursorIcon::Default))
+			.eventmask(rxcb::XCB_EVENT_MASK_EXPOSURE | rxcb::XCB_EVENT_MASK_STRUCTURE_NOTIFY |
+				rxcb::XCB_EVENT_MASK_KEY_PRESS | rxcb::XCB_EVENT_MASK_KEY_RELEASE |
+				rxcb::XCB_EVENT_MASK_BUTTON_PRESS | rxcb::XCB_EVENT_MASK_BUTTON_RELEASE |
+				rxcb::XCB_EVENT_MASK_POINTER_MOTION | rxcb::XCB_EVENT_MASK_FOCUS_CHANGE);
 		let mut allowed_actions = vec![
 			server.action_atoms.move_,
 			server.action_atoms.minimize,
@@ -136,21 +1124,50 @@ impl<'c> WindowBuilder<'c> for NativeWindowBuilder<'c>
 		if self.closable_ { allowed_actions.push(server.action_atoms.close); }
 		if self.resizable_ { allowed_actions.push(server.action_atoms.resize); }
 		let w = server.srv.new_window_id();
-		server.srv.create_window(Some(BITDEPTH as _), &w, Some(server.root_id), self.pos.0, self.pos.1,
-			self.size.0, self.size.1, 0, rxcb::WindowIOClass::InputOutput, Some(server.desired_visualid), &vlist)?;
+		server.srv.create_window(&w, rxcb::WindowParams
+		{
+			depth: Some(depth), parent: Some(server.root_id), pos: self.pos, size: self.size, border_width: 0,
+			class: rxcb::WindowIOClass::InputOutput, visual: Some(visual), values: &vlist
+		}).map_err(|e| server.srv.error(&e))?;
 		w.replace_property(&server.srv, server.wm_protocols, &server.wm_delete_window);
 		w.replace_property(&server.srv, rxcb::XCB_ATOM_WM_NAME, self.caption);
 		w.replace_property(&server.srv, server.action_atoms.allowed_actions, &allowed_actions[..]);
-		#[cfg(feature = "with_ferrite")] { Ok(NativeWindow(w, server.clone(), None)) }
-		#[cfg(not(feature = "with_ferrite"))] { Ok(NativeWindow(w, server.clone())) }
+		server.xdnd.make_aware(&server.srv, &w);
+		if let Some(ref x) = server.xim { x.add_window(&server.srv, w.id()); }
+		if let Some(ref x) = server.xinput { x.select_window(&server.srv, w.id()); }
+		let entry = Rc::new(WindowEntry
+		{
+			schedule: FrameSchedule::new(self.render_mode_), clock: FrameClock::new(), vsync: VsyncState::new(),
+			size: Cell::new(self.size), mapped: Cell::new(false), caption: self.caption.to_owned(), focused: Cell::new(false),
+			callbacks: Box::new(Rc::downgrade(event)),
+			cursor: Cell::new(CursorIcon::Default), custom_cursor: Cell::new(None), cursor_visible: Cell::new(true),
+			shm_busy: Cell::new(false), format
+		});
+		if server.present.is_some()
+		{
+			server.srv.present_select_input(server.srv.new_id(), &w, rxcb::XCB_PRESENT_EVENT_MASK_COMPLETE_NOTIFY);
+			entry.schedule.set_pacing(Pacing::External);
+		}
+		// Present is unavailable: paced by the monitor showing the window once it is mapped
+		else { entry.schedule.set_pacing(Pacing::Interval(refresh_interval(DEFAULT_REFRESH_RATE))); }
+		server.windows.borrow_mut().insert(w.id(), entry.clone());
+		server.created.borrow_mut().push(w.id());
+		let blitter = Blitter
+		{
+			gc: rxcb::GContext::new(&server.srv, w.id()),
+			pixels: RefCell::new(PixelBuffer::new(self.size.0 as _, self.size.1 as _, format)), depth,
+			shm: RefCell::new(None)
+		};
+		Ok(NativeWindow { handle: w, server: server.clone(), entry, blitter })
 	}
-    #[cfg(feature = "with_ferrite")]
+    #[cfg(feature = "with_bedrock")]
     /// Create a Renderable window
-    fn create_renderable<E: EventDelegate>(&self, server: &Rc<GUIApplication<E>>) -> IOResult<NativeWindow<E>>
+    fn create_renderable<WE: WindowEventDelegate>(&self, server: &Rc<GUIApplication<WE::ClientDelegate>>, event: &Rc<WE>)
+		-> Result<NativeWindow<WE>> where WE::ClientDelegate: 'static
 	{
-		let mut w = self.create(server)?;
-		let vc = FeViewController::new(server, &w);
-		w.2 = Some(vc); Ok(w)
+		let w = self.create(server, event)?;
+		event.init_view(&w);
+		Ok(w)
 	}
 }
 pub struct ActionAtoms
@@ -163,7 +1180,7 @@ pub struct ActionAtoms
 }
 impl ActionAtoms
 {
-	pub fn init(con: &rxcb::Connection) -> Result<Self, rxcb::GenericError>
+	pub fn init(con: &rxcb::Connection) -> ::std::result::Result<Self, rxcb::GenericError>
 	{
 		let aack = con.intern("_NET_WM_ALLOWED_ACTIONS");
 		let (mvc, rszc) = (con.intern("_NET_WM_ACTION_MOVE"), con.intern("_NET_WM_ACTION_RESIZE"));
@@ -185,12 +1202,21 @@ impl ActionAtoms
 	}
 }
 
-pub struct FeViewController<E: EventDelegate>(Weak<GUIApplication<E>>);
-impl<E: EventDelegate> FeViewController<E>
+#[cfg(test)]
+mod tests
 {
-	pub fn new(srv: &Rc<GUIApplication<E>>, w: &NativeWindow<E>) -> Self
+	use super::zpixmap_format;
+	use PixelFormat;
+
+	#[test]
+	fn pixel_formats_of_visuals()
 	{
-		srv.dg.on_init_view(srv, w);
-		FeViewController(Rc::downgrade(srv))
+		let (rgb, bgr) = ((0xff_0000, 0xff00, 0xff), (0xff, 0xff00, 0xff_0000));
+		assert_eq!(zpixmap_format(rgb, true), Some(PixelFormat::Bgra8));
+		assert_eq!(zpixmap_format(bgr, true), Some(PixelFormat::Rgba8));
+		// xRGB in big endian puts the padding byte first
+		assert_eq!(zpixmap_format(rgb, false), None);
+		assert_eq!(zpixmap_format((0xff00, 0xff_0000, 0xff00_0000), false), Some(PixelFormat::Bgra8));
+		assert_eq!(zpixmap_format((0xf800, 0x7e0, 0x1f), true), None);
 	}
 }
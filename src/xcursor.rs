@@ -0,0 +1,128 @@
+//! Cursor themes in the Xcursor format
+
+use std::fs::File;
+use std::io::{Read, BufRead, BufReader};
+use std::path::PathBuf;
+use std::env::var;
+use CursorIcon;
+
+const DEFAULT_PATH: &str = "~/.local/share/icons:~/.icons:/usr/share/icons:/usr/share/pixmaps";
+const DEFAULT_SIZE: u32 = 24;
+/// Limit of the `Inherits` chain
+const MAX_INHERIT_DEPTH: usize = 8;
+const IMAGE_CHUNK: u32 = 0xfffd_0002;
+
+/// A cursor image with premultiplied ARGB pixels
+pub struct CursorImage { pub width: u16, pub height: u16, pub hotspot: (u16, u16), pub pixels: Vec<u32> }
+
+/// The user's cursor theme, configured like libXcursor does
+pub struct Theme { name: String, size: u32, path: Vec<PathBuf> }
+impl Theme
+{
+	/// `resources` is the contents of the RESOURCE_MANAGER property
+	pub fn new(resources: &str) -> Self
+	{
+		let name = var("XCURSOR_THEME").ok().filter(|s| !s.is_empty())
+			.or_else(|| resource(resources, "Xcursor.theme").map(String::from)).unwrap_or_else(|| "default".to_owned());
+		let size = var("XCURSOR_SIZE").ok().and_then(|s| s.parse().ok())
+			.or_else(|| resource(resources, "Xcursor.size").and_then(|s| s.parse().ok()))
+			.or_else(|| resource(resources, "Xft.dpi").and_then(|s| s.parse::<f32>().ok()).map(|dpi| (dpi * 16.0 / 72.0) as u32))
+			.filter(|&s| s > 0).unwrap_or(DEFAULT_SIZE);
+		let home = var("HOME").unwrap_or_default();
+		let path = var("XCURSOR_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_owned()).split(':').filter(|d| !d.is_empty())
+			.map(|d| d.strip_prefix("~/").map_or_else(|| PathBuf::from(d), |rel| PathBuf::from(&home).join(rel))).collect();
+		Theme { name, size, path }
+	}
+	/// The image of the icon nearest to the configured size
+	pub fn load(&self, icon: CursorIcon) -> Option<CursorImage>
+	{
+		names(icon).iter().filter_map(|n| self.find(&self.name, n, 0)).filter_map(|p| read_file(p, self.size)).next()
+	}
+
+	fn find(&self, theme: &str, name: &str, depth: usize) -> Option<PathBuf>
+	{
+		if depth > MAX_INHERIT_DEPTH { return None; }
+		let found = self.path.iter().map(|d| d.join(theme).join("cursors").join(name)).find(|p| p.is_file());
+		found.or_else(|| self.inherits(theme).iter().filter_map(|t| self.find(t, name, depth + 1)).next())
+	}
+	/// Themes listed in the `Inherits` key of the first index.theme found
+	fn inherits(&self, theme: &str) -> Vec<String>
+	{
+		let f = match self.path.iter().filter_map(|d| File::open(d.join(theme).join("index.theme")).ok()).next()
+		{
+			Some(f) => f, None => return Vec::new()
+		};
+		for l in BufReader::new(f).lines().map_while(Result::ok)
+		{
+			let l = l.trim();
+			if !l.starts_with("Inherits") { continue; }
+			let v = match l.find('=') { Some(p) => &l[p + 1 ..], None => continue };
+			return v.split(&[',', ';'][..]).map(str::trim).filter(|t| !t.is_empty() && *t != theme)
+				.map(String::from).collect();
+		}
+		Vec::new()
+	}
+}
+
+/// Value of an entry like `Xcursor.theme: Adwaita` in the resource database
+fn resource<'s>(resources: &'s str, key: &str) -> Option<&'s str>
+{
+	resources.lines().filter_map(|l| { let p = l.find(':')?; Some((l[.. p].trim(), l[p + 1 ..].trim())) })
+		.find(|&(k, _)| k == key).map(|(_, v)| v)
+}
+/// Names of the icon in themes, CSS names first
+fn names(icon: CursorIcon) -> &'static [&'static str]
+{
+	match icon
+	{
+		CursorIcon::Default => &["default", "left_ptr"],
+		CursorIcon::Text => &["text", "xterm"],
+		CursorIcon::Hand => &["pointer", "hand2", "hand1"],
+		CursorIcon::Crosshair => &["crosshair", "cross"],
+		CursorIcon::Move => &["move", "fleur"],
+		CursorIcon::Wait => &["wait", "watch"],
+		CursorIcon::Progress => &["progress", "left_ptr_watch"],
+		CursorIcon::Help => &["help", "question_arrow"],
+		CursorIcon::NotAllowed => &["not-allowed", "crossed_circle"],
+		CursorIcon::Grab => &["grab", "openhand", "hand1"],
+		CursorIcon::Grabbing => &["grabbing", "closedhand", "fleur"],
+		CursorIcon::ResizeNS => &["ns-resize", "sb_v_double_arrow"],
+		CursorIcon::ResizeEW => &["ew-resize", "sb_h_double_arrow"],
+		CursorIcon::ResizeNESW => &["nesw-resize", "fd_double_arrow", "bottom_left_corner"],
+		CursorIcon::ResizeNWSE => &["nwse-resize", "bd_double_arrow", "bottom_right_corner"]
+	}
+}
+/// Glyph of the core cursor font used when no theme provides the icon
+pub fn core_glyph(icon: CursorIcon) -> u16
+{
+	match icon
+	{
+		CursorIcon::Default => 68, CursorIcon::Text => 152, CursorIcon::Hand => 60, CursorIcon::Crosshair => 34,
+		CursorIcon::Move | CursorIcon::Grabbing => 52, CursorIcon::Wait | CursorIcon::Progress => 150,
+		CursorIcon::Help => 92, CursorIcon::NotAllowed => 0, CursorIcon::Grab => 58,
+		CursorIcon::ResizeNS => 116, CursorIcon::ResizeEW => 108, CursorIcon::ResizeNESW => 12, CursorIcon::ResizeNWSE => 14
+	}
+}
+
+/// The first frame of the image nearest to `size` in an Xcursor file
+fn read_file(path: PathBuf, size: u32) -> Option<CursorImage>
+{
+	let mut bytes = Vec::new();
+	File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)).ok()?;
+	let u32_at = |o: usize| bytes.get(o .. o + 4).map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24);
+	if bytes.get(.. 4) != Some(b"Xcur") { return None; }
+	let header = u32_at(4)? as usize;
+	let ntoc = u32_at(12)? as usize;
+	let images = (0 .. ntoc).filter_map(|i| { let o = header + i * 12; Some((u32_at(o)?, u32_at(o + 4)?, u32_at(o + 8)?)) })
+		.filter(|&(t, _, _)| t == IMAGE_CHUNK).collect::<Vec<_>>();
+	let nearest = images.iter().map(|&(_, s, _)| s).min_by_key(|&s| (s as i64 - size as i64).abs())?;
+	let &(_, _, pos) = images.iter().find(|&&(_, s, _)| s == nearest)?;
+	let pos = pos as usize;
+	if u32_at(pos + 4)? != IMAGE_CHUNK { return None; }
+	let (width, height) = (u32_at(pos + 16)?, u32_at(pos + 20)?);
+	let (xhot, yhot) = (u32_at(pos + 24)?, u32_at(pos + 28)?);
+	if width == 0 || height == 0 || width > 0x7fff || height > 0x7fff || xhot >= width || yhot >= height { return None; }
+	let data = pos + u32_at(pos)? as usize;
+	let pixels = (0 .. (width * height) as usize).map(|i| u32_at(data + i * 4)).collect::<Option<Vec<_>>>()?;
+	Some(CursorImage { width: width as _, height: height as _, hotspot: (xhot as _, yhot as _), pixels })
+}
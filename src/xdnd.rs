@@ -0,0 +1,238 @@
+//! XDND(drag and drop between X clients, version 5): atoms, client messages and the source side of drags
+
+use rxcb;
+use DropEffect;
+use mime::TEXT_UTF8;
+use std::time::{Duration, Instant};
+
+/// Protocol version advertised by `XdndAware`
+pub const VERSION: u32 = 5;
+/// How long a source waits for XdndFinished after dropping
+const FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Client messages received by a drop target
+pub enum TargetMessage
+{
+	Enter { source: rxcb::WindowID, window: rxcb::WindowID, types: Vec<rxcb::Atom> },
+	/// `root` is the pointer position in the root window
+	Position { source: rxcb::WindowID, window: rxcb::WindowID, root: (i16, i16) },
+	Leave { source: rxcb::WindowID, window: rxcb::WindowID },
+	Drop { source: rxcb::WindowID, window: rxcb::WindowID, time: rxcb::Timestamp }
+}
+
+/// Client messages received by a drag source
+pub enum SourceMessage
+{
+	/// `effect` is None if the target refuses the drop
+	Status { target: rxcb::WindowID, effect: DropEffect },
+	Finished { target: rxcb::WindowID, effect: DropEffect }
+}
+
+/// A drag started by the application
+pub struct DragSource
+{
+	/// The window that started the drag and owns `XdndSelection`
+	window: rxcb::WindowID, types: Vec<rxcb::Atom>, allowed: Vec<DropEffect>,
+	/// (window, negotiated version) under the pointer
+	target: Option<(rxcb::WindowID, u32)>,
+	/// Effect of the last XdndStatus
+	effect: DropEffect,
+	/// An XdndPosition is not answered yet. Further positions are held in `pending`(root position, time)
+	waiting_status: bool, pending: Option<((i16, i16), rxcb::Timestamp)>,
+	/// XdndDrop was sent at
+	dropped: Option<Instant>
+}
+impl DragSource
+{
+	pub fn window(&self) -> rxcb::WindowID { self.window }
+	/// When to give up waiting for XdndFinished
+	pub fn deadline(&self) -> Option<Instant> { self.dropped.map(|t| t + FINISH_TIMEOUT) }
+	pub fn is_dropped(&self) -> bool { self.dropped.is_some() }
+}
+
+pub struct Xdnd
+{
+	aware: rxcb::Atom, enter: rxcb::Atom, position: rxcb::Atom, status: rxcb::Atom, leave: rxcb::Atom,
+	drop: rxcb::Atom, finished: rxcb::Atom, type_list: rxcb::Atom,
+	action_copy: rxcb::Atom, action_move: rxcb::Atom, action_link: rxcb::Atom
+}
+impl Xdnd
+{
+	pub fn new(srv: &rxcb::Connection) -> Result<Self, rxcb::GenericError>
+	{
+		let names = ["XdndAware", "XdndEnter", "XdndPosition", "XdndStatus", "XdndLeave", "XdndDrop", "XdndFinished",
+			"XdndTypeList", "XdndActionCopy", "XdndActionMove", "XdndActionLink"];
+		let cookies = names.iter().map(|n| srv.intern_create(n)).collect::<Vec<_>>();
+		let mut a = Vec::with_capacity(names.len());
+		for c in cookies { a.push(c.reply()?); }
+		Ok(Xdnd
+		{
+			aware: a[0], enter: a[1], position: a[2], status: a[3], leave: a[4], drop: a[5], finished: a[6],
+			type_list: a[7], action_copy: a[8], action_move: a[9], action_link: a[10]
+		})
+	}
+	/// Accept drops on a toplevel window
+	pub fn make_aware(&self, srv: &rxcb::Connection, w: &rxcb::Window)
+	{
+		w.replace_property(srv, self.aware, &(VERSION as rxcb::Atom));
+	}
+	/// Decode a message for a source
+	pub fn decode_source(&self, e: &rxcb::ClientMessageEvent) -> Option<SourceMessage>
+	{
+		let (t, d) = (e.msg_type(), e.data32());
+		let effect = |accepted: bool, a: rxcb::Atom| if accepted { self.effect(a) } else { DropEffect::None };
+		if t == self.status { Some(SourceMessage::Status { target: d[0], effect: effect((d[1] & 1) != 0, d[4]) }) }
+		else if t == self.finished { Some(SourceMessage::Finished { target: d[0], effect: effect((d[1] & 1) != 0, d[2]) }) }
+		else { None }
+	}
+	/// Decode a message for a target. Types beyond the third are read from the source's `XdndTypeList`
+	pub fn decode(&self, srv: &rxcb::Connection, e: &rxcb::ClientMessageEvent) -> Option<TargetMessage>
+	{
+		let (t, d, window) = (e.msg_type(), e.data32(), e.window());
+		if t == self.enter
+		{
+			let types = if (d[1] & 1) != 0
+			{
+				srv.get_property(d[0], self.type_list, false).map(|r| r.value32().to_owned()).unwrap_or_default()
+			}
+			else { d[2..].iter().cloned().filter(|&a| a != rxcb::XCB_ATOM_NONE).collect() };
+			Some(TargetMessage::Enter { source: d[0], window, types })
+		}
+		else if t == self.position
+		{
+			Some(TargetMessage::Position { source: d[0], window, root: ((d[2] >> 16) as i16, (d[2] & 0xffff) as i16) })
+		}
+		else if t == self.leave { Some(TargetMessage::Leave { source: d[0], window }) }
+		else if t == self.drop { Some(TargetMessage::Drop { source: d[0], window, time: d[2] }) }
+		else { None }
+	}
+	/// Reply to XdndPosition. Positions are requested continuously(no rectangle to skip)
+	pub fn send_status(&self, srv: &rxcb::Connection, source: rxcb::WindowID, target: rxcb::WindowID, effect: DropEffect)
+	{
+		let accept = if effect == DropEffect::None { 0 } else { 1 };
+		srv.send_client_message(source, source, self.status, [target, accept | 2, 0, 0, self.action(effect)]);
+	}
+	/// Tell the source that the drop has been processed
+	pub fn send_finished(&self, srv: &rxcb::Connection, source: rxcb::WindowID, target: rxcb::WindowID, effect: DropEffect)
+	{
+		let accepted = if effect == DropEffect::None { 0 } else { 1 };
+		srv.send_client_message(source, source, self.finished, [target, accepted, self.action(effect), 0, 0]);
+	}
+
+	/// Start dragging `types` from `window`. `allowed` is in order of preference
+	pub fn begin(&self, srv: &rxcb::Connection, window: rxcb::WindowID, types: Vec<rxcb::Atom>, allowed: &[DropEffect])
+		-> DragSource
+	{
+		// XdndEnter carries up to three types
+		if types.len() > 3 { srv.change_property32(window, self.type_list, rxcb::XCB_ATOM_ATOM, &types); }
+		DragSource
+		{
+			window, types, allowed: allowed.to_owned(), target: None, effect: DropEffect::None,
+			waiting_status: false, pending: None, dropped: None
+		}
+	}
+	/// The pointer moved to `root_pos` in `root`
+	pub fn drag_motion(&self, srv: &rxcb::Connection, root: rxcb::WindowID, s: &mut DragSource,
+		root_pos: (i16, i16), time: rxcb::Timestamp)
+	{
+		if s.dropped.is_some() { return; }
+		let target = self.find_target(srv, root);
+		if target.map(|t| t.0) != s.target.map(|t| t.0)
+		{
+			if let Some((w, _)) = s.target { srv.send_client_message(w, w, self.leave, [s.window, 0, 0, 0, 0]); }
+			if let Some((w, v)) = target
+			{
+				let mut d = [s.window, (v << 24) | (s.types.len() > 3) as u32, 0, 0, 0];
+				for (d, &t) in d[2..].iter_mut().zip(s.types.iter()) { *d = t; }
+				srv.send_client_message(w, w, self.enter, d);
+			}
+			s.target = target; s.effect = DropEffect::None; s.waiting_status = false; s.pending = None;
+		}
+		if s.target.is_none() { return; }
+		if s.waiting_status { s.pending = Some((root_pos, time)); } else { self.send_position(srv, s, root_pos, time); }
+	}
+	/// Handle XdndStatus or XdndFinished. Returns the final effect once the drag is over
+	pub fn drag_message(&self, srv: &rxcb::Connection, s: &mut DragSource, m: SourceMessage) -> Option<DropEffect>
+	{
+		match m
+		{
+			SourceMessage::Status { target, effect } =>
+			{
+				if s.target.map(|t| t.0) != Some(target) || s.dropped.is_some() { return None; }
+				// effects the application does not allow count as refusals
+				s.effect = if s.allowed.contains(&effect) { effect } else { DropEffect::None };
+				s.waiting_status = false;
+				if let Some((p, t)) = s.pending.take() { self.send_position(srv, s, p, t); }
+				None
+			},
+			SourceMessage::Finished { target, effect } =>
+			{
+				if s.target.map(|t| t.0) != Some(target) || s.dropped.is_none() { return None; }
+				// XdndFinished carries the performed action since version 5
+				let v = s.target.map_or(0, |t| t.1);
+				Some(if v >= 5 { if s.allowed.contains(&effect) { effect } else { DropEffect::None } } else { s.effect })
+			}
+		}
+	}
+	/// The button was released. Returns the final effect if the drag ends without a drop
+	pub fn drag_release(&self, srv: &rxcb::Connection, s: &mut DragSource, time: rxcb::Timestamp) -> Option<DropEffect>
+	{
+		if s.dropped.is_some() { return None; }
+		let w = match s.target { Some((w, _)) => w, None => return Some(DropEffect::None) };
+		if s.effect == DropEffect::None
+		{
+			srv.send_client_message(w, w, self.leave, [s.window, 0, 0, 0, 0]);
+			return Some(DropEffect::None);
+		}
+		srv.send_client_message(w, w, self.drop, [s.window, 0, time, 0, 0]);
+		s.dropped = Some(Instant::now());
+		None
+	}
+	fn send_position(&self, srv: &rxcb::Connection, s: &mut DragSource, root_pos: (i16, i16), time: rxcb::Timestamp)
+	{
+		let w = match s.target { Some((w, _)) => w, None => return };
+		let pos = ((root_pos.0 as u16 as u32) << 16) | root_pos.1 as u16 as u32;
+		let action = self.action(s.allowed.first().cloned().unwrap_or(DropEffect::Copy));
+		srv.send_client_message(w, w, self.position, [s.window, 0, pos, time, action]);
+		s.waiting_status = true;
+	}
+	/// The XDND-aware window under the pointer with the version to speak. `XdndProxy` is not followed
+	fn find_target(&self, srv: &rxcb::Connection, root: rxcb::WindowID) -> Option<(rxcb::WindowID, u32)>
+	{
+		let mut w = root;
+		while let Some(c) = srv.pointer_child(w)
+		{
+			if let Ok(p) = srv.get_property(c, self.aware, false)
+			{
+				if let Some(&v) = p.value32().first() { return if v >= 3 { Some((c, v.min(VERSION))) } else { None }; }
+			}
+			w = c;
+		}
+		None
+	}
+	fn effect(&self, a: rxcb::Atom) -> DropEffect
+	{
+		if a == self.action_copy { DropEffect::Copy }
+		else if a == self.action_move { DropEffect::Move }
+		else if a == self.action_link { DropEffect::Link }
+		else { DropEffect::None }
+	}
+	fn action(&self, e: DropEffect) -> rxcb::Atom
+	{
+		match e
+		{
+			DropEffect::None => rxcb::XCB_ATOM_NONE, DropEffect::Copy => self.action_copy,
+			DropEffect::Move => self.action_move, DropEffect::Link => self.action_link
+		}
+	}
+	/// MIME type of a drag type. None for types that are not MIME types(e.g. `STRING`)
+	pub fn format_name(&self, srv: &rxcb::Connection, a: rxcb::Atom) -> Option<String>
+	{
+		match srv.atom_name(a)
+		{
+			Ok(ref n) if n == "UTF8_STRING" => Some(TEXT_UTF8.to_owned()),
+			Ok(n) => if n.contains('/') { Some(n) } else { None },
+			Err(_) => None
+		}
+	}
+}
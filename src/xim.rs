@@ -0,0 +1,559 @@
+//! XIM(X11R6 Input Method Protocol) client over ClientMessages: an input context per window,
+//! key events filtered by the input method server, preedit callbacks and committed text
+//!
+//! The server is chosen by `@im=` of `XMODIFIERS`(the first registered one otherwise) and connected once at startup.
+//! Dynamic event flow(trigger keys) is not supported; key events are forwarded as requested by `XIM_SET_EVENT_MASK`.
+
+use rxcb;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+const XIM_CONNECT: u8 = 1; const XIM_CONNECT_REPLY: u8 = 2;
+const XIM_ERROR: u8 = 20;
+const XIM_OPEN: u8 = 30; const XIM_OPEN_REPLY: u8 = 31;
+const XIM_SET_EVENT_MASK: u8 = 37;
+const XIM_ENCODING_NEGOTIATION: u8 = 38; const XIM_ENCODING_NEGOTIATION_REPLY: u8 = 39;
+const XIM_GET_IM_VALUES: u8 = 44; const XIM_GET_IM_VALUES_REPLY: u8 = 45;
+const XIM_CREATE_IC: u8 = 50; const XIM_CREATE_IC_REPLY: u8 = 51; const XIM_DESTROY_IC: u8 = 52;
+const XIM_SET_IC_VALUES: u8 = 54;
+const XIM_SET_IC_FOCUS: u8 = 58; const XIM_UNSET_IC_FOCUS: u8 = 59;
+const XIM_FORWARD_EVENT: u8 = 60; const XIM_SYNC: u8 = 61; const XIM_SYNC_REPLY: u8 = 62; const XIM_COMMIT: u8 = 63;
+const XIM_PREEDIT_START: u8 = 73; const XIM_PREEDIT_START_REPLY: u8 = 74; const XIM_PREEDIT_DRAW: u8 = 75;
+const XIM_PREEDIT_CARET: u8 = 76; const XIM_PREEDIT_CARET_REPLY: u8 = 77; const XIM_PREEDIT_DONE: u8 = 78;
+
+/// Input styles in order of preference: preedit drawn by the application, at the spot or by the server
+const STYLES: [u32; 3] = [0x0002 | 0x0400, 0x0004 | 0x0400, 0x0008 | 0x0400];
+const PREEDIT_POSITION: u32 = 0x0004;
+const KEY_PRESS_MASK: u32 = 1; const KEY_RELEASE_MASK: u32 = 2;
+
+/// Results of messages from the server
+pub enum ImeEvent
+{
+	/// `cursor` is a byte range of `text`. Empty text ends the composition
+	Preedit { window: rxcb::WindowID, text: String, cursor: Option<(usize, usize)> },
+	Commit { window: rxcb::WindowID, text: String },
+	/// A key event the server did not consume
+	Key { window: rxcb::WindowID, keycode: u8, pressed: bool }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State { XConnecting, Connecting, Opening, Ready, Broken }
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding { Utf8, CompoundText }
+
+/// Input context of a window
+struct Ic
+{
+	window: rxcb::WindowID, id: Option<u16>, focused: bool, spot: Option<(i16, i16)>, forward_mask: u32,
+	preedit: Vec<char>, caret: usize
+}
+
+pub struct Xim
+{
+	window: rxcb::Window, xconnect: rxcb::Atom, protocol: rxcb::Atom, moredata: rxcb::Atom,
+	/// Owner of the server's selection, then the server's communication window
+	server: Cell<rxcb::WindowID>, state: Cell<State>, encoding: Cell<Encoding>, style: Cell<u32>, im: Cell<u16>,
+	/// (name, ID) of the IC attributes
+	ic_attrs: RefCell<Vec<(String, u16)>>, query_input_style: Cell<Option<u16>>,
+	ics: RefCell<Vec<Ic>>,
+	/// Windows waiting for XIM_CREATE_IC_REPLY, in order of the requests
+	creating: RefCell<VecDeque<rxcb::WindowID>>,
+	/// Message being received in pieces
+	incoming: RefCell<Vec<u8>>
+}
+impl Xim
+{
+	/// Connect to the input method server. None if no server is running
+	pub fn connect(srv: &rxcb::Connection, root: rxcb::WindowID) -> Option<Self>
+	{
+		let servers = srv.intern("XIM_SERVERS").reply().ok().filter(|&a| a != rxcb::XCB_ATOM_NONE)?;
+		let list = srv.get_property(root, servers, false).ok()?.value32().to_owned();
+		let names = list.iter().filter_map(|&a| srv.atom_name(a).ok().map(|n| (a, n))).collect::<Vec<_>>();
+		let wanted = ::std::env::var("XMODIFIERS").ok()
+			.and_then(|m| m.split('@').find(|p| p.starts_with("im=")).map(|p| format!("@server={}", &p[3..])));
+		let (selection, _) = names.iter().find(|n| Some(&n.1) == wanted.as_ref()).or_else(|| names.first())?.clone();
+		let server = srv.selection_owner(selection).ok().filter(|&w| w != rxcb::XCB_WINDOW_NONE)?;
+		let atoms = ["_XIM_XCONNECT", "_XIM_PROTOCOL", "_XIM_MOREDATA"].iter().map(|n| srv.intern_create(n)).collect::<Vec<_>>();
+		let mut a = Vec::with_capacity(3);
+		for c in atoms { a.push(c.reply().ok()?); }
+		let window = srv.new_window_id();
+		srv.create_window(&window, rxcb::WindowParams
+		{
+			depth: None, parent: Some(root), pos: (-1, -1), size: (1, 1), border_width: 0,
+			class: rxcb::WindowIOClass::InputOnly, visual: None, values: &rxcb::WindowValueList::new()
+		}).ok()?;
+		srv.send_client_message(server, server, a[0], [window.id(), 0, 0, 0, 0]);
+		Some(Xim
+		{
+			window, xconnect: a[0], protocol: a[1], moredata: a[2],
+			server: Cell::new(server), state: Cell::new(State::XConnecting), encoding: Cell::new(Encoding::CompoundText),
+			style: Cell::new(0), im: Cell::new(0), ic_attrs: RefCell::new(Vec::new()), query_input_style: Cell::new(None),
+			ics: RefCell::new(Vec::new()), creating: RefCell::new(VecDeque::new()), incoming: RefCell::new(Vec::new())
+		})
+	}
+
+	/// Create an input context for a window
+	pub fn add_window(&self, srv: &rxcb::Connection, window: rxcb::WindowID)
+	{
+		self.ics.borrow_mut().push(Ic
+		{
+			window, id: None, focused: false, spot: None, forward_mask: KEY_PRESS_MASK, preedit: Vec::new(), caret: 0
+		});
+		if self.state.get() == State::Ready { self.create_ic(srv, window); }
+	}
+	pub fn remove_window(&self, srv: &rxcb::Connection, window: rxcb::WindowID)
+	{
+		let ic = { let mut ics = self.ics.borrow_mut(); ics.iter().position(|c| c.window == window).map(|i| ics.remove(i)) };
+		if let Some(id) = ic.and_then(|c| c.id) { self.send_ic(srv, XIM_DESTROY_IC, id); }
+	}
+	pub fn set_focus(&self, srv: &rxcb::Connection, window: rxcb::WindowID, focused: bool)
+	{
+		let id = match self.ics.borrow_mut().iter_mut().find(|c| c.window == window)
+		{
+			Some(c) => { c.focused = focused; c.id }, None => return
+		};
+		if let Some(id) = id { self.send_ic(srv, if focused { XIM_SET_IC_FOCUS } else { XIM_UNSET_IC_FOCUS }, id); }
+	}
+	/// Place the candidate window. `spot` is the baseline of the insertion point in the window
+	pub fn set_spot(&self, srv: &rxcb::Connection, window: rxcb::WindowID, spot: (i16, i16))
+	{
+		let id = match self.ics.borrow_mut().iter_mut().find(|c| c.window == window)
+		{
+			Some(c) => { c.spot = Some(spot); c.id }, None => return
+		};
+		let (id, attrs) = match (id, self.preedit_attributes(Some(spot))) { (Some(id), Some(a)) => (id, a), _ => return };
+		let mut w = Writer::new();
+		w.u16(self.im.get()); w.u16(id); w.u16(attrs.len() as _); w.u16(0); w.bytes(&attrs);
+		self.send(srv, XIM_SET_IC_VALUES, w.0);
+	}
+	/// Forward a key event to the server if it asks for it. Returns true if the event is left to the server
+	pub fn filter_key(&self, srv: &rxcb::Connection, e: &rxcb::KeyEvent, pressed: bool) -> bool
+	{
+		let mask = if pressed { KEY_PRESS_MASK } else { KEY_RELEASE_MASK };
+		let id = match self.ics.borrow().iter().find(|c| c.window == e.window())
+		{
+			Some(&Ic { id: Some(id), focused: true, forward_mask, .. }) if (forward_mask & mask) != 0 => id,
+			_ => return false
+		};
+		let mut w = Writer::new();
+		// synchronous: the server answers with XIM_SYNC_REPLY or the event itself
+		w.u16(self.im.get()); w.u16(id); w.u16(1); w.u16(0); w.bytes(&e.raw());
+		self.send(srv, XIM_FORWARD_EVENT, w.0);
+		true
+	}
+
+	/// Handle a ClientMessage. None if it is not for the input method
+	pub fn handle_event(&self, srv: &rxcb::Connection, e: &rxcb::ClientMessageEvent) -> Option<Vec<ImeEvent>>
+	{
+		if e.window() != self.window.id() { return None; }
+		let mut out = Vec::new();
+		if e.msg_type() == self.xconnect
+		{
+			if self.state.get() != State::XConnecting { return Some(out); }
+			self.server.set(e.data32()[0]);
+			let mut w = Writer::new();
+			// byte order, protocol version 1.0, no authentication
+			w.u8(if cfg!(target_endian = "little") { 0x6c } else { 0x42 }); w.u8(0); w.u16(1); w.u16(0); w.u16(0);
+			self.send(srv, XIM_CONNECT, w.0);
+			self.state.set(State::Connecting);
+		}
+		else if e.msg_type() == self.moredata { self.incoming.borrow_mut().extend_from_slice(&e.data8()); }
+		else if e.msg_type() == self.protocol
+		{
+			if e.format() == 32
+			{
+				// (length, property) of a message written to the communication window
+				let d = e.data32();
+				let p = srv.get_property(self.window.id(), d[1], true).ok()?;
+				let v = p.value();
+				self.incoming.borrow_mut().extend_from_slice(&v[.. (d[0] as usize).min(v.len())]);
+			}
+			else { self.incoming.borrow_mut().extend_from_slice(&e.data8()); }
+			let msg = ::std::mem::take(&mut *self.incoming.borrow_mut());
+			if msg.len() >= 4
+			{
+				let len = 4 + u16::from_ne_bytes([msg[2], msg[3]]) as usize * 4;
+				if msg.len() >= len { self.process(srv, msg[0], &msg[4 .. len], &mut out); }
+			}
+		}
+		else { return None; }
+		Some(out)
+	}
+	fn process(&self, srv: &rxcb::Connection, major: u8, body: &[u8], out: &mut Vec<ImeEvent>)
+	{
+		let mut r = Reader(body);
+		match major
+		{
+			XIM_CONNECT_REPLY =>
+			{
+				let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().filter_map(|v| ::std::env::var(v).ok())
+					.find(|v| !v.is_empty()).unwrap_or_else(|| "C".to_owned());
+				let mut w = Writer::new();
+				w.u8(locale.len() as _); w.bytes(locale.as_bytes()); w.pad();
+				self.send(srv, XIM_OPEN, w.0);
+				self.state.set(State::Opening);
+			},
+			XIM_OPEN_REPLY => if self.opened(srv, &mut r).is_none() { self.state.set(State::Broken); },
+			XIM_ENCODING_NEGOTIATION_REPLY =>
+			{
+				// (im, category, index of the encoding)
+				let index = r.u16().and_then(|_| r.u16()).and_then(|_| r.u16());
+				self.encoding.set(if index == Some(0) { Encoding::Utf8 } else { Encoding::CompoundText });
+			},
+			XIM_GET_IM_VALUES_REPLY =>
+			{
+				let styles = self.input_styles(&mut r).unwrap_or_default();
+				match STYLES.iter().find(|s| styles.contains(s))
+				{
+					Some(&s) =>
+					{
+						self.style.set(s); self.state.set(State::Ready);
+						let windows = self.ics.borrow().iter().map(|c| c.window).collect::<Vec<_>>();
+						for w in windows { self.create_ic(srv, w); }
+					},
+					None => self.state.set(State::Broken)
+				}
+			},
+			XIM_CREATE_IC_REPLY =>
+			{
+				let id = match r.u16().and_then(|_| r.u16()) { Some(id) => id, None => return };
+				let window = match self.creating.borrow_mut().pop_front() { Some(w) => w, None => return };
+				let focused = match self.ics.borrow_mut().iter_mut().find(|c| c.window == window)
+				{
+					Some(c) => { c.id = Some(id); c.focused }, None => { self.send_ic(srv, XIM_DESTROY_IC, id); return }
+				};
+				if focused { self.send_ic(srv, XIM_SET_IC_FOCUS, id); }
+			},
+			XIM_ERROR =>
+			{
+				// (im, ic, flag: 1 = im valid, 2 = ic valid, ...)
+				let flag = r.u16().and_then(|_| r.u16()).and_then(|_| r.u16()).unwrap_or(0);
+				if self.state.get() != State::Ready { self.state.set(State::Broken); }
+				else if (flag & 2) == 0 { self.creating.borrow_mut().pop_front(); }
+			},
+			_ => if let (Some(_), Some(ic)) = (r.u16(), r.u16()) { self.process_ic(srv, major, ic, &mut r, out); }
+		}
+	}
+	/// Messages about an input context
+	fn process_ic(&self, srv: &rxcb::Connection, major: u8, ic: u16, r: &mut Reader, out: &mut Vec<ImeEvent>)
+	{
+		let mut ics = self.ics.borrow_mut();
+		let c = match ics.iter_mut().find(|c| c.id == Some(ic)) { Some(c) => c, None => return };
+		match major
+		{
+			XIM_SET_EVENT_MASK => if let Some(m) = r.u32() { c.forward_mask = m; },
+			XIM_SYNC => self.send_ic(srv, XIM_SYNC_REPLY, ic),
+			XIM_FORWARD_EVENT =>
+			{
+				let flag = r.u16().unwrap_or(0);
+				let ev = r.u16().and_then(|_| r.bytes(32));
+				if (flag & 1) != 0 { self.send_ic(srv, XIM_SYNC_REPLY, ic); }
+				// a core KeyPress/KeyRelease in our byte order
+				if let Some(ev) = ev.filter(|e| e[0] & 0x7f == 2 || e[0] & 0x7f == 3)
+				{
+					let window = u32::from_ne_bytes([ev[12], ev[13], ev[14], ev[15]]);
+					out.push(ImeEvent::Key { window, keycode: ev[1], pressed: ev[0] & 0x7f == 2 });
+				}
+			},
+			XIM_COMMIT =>
+			{
+				let flag = r.u16().unwrap_or(0);
+				// 4: with a keysym, 2: with a string
+				if (flag & 4) != 0 { r.u16(); r.u32(); }
+				let text = if (flag & 2) != 0 { r.u16().and_then(|n| r.bytes(n as _)).map(|t| self.decode(t)) } else { None };
+				if (flag & 1) != 0 { self.send_ic(srv, XIM_SYNC_REPLY, ic); }
+				if let Some(text) = text.filter(|t| !t.is_empty()) { out.push(ImeEvent::Commit { window: c.window, text }); }
+			},
+			XIM_PREEDIT_START =>
+			{
+				let mut w = Writer::new();
+				// no limit on the length of the preedit
+				w.u16(self.im.get()); w.u16(ic); w.u32(!0);
+				self.send(srv, XIM_PREEDIT_START_REPLY, w.0);
+			},
+			XIM_PREEDIT_DRAW =>
+			{
+				let (caret, first, length, status) = match (r.u32(), r.u32(), r.u32(), r.u32())
+				{
+					(Some(a), Some(b), Some(c), Some(d)) => (a as i32, b as i32, c as i32, d), _ => return
+				};
+				// status 1: no string
+				let text = if (status & 1) != 0 { String::new() }
+					else { r.u16().and_then(|n| r.bytes(n as _)).map(|t| self.decode(t)).unwrap_or_default() };
+				let first = (first.max(0) as usize).min(c.preedit.len());
+				let end = (first + length.max(0) as usize).min(c.preedit.len());
+				c.preedit.splice(first .. end, text.chars());
+				c.caret = (caret.max(0) as usize).min(c.preedit.len());
+				out.push(preedit_event(c));
+			},
+			XIM_PREEDIT_CARET =>
+			{
+				let (position, direction) = (r.u32().unwrap_or(0) as i32, r.u32().unwrap_or(11));
+				c.caret = match direction
+				{
+					0 => c.caret + 1, 1 => c.caret.saturating_sub(1),
+					10 => position.max(0) as usize, _ => c.caret
+				}.min(c.preedit.len());
+				let mut w = Writer::new();
+				w.u16(self.im.get()); w.u16(ic); w.u32(c.caret as _);
+				self.send(srv, XIM_PREEDIT_CARET_REPLY, w.0);
+				out.push(preedit_event(c));
+			},
+			XIM_PREEDIT_DONE =>
+			{
+				c.preedit.clear(); c.caret = 0;
+				out.push(preedit_event(c));
+			},
+			_ => ()
+		}
+	}
+	/// XIM_OPEN_REPLY: remember the attributes and negotiate the encoding and the input style
+	fn opened(&self, srv: &rxcb::Connection, r: &mut Reader) -> Option<()>
+	{
+		self.im.set(r.u16()?);
+		let im_attrs = read_attrs(r.u16().and_then(|n| r.bytes(n as _))?)?;
+		let ic_attrs = r.u16().and_then(|n| { r.u16(); r.bytes(n as _) }).and_then(read_attrs)?;
+		self.query_input_style.set(im_attrs.iter().find(|a| a.0 == "queryInputStyle").map(|a| a.1));
+		*self.ic_attrs.borrow_mut() = ic_attrs;
+
+		let mut encodings = Writer::new();
+		for e in &["UTF-8", "COMPOUND_TEXT"] { encodings.u8(e.len() as _); encodings.bytes(e.as_bytes()); }
+		let mut w = Writer::new();
+		w.u16(self.im.get()); w.u16(encodings.0.len() as _); w.bytes(&encodings.0); w.pad(); w.u16(0); w.u16(0);
+		self.send(srv, XIM_ENCODING_NEGOTIATION, w.0);
+
+		let mut w = Writer::new();
+		w.u16(self.im.get()); w.u16(2); w.u16(self.query_input_style.get()?); w.pad();
+		self.send(srv, XIM_GET_IM_VALUES, w.0);
+		Some(())
+	}
+	/// Styles in XIM_GET_IM_VALUES_REPLY
+	fn input_styles(&self, r: &mut Reader) -> Option<Vec<u32>>
+	{
+		r.u16()?;
+		let mut values = Reader(r.u16().and_then(|n| r.bytes(n as _))?);
+		while let Some(id) = values.u16()
+		{
+			let mut v = Reader(values.u16().and_then(|n| { let v = values.bytes(n as _); values.align(n as _); v })?);
+			if Some(id) != self.query_input_style.get() { continue; }
+			let n = v.u16()?; v.u16();
+			return (0 .. n).map(|_| v.u32()).collect();
+		}
+		None
+	}
+	fn create_ic(&self, srv: &rxcb::Connection, window: rxcb::WindowID)
+	{
+		let spot = self.ics.borrow().iter().find(|c| c.window == window).and_then(|c| c.spot);
+		let mut attrs = Writer::new();
+		for &(name, value) in &[("inputStyle", self.style.get()), ("clientWindow", window), ("focusWindow", window)]
+		{
+			if let Some(id) = self.ic_attr(name) { attrs.attr(id, &value.to_ne_bytes()); }
+		}
+		// the spot is required by the position style
+		let spot = if (self.style.get() & PREEDIT_POSITION) != 0 { Some(spot.unwrap_or((0, 0))) } else { spot };
+		if let Some(p) = self.preedit_attributes(spot) { attrs.bytes(&p); }
+		let mut w = Writer::new();
+		w.u16(self.im.get()); w.u16(attrs.0.len() as _); w.bytes(&attrs.0);
+		self.send(srv, XIM_CREATE_IC, w.0);
+		self.creating.borrow_mut().push_back(window);
+	}
+	/// `preeditAttributes` with the spot location
+	fn preedit_attributes(&self, spot: Option<(i16, i16)>) -> Option<Vec<u8>>
+	{
+		let (x, y) = spot?;
+		let (nested, spot_id) = (self.ic_attr("preeditAttributes")?, self.ic_attr("spotLocation")?);
+		let mut point = Writer::new();
+		point.u16(x as _); point.u16(y as _);
+		let mut inner = Writer::new();
+		inner.attr(spot_id, &point.0);
+		let mut w = Writer::new();
+		w.attr(nested, &inner.0);
+		Some(w.0)
+	}
+	fn ic_attr(&self, name: &str) -> Option<u16> { self.ic_attrs.borrow().iter().find(|a| a.0 == name).map(|a| a.1) }
+	/// Requests consisting of the input method and input context IDs
+	fn send_ic(&self, srv: &rxcb::Connection, major: u8, ic: u16)
+	{
+		let mut w = Writer::new();
+		w.u16(self.im.get()); w.u16(ic);
+		self.send(srv, major, w.0);
+	}
+	/// Send a message in 20 byte pieces
+	fn send(&self, srv: &rxcb::Connection, major: u8, body: Vec<u8>)
+	{
+		let mut msg = Writer::new();
+		msg.u8(major); msg.u8(0); msg.u16(body.len().div_ceil(4) as _); msg.bytes(&body); msg.pad();
+		let server = self.server.get();
+		let mut chunks = msg.0.chunks(20).peekable();
+		while let Some(c) = chunks.next()
+		{
+			let mut data = [0u8; 20];
+			data[.. c.len()].copy_from_slice(c);
+			let type_ = if chunks.peek().is_some() { self.moredata } else { self.protocol };
+			srv.send_client_message8(server, server, type_, data);
+		}
+	}
+	/// Text in the negotiated encoding
+	fn decode(&self, t: &[u8]) -> String
+	{
+		match self.encoding.get() { Encoding::Utf8 => String::from_utf8_lossy(t).into_owned(), Encoding::CompoundText => compound_text(t) }
+	}
+}
+
+fn preedit_event(c: &Ic) -> ImeEvent
+{
+	let text = c.preedit.iter().collect::<String>();
+	let caret = c.preedit[.. c.caret].iter().map(|c| c.len_utf8()).sum();
+	let cursor = if text.is_empty() { None } else { Some((caret, caret)) };
+	ImeEvent::Preedit { window: c.window, text, cursor }
+}
+/// LISTofXIMATTR/XICATTR: (name, ID)
+fn read_attrs(b: &[u8]) -> Option<Vec<(String, u16)>>
+{
+	let mut r = Reader(b);
+	let mut attrs = Vec::new();
+	while let Some(id) = r.u16()
+	{
+		r.u16()?;
+		let n = r.u16()? as usize;
+		attrs.push((String::from_utf8_lossy(r.bytes(n)?).into_owned(), id));
+		r.align(2 + n);
+	}
+	Some(attrs)
+}
+/// Decode COMPOUND_TEXT. ASCII, Latin-1 and UTF-8 segments are supported; other character sets become U+FFFD
+fn compound_text(b: &[u8]) -> String
+{
+	let (mut s, mut i, mut gl, mut gr) = (String::new(), 0, true, true);
+	while i < b.len()
+	{
+		if b[i] == 0x1b
+		{
+			// ESC % G ... ESC % @: UTF-8
+			if b[i + 1 ..].starts_with(b"%G")
+			{
+				let rest = &b[i + 3 ..];
+				let end = (0 .. rest.len()).find(|&j| rest[j ..].starts_with(b"\x1b%@")).unwrap_or(rest.len());
+				s.push_str(&String::from_utf8_lossy(&rest[.. end]));
+				i += 3 + end + 3;
+				continue;
+			}
+			// designation: intermediate bytes and a final byte
+			let len = b[i + 1 ..].iter().position(|&c| c >= 0x30).map_or(b.len() - i, |p| p + 2);
+			let seq = &b[i + 1 .. (i + len).min(b.len())];
+			match seq.first()
+			{
+				Some(&b'(') => gl = seq == b"(B" || seq == b"(J",
+				Some(&b'-') => gr = seq == b"-A",
+				Some(&b'$') => if seq.get(1) == Some(&b')') { gr = false; } else { gl = false; },
+				_ => ()
+			}
+			i += len;
+			continue;
+		}
+		let c = b[i];
+		let ok = if c < 0x80 { gl || c < 0x20 } else { gr };
+		if ok { s.push(c as char); } else if !s.ends_with('\u{fffd}') { s.push('\u{fffd}'); }
+		i += 1;
+	}
+	s
+}
+
+/// Message body in the client's(native) byte order
+struct Writer(Vec<u8>);
+impl Writer
+{
+	fn new() -> Self { Writer(Vec::new()) }
+	fn u8(&mut self, v: u8) { self.0.push(v); }
+	fn u16(&mut self, v: u16) { self.0.extend_from_slice(&v.to_ne_bytes()); }
+	fn u32(&mut self, v: u32) { self.0.extend_from_slice(&v.to_ne_bytes()); }
+	fn bytes(&mut self, b: &[u8]) { self.0.extend_from_slice(b); }
+	/// Pad to a multiple of 4 bytes
+	fn pad(&mut self) { while !self.0.len().is_multiple_of(4) { self.0.push(0); } }
+	/// XICATTRIBUTE: ID, length, value and padding
+	fn attr(&mut self, id: u16, value: &[u8])
+	{
+		self.u16(id); self.u16(value.len() as _); self.bytes(value);
+		for _ in 0 .. (4 - value.len() % 4) % 4 { self.0.push(0); }
+	}
+}
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a>
+{
+	fn bytes(&mut self, n: usize) -> Option<&'a [u8]>
+	{
+		if self.0.len() < n { return None; }
+		let (h, t) = self.0.split_at(n); self.0 = t; Some(h)
+	}
+	fn u16(&mut self) -> Option<u16> { self.bytes(2).map(|b| u16::from_ne_bytes([b[0], b[1]])) }
+	fn u32(&mut self) -> Option<u32> { self.bytes(4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]])) }
+	/// Skip the padding after `n` bytes
+	fn align(&mut self, n: usize) { let p = (4 - n % 4) % 4; self.bytes(p.min(self.0.len())); }
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn readers_read_what_writers_write()
+	{
+		let mut w = Writer::new();
+		w.u8(7); w.pad(); w.u16(0x1234); w.u32(0xdead_beef); w.attr(3, b"abcde"); w.bytes(b"xy");
+		assert_eq!(w.0.len(), 4 + 2 + 4 + 4 + 8 + 2);
+		let mut r = Reader(&w.0);
+		assert_eq!(r.bytes(1), Some(&[7][..]));
+		r.align(1);
+		assert_eq!(r.u16(), Some(0x1234));
+		assert_eq!(r.u32(), Some(0xdead_beef));
+		assert_eq!((r.u16(), r.u16()), (Some(3), Some(5)));
+		assert_eq!(r.bytes(5), Some(&b"abcde"[..]));
+		r.align(5);
+		assert_eq!(r.bytes(2), Some(&b"xy"[..]));
+		assert_eq!(r.u16(), None);
+		// padding past the end is ignored
+		r.align(1);
+		assert_eq!(r.bytes(0), Some(&[][..]));
+	}
+	#[test]
+	fn attribute_lists_are_parsed()
+	{
+		let mut w = Writer::new();
+		for &(id, name) in &[(1u16, "queryInputStyle"), (2, "inputStyle"), (3, "spotLocation")]
+		{
+			w.u16(id); w.u16(10); w.u16(name.len() as _); w.bytes(name.as_bytes());
+			while !(w.0.len() - 4).is_multiple_of(4) { w.u8(0); }
+		}
+		assert_eq!(read_attrs(&w.0), Some(vec![
+			("queryInputStyle".to_owned(), 1), ("inputStyle".to_owned(), 2), ("spotLocation".to_owned(), 3)
+		]));
+		// a name cut short
+		assert_eq!(read_attrs(&w.0[.. 10]), None);
+	}
+	#[test]
+	fn compound_text_segments_are_decoded()
+	{
+		assert_eq!(compound_text(b"plain\ttext"), "plain\ttext");
+		// ISO 8859-1 right half
+		assert_eq!(compound_text(b"\x1b-Acaf\xe9"), "café");
+		assert_eq!(compound_text(b"a\x1b%G\xe6\x97\xa5\xe6\x9c\xac\x1b%@b"), "a日本b");
+		// an unterminated UTF-8 segment runs to the end
+		assert_eq!(compound_text(b"\x1b%G\xe3\x81\x82"), "あ");
+		// JIS X 0208 is not supported; a run of it becomes one replacement character
+		assert_eq!(compound_text(b"\x1b$(B\x46\x7c\x4b\x5c\x1b(Bok"), "\u{fffd}ok");
+		assert_eq!(compound_text(b"\x1b-M\xe9"), "\u{fffd}");
+		assert_eq!(compound_text(b"end\x1b"), "end");
+	}
+	#[test]
+	fn preedit_cursors_are_byte_offsets()
+	{
+		let mut ic = Ic { window: 1, id: None, focused: true, spot: None, forward_mask: 0, preedit: "aにほ".chars().collect(), caret: 2 };
+		match preedit_event(&ic)
+		{
+			ImeEvent::Preedit { window: 1, ref text, cursor: Some((4, 4)) } if text == "aにほ" => (), _ => panic!()
+		}
+		ic.preedit.clear(); ic.caret = 0;
+		match preedit_event(&ic) { ImeEvent::Preedit { cursor: None, ref text, .. } if text.is_empty() => (), _ => panic!() }
+	}
+}
@@ -0,0 +1,499 @@
+//! XInput 2 events
+
+use rxcb::{self, GenericEvent, XInputExtension, Atom, WindowID};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use {TouchPhase, Gesture, ScrollPhase, ScrollSource};
+
+const XI_BUTTON_PRESS: u16 = 4;
+const XI_BUTTON_RELEASE: u16 = 5;
+const XI_MOTION: u16 = 6;
+const XI_ENTER: u16 = 7;
+const XI_HIERARCHY_CHANGED: u16 = 11;
+const XI_RAW_MOTION: u16 = 17;
+const XI_TOUCH_BEGIN: u16 = 18;
+const XI_TOUCH_UPDATE: u16 = 19;
+const XI_TOUCH_END: u16 = 20;
+const XI_GESTURE_PINCH_BEGIN: u16 = 27;
+const XI_GESTURE_PINCH_END: u16 = 29;
+const XI_GESTURE_SWIPE_BEGIN: u16 = 30;
+const XI_GESTURE_SWIPE_END: u16 = 32;
+/// Flag of the gesture end events
+const XI_GESTURE_CANCELLED: u32 = 1;
+
+const XI_SLAVE_POINTER: u16 = 3;
+const XI_VALUATOR_CLASS: u16 = 2;
+const XI_SCROLL_CLASS: u16 = 3;
+const XI_SCROLL_TYPE_HORIZONTAL: u16 = 2;
+/// Flag of button events emulated from scroll valuators
+const XI_POINTER_EMULATED: u32 = 1 << 16;
+/// Touchpads report no end of scrolls: a scroll is taken to end after a pause this long. A guess, longer than
+/// the interval of touchpad events(about 10 ms) and short enough for an end to follow the lifting of the fingers
+const SCROLL_END_DELAY: Duration = Duration::from_millis(100);
+
+/// An XI2 event decoded for the windows
+pub enum XIEvent
+{
+	/// Unaccelerated movement of a master pointer
+	RawMotion { dx: f32, dy: f32 },
+	Touch { window: WindowID, phase: TouchPhase, id: u32, x: f32, y: f32 },
+	/// `pressure` and `tilt` are normalized by the ranges of the axes
+	Pen { window: WindowID, x: f32, y: f32, pressure: f32, tilt: (f32, f32), buttons: u32, eraser: bool },
+	Gesture { window: WindowID, gesture: Gesture, phase: TouchPhase },
+	/// Notches scrolled, up and left positive
+	Scroll { window: WindowID, dx: f32, dy: f32, phase: ScrollPhase, source: ScrollSource },
+	/// Devices were added or removed. `ended` ends the scrolls in progress, which the devices queried anew forget.
+	/// Windows must be selected again
+	DevicesChanged { ended: Vec<XIEvent> }
+}
+
+/// An axis of a device
+struct Valuator { number: usize, label: Atom, min: f64, max: f64 }
+impl Valuator
+{
+	fn normalize(&self, v: f64) -> f64 { if self.max > self.min { (v - self.min) / (self.max - self.min) } else { 0.0 } }
+}
+/// A slave pointer with a pressure axis
+struct Pen
+{
+	eraser: bool, pressure: Valuator, tilt: (Option<Valuator>, Option<Valuator>),
+	/// Last values, as events carry changed axes only
+	last: RefCell<(f32, (f32, f32))>
+}
+/// A valuator that scrolls(XI 2.1)
+struct ScrollAxis
+{
+	number: usize, horizontal: bool, increment: f64,
+	/// None until a reference value arrives after the pointer enters a window
+	last: Cell<Option<f64>>
+}
+impl ScrollAxis
+{
+	/// Notches scrolled since the last value, up and left positive. None for the first value, which is the reference
+	fn notches(&self, v: f64) -> Option<f32>
+	{
+		// values grow downwards and rightwards
+		self.last.replace(Some(v)).map(|last| -((v - last) / self.increment) as f32)
+	}
+}
+/// A slave pointer. Its device events are selected besides the core events of its master
+struct Device
+{
+	pen: Option<Pen>, scroll: Vec<ScrollAxis>, source: ScrollSource,
+	/// Window and time of the last scroll of a touchpad, until it ends
+	scrolling: Cell<Option<(WindowID, Instant)>>
+}
+/// Axis labels of the evdev and libinput drivers(`Abs Pressure` and so on). Devices are pens if they label a pressure axis so;
+/// drivers labelling it otherwise are not recognized
+struct Labels { pressure: Atom, tilt_x: Atom, tilt_y: Atom }
+
+pub struct XInput
+{
+	ext: XInputExtension, labels: Labels, devices: RefCell<HashMap<u16, Device>>,
+	/// Sum of the angle deltas of the current pinch
+	pinch_rotation: Cell<f32>
+}
+impl XInput
+{
+	/// Devices are watched on the root window
+	pub fn new(srv: &rxcb::Connection, root: WindowID) -> Option<Self>
+	{
+		let ext = srv.xinput_extension()?;
+		let intern = |n| srv.intern(n).reply().unwrap_or(rxcb::XCB_ATOM_NONE);
+		let labels = Labels { pressure: intern("Abs Pressure"), tilt_x: intern("Abs Tilt X"), tilt_y: intern("Abs Tilt Y") };
+		let x = XInput { ext, labels, devices: RefCell::new(HashMap::new()), pinch_rotation: Cell::new(0.0) };
+		x.query_devices(srv);
+		srv.xi_select_events(root, &[(rxcb::XI_ALL_DEVICES, &[1 << XI_HIERARCHY_CHANGED])]);
+		Some(x)
+	}
+	/// Raw events are selected on the root window and delivered regardless of grabs(XI 2.1)
+	pub fn select_raw_motion(&self, srv: &rxcb::Connection, root: WindowID, enable: bool)
+	{
+		srv.xi_select_events(root, &[(rxcb::XI_ALL_MASTER_DEVICES, &[if enable { 1 << XI_RAW_MOTION } else { 0 }])]);
+	}
+	/// Raw motions are reported during grabs since XI 2.1
+	pub fn raw_motion_available(&self) -> bool { self.ext.version() >= (2, 1) }
+	/// Touchpad gestures need XI 2.4
+	pub fn gestures_available(&self) -> bool { self.ext.version() >= (2, 4) }
+	/// Scroll valuators need XI 2.1. Wheels are reported by device events then, and core wheel buttons are duplicates
+	pub fn smooth_scroll_available(&self) -> bool { self.ext.version() >= (2, 1) }
+	/// Select touches(XI 2.2), gestures(XI 2.4) and the device events of slave pointers. Core pointer events are delivered as before
+	pub fn select_window(&self, srv: &rxcb::Connection, w: WindowID)
+	{
+		// scroll valuators are relative to the values when the pointer enters
+		let mut master = [if self.smooth_scroll_available() { 1 << XI_ENTER } else { 0 }, 0];
+		if self.ext.version() >= (2, 2) { master[0] |= 1 << XI_TOUCH_BEGIN | 1 << XI_TOUCH_UPDATE | 1 << XI_TOUCH_END; }
+		if self.gestures_available()
+		{
+			for t in XI_GESTURE_PINCH_BEGIN ..= XI_GESTURE_SWIPE_END { master[t as usize / 32] |= 1 << (t % 32); }
+		}
+		let device = [1 << XI_BUTTON_PRESS | 1 << XI_BUTTON_RELEASE | 1 << XI_MOTION];
+		let mut masks = vec![(rxcb::XI_ALL_MASTER_DEVICES, &master[..])];
+		// events of slaves are delivered besides the core events of their masters
+		masks.extend(self.devices.borrow().keys().map(|&id| (id, &device[..])));
+		srv.xi_select_events(w, &masks);
+	}
+	pub fn decode(&self, srv: &rxcb::Connection, e: &GenericEvent) -> Option<XIEvent>
+	{
+		let d = e.ge_data();
+		match self.ext.event_type(e)?
+		{
+			XI_RAW_MOTION =>
+			{
+				// valuators 0 and 1 are the axes of the pointer. Raw values follow the processed ones
+				let v = Valuators::new(d, u16_at(d, 22)? as usize, 36)?;
+				let raw = v.values + v.count() * 8;
+				let axis = |n| v.index_of(n).and_then(|i| fp3232_at(d, raw + i * 8)).unwrap_or(0.0) as f32;
+				Some(XIEvent::RawMotion { dx: axis(0), dy: axis(1) })
+			},
+			t @ XI_TOUCH_BEGIN ..= XI_TOUCH_END =>
+			{
+				let phase = match t { XI_TOUCH_BEGIN => TouchPhase::Started, XI_TOUCH_UPDATE => TouchPhase::Moved, _ => TouchPhase::Ended };
+				let e = DeviceEvent::new(d)?;
+				Some(XIEvent::Touch { window: e.window, phase, id: e.detail, x: e.pos.0, y: e.pos.1 })
+			},
+			XI_ENTER =>
+			{
+				for a in self.devices.borrow().values().flat_map(|d| d.scroll.iter()) { a.last.set(None); }
+				None
+			},
+			t @ XI_BUTTON_PRESS ..= XI_MOTION =>
+			{
+				let e = DeviceEvent::new(d)?;
+				let devices = self.devices.borrow();
+				let device = devices.get(&e.deviceid)?;
+				if t == XI_MOTION && device.scroll.iter().any(|a| e.valuators.index_of(a.number).is_some())
+				{
+					return self.scroll(d, &e, device);
+				}
+				let pen = match device.pen
+				{
+					Some(ref p) => p,
+					// wheels without scroll valuators. Emulated presses duplicate the motions of the valuators
+					None => return match (t, e.detail)
+					{
+						(XI_BUTTON_PRESS, 4 ..= 7) if u32_at(d, 60)? & XI_POINTER_EMULATED == 0 =>
+						{
+							let (dx, dy) = match e.detail { 4 => (0.0, 1.0), 5 => (0.0, -1.0), 6 => (1.0, 0.0), _ => (-1.0, 0.0) };
+							Some(XIEvent::Scroll { window: e.window, dx, dy, phase: ScrollPhase::Moved, source: ScrollSource::Wheel })
+						},
+						_ => None
+					}
+				};
+				let value = |v: &Valuator| e.valuators.index_of(v.number).and_then(|i| fp3232_at(d, e.valuators.values + i * 8))
+					.map(|x| v.normalize(x) as f32);
+				let mut last = pen.last.borrow_mut();
+				if let Some(p) = value(&pen.pressure) { last.0 = p; }
+				if let Some(t) = pen.tilt.0.as_ref().and_then(&value) { (last.1).0 = t * 2.0 - 1.0; }
+				if let Some(t) = pen.tilt.1.as_ref().and_then(&value) { (last.1).1 = t * 2.0 - 1.0; }
+				// the button mask is the state before the event. Bit n is the button n
+				let mut buttons = e.buttons >> 1;
+				match t
+				{
+					XI_BUTTON_PRESS if e.detail > 0 => buttons |= 1u32.checked_shl(e.detail - 1).unwrap_or(0),
+					XI_BUTTON_RELEASE if e.detail > 0 => buttons &= !1u32.checked_shl(e.detail - 1).unwrap_or(0),
+					_ => ()
+				}
+				Some(XIEvent::Pen
+				{
+					window: e.window, x: e.pos.0, y: e.pos.1, pressure: last.0, tilt: last.1, buttons, eraser: pen.eraser
+				})
+			},
+			t @ XI_GESTURE_PINCH_BEGIN ..= XI_GESTURE_PINCH_END =>
+			{
+				// the scale is relative to the begin, the angle to the previous event
+				let phase = gesture_phase(t - XI_GESTURE_PINCH_BEGIN, u32_at(d, 100)?);
+				if phase == TouchPhase::Started { self.pinch_rotation.set(0.0); }
+				let rotation = self.pinch_rotation.get() + fp1616_at(d, 72)?;
+				self.pinch_rotation.set(rotation);
+				Some(XIEvent::Gesture { window: u32_at(d, 24)?, gesture: Gesture::Pinch { scale: fp1616_at(d, 68)?, rotation }, phase })
+			},
+			t @ XI_GESTURE_SWIPE_BEGIN ..= XI_GESTURE_SWIPE_END =>
+			{
+				let gesture = Gesture::Swipe { dx: fp1616_at(d, 52)?, dy: fp1616_at(d, 56)?, fingers: u32_at(d, 16)? };
+				Some(XIEvent::Gesture { window: u32_at(d, 24)?, gesture, phase: gesture_phase(t - XI_GESTURE_SWIPE_BEGIN, u32_at(d, 92)?) })
+			},
+			XI_HIERARCHY_CHANGED =>
+			{
+				let ended = self.end_scrolls(|_| true);
+				self.query_devices(srv);
+				Some(XIEvent::DevicesChanged { ended })
+			},
+			_ => None
+		}
+	}
+
+	/// Earliest time a touchpad scroll ends
+	pub fn scroll_deadline(&self) -> Option<Instant>
+	{
+		self.devices.borrow().values().filter_map(|d| d.scrolling.get()).map(|(_, t)| t + SCROLL_END_DELAY).min()
+	}
+	/// End the touchpad scrolls paused until `now`
+	pub fn expire_scrolls(&self, now: Instant) -> Vec<XIEvent> { self.end_scrolls(|t| t + SCROLL_END_DELAY <= now) }
+	/// End the touchpad scrolls whose last event time passes `ends`
+	fn end_scrolls<F: Fn(Instant) -> bool>(&self, ends: F) -> Vec<XIEvent>
+	{
+		self.devices.borrow().values().filter_map(|d| match d.scrolling.get()
+		{
+			Some((window, t)) if ends(t) =>
+			{
+				d.scrolling.set(None);
+				Some(XIEvent::Scroll { window, dx: 0.0, dy: 0.0, phase: ScrollPhase::Ended, source: d.source })
+			},
+			_ => None
+		}).collect()
+	}
+
+	/// Scroll by the scroll valuators in a motion
+	fn scroll(&self, d: &[u8], e: &DeviceEvent, device: &Device) -> Option<XIEvent>
+	{
+		let (mut dx, mut dy) = (0.0, 0.0);
+		for a in &device.scroll
+		{
+			let v = match e.valuators.index_of(a.number).and_then(|i| fp3232_at(d, e.valuators.values + i * 8)) { Some(v) => v, None => continue };
+			if let Some(notches) = a.notches(v)
+			{
+				if a.horizontal { dx += notches; } else { dy += notches; }
+			}
+		}
+		if dx == 0.0 && dy == 0.0 { return None; }
+		let phase = match (device.source, device.scrolling.get())
+		{
+			(ScrollSource::Touchpad, Some((w, _))) if w == e.window => ScrollPhase::Moved,
+			(ScrollSource::Touchpad, _) => ScrollPhase::Started,
+			_ => ScrollPhase::Moved
+		};
+		if device.source == ScrollSource::Touchpad { device.scrolling.set(Some((e.window, Instant::now()))); }
+		Some(XIEvent::Scroll { window: e.window, dx, dy, phase, source: device.source })
+	}
+	fn query_devices(&self, srv: &rxcb::Connection)
+	{
+		let r = match srv.xi_query_device(rxcb::XI_ALL_DEVICES) { Ok(r) => r, Err(_) => return };
+		let mut devices = self.devices.borrow_mut();
+		devices.clear();
+		for info in DeviceInfo::parse(r.data())
+		{
+			if info.use_ != XI_SLAVE_POINTER { continue; }
+			let name = info.name.to_lowercase();
+			let scroll = if self.smooth_scroll_available() { info.scroll } else { Vec::new() };
+			let (mut pressure, mut tilt) = (None, (None, None));
+			for v in info.valuators
+			{
+				if v.label == self.labels.pressure { pressure = Some(v); }
+				else if v.label == self.labels.tilt_x { tilt.0 = Some(v); }
+				else if v.label == self.labels.tilt_y { tilt.1 = Some(v); }
+			}
+			// XI2 does not tell the tool. The wacom driver adds a slave pointer per tool, named like "Wacom Intuos Pen eraser"
+			let pen = pressure.map(|pressure| Pen { eraser: name.contains("eraser"), pressure, tilt, last: RefCell::new((0.0, (0.0, 0.0))) });
+			// wheels are selected as well to tell their notches from emulated ones
+			if pen.is_none() && !self.smooth_scroll_available() { continue; }
+			// XI2 does not tell the kind of a pointer; drivers name touchpads after the hardware(e.g. "SynPS/2 Synaptics TouchPad")
+			let source = if ["touchpad", "trackpad", "synaptics"].iter().any(|n| name.contains(n)) { ScrollSource::Touchpad }
+				else if ["trackpoint", "pointing stick"].iter().any(|n| name.contains(n)) { ScrollSource::Other }
+				else { ScrollSource::Wheel };
+			devices.insert(info.id, Device { pen, scroll, source, scrolling: Cell::new(None) });
+		}
+	}
+}
+
+/// Phase of the begin, update or end event(0, 1 or 2) of a gesture
+fn gesture_phase(n: u16, flags: u32) -> TouchPhase
+{
+	match n
+	{
+		0 => TouchPhase::Started, 1 => TouchPhase::Moved,
+		_ => if flags & XI_GESTURE_CANCELLED != 0 { TouchPhase::Cancelled } else { TouchPhase::Ended }
+	}
+}
+
+/// An entry of an XIQueryDevice reply
+struct DeviceInfo { id: u16, use_: u16, name: String, valuators: Vec<Valuator>, scroll: Vec<ScrollAxis> }
+impl DeviceInfo
+{
+	fn parse(d: &[u8]) -> Vec<DeviceInfo>
+	{
+		let mut infos = Vec::new();
+		let mut o = 32;
+		for _ in 0 .. u16_at(d, 8).unwrap_or(0)
+		{
+			let (id, use_, classes, name_len) = match (u16_at(d, o), u16_at(d, o + 2), u16_at(d, o + 6), u16_at(d, o + 8))
+			{
+				(Some(a), Some(b), Some(c), Some(n)) => (a, b, c, n as usize), _ => break
+			};
+			let name = d.get(o + 12 .. o + 12 + name_len).map_or_else(String::new, |n| String::from_utf8_lossy(n).into_owned());
+			o += 12 + name_len.div_ceil(4) * 4;
+			let (mut valuators, mut scroll) = (Vec::new(), Vec::new());
+			for _ in 0 .. classes
+			{
+				let (ty, len) = match (u16_at(d, o), u16_at(d, o + 2)) { (Some(t), Some(l)) => (t, l as usize * 4), _ => return infos };
+				match ty
+				{
+					XI_VALUATOR_CLASS => if let (Some(number), Some(label), Some(min), Some(max)) =
+						(u16_at(d, o + 6), u32_at(d, o + 8), fp3232_at(d, o + 12), fp3232_at(d, o + 20))
+					{
+						valuators.push(Valuator { number: number as _, label, min, max });
+					},
+					XI_SCROLL_CLASS => if let (Some(number), Some(axis), Some(increment)) = (u16_at(d, o + 6), u16_at(d, o + 8), fp3232_at(d, o + 16))
+					{
+						if increment != 0.0
+						{
+							let horizontal = axis == XI_SCROLL_TYPE_HORIZONTAL;
+							scroll.push(ScrollAxis { number: number as _, horizontal, increment, last: Cell::new(None) });
+						}
+					},
+					_ => ()
+				}
+				o += len.max(4);
+			}
+			infos.push(DeviceInfo { id, use_, name, valuators, scroll });
+		}
+		infos
+	}
+}
+
+/// The common part of device events(XI 2.0) and touch events(XI 2.2)
+struct DeviceEvent<'d> { deviceid: u16, detail: u32, window: WindowID, pos: (f32, f32), buttons: u32, valuators: Valuators<'d> }
+impl<'d> DeviceEvent<'d>
+{
+	fn new(d: &'d [u8]) -> Option<Self>
+	{
+		let buttons_len = u16_at(d, 52)? as usize;
+		Some(DeviceEvent
+		{
+			deviceid: u16_at(d, 10)?, detail: u32_at(d, 16)?, window: u32_at(d, 24)?,
+			pos: (fp1616_at(d, 44)?, fp1616_at(d, 48)?),
+			buttons: if buttons_len > 0 { u32_at(d, 84)? } else { 0 },
+			valuators: Valuators::new(d, u16_at(d, 54)? as usize, 84 + buttons_len * 4)?
+		})
+	}
+}
+
+/// Mask of the valuators present in an event, followed by their values in FP3232
+struct Valuators<'d> { mask: &'d [u8], values: usize }
+impl<'d> Valuators<'d>
+{
+	/// `len` is the length of the mask in 4 byte units
+	fn new(d: &'d [u8], len: usize, offset: usize) -> Option<Self>
+	{
+		Some(Valuators { mask: d.get(offset .. offset + len * 4)?, values: offset + len * 4 })
+	}
+	fn count(&self) -> usize { self.mask.iter().map(|b| b.count_ones() as usize).sum() }
+	/// Position of the valuator among the values, if present
+	fn index_of(&self, n: usize) -> Option<usize>
+	{
+		if self.mask.get(n / 8)? & (1 << (n % 8)) == 0 { return None; }
+		Some(self.mask[.. n / 8].iter().map(|b| b.count_ones() as usize).sum::<usize>() + (self.mask[n / 8] & ((1 << (n % 8)) - 1)).count_ones() as usize)
+	}
+}
+fn u16_at(d: &[u8], o: usize) -> Option<u16> { d.get(o .. o + 2).map(|b| u16::from_ne_bytes([b[0], b[1]])) }
+fn u32_at(d: &[u8], o: usize) -> Option<u32> { d.get(o .. o + 4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]])) }
+fn fp1616_at(d: &[u8], o: usize) -> Option<f32> { u32_at(d, o).map(|v| v as i32 as f32 / 65536.0) }
+fn fp3232_at(d: &[u8], o: usize) -> Option<f64>
+{
+	Some(u32_at(d, o)? as i32 as f64 + u32_at(d, o + 4)? as f64 / 4294967296.0)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// Bytes of the wire format in the client's byte order, as libxcb hands them over
+	struct Bytes(Vec<u8>);
+	impl Bytes
+	{
+		fn new(len: usize) -> Self { Bytes(vec![0; len]) }
+		fn u16(&mut self, o: usize, v: u16) -> &mut Self { self.0[o .. o + 2].copy_from_slice(&v.to_ne_bytes()); self }
+		fn u32(&mut self, o: usize, v: u32) -> &mut Self { self.0[o .. o + 4].copy_from_slice(&v.to_ne_bytes()); self }
+		fn fp1616(&mut self, o: usize, v: f32) -> &mut Self { self.u32(o, (v * 65536.0) as i32 as u32) }
+		fn fp3232(&mut self, o: usize, v: f64) -> &mut Self
+		{
+			let i = v.floor();
+			self.u32(o, i as i32 as u32).u32(o + 4, ((v - i) * 4294967296.0) as u32)
+		}
+		fn bytes(&mut self, o: usize, b: &[u8]) -> &mut Self { self.0[o .. o + b.len()].copy_from_slice(b); self }
+	}
+
+	/// XI_ButtonPress of the tip of a pen: one button mask unit, one valuator mask unit with axes 0, 2 and 3
+	fn pen_press() -> Vec<u8>
+	{
+		let mut e = Bytes::new(84 + 4 + 4 + 3 * 8);
+		e.u16(8, XI_BUTTON_PRESS).u16(10, 12).u32(16, 1).u32(24, 0x0040_0001).fp1616(44, 10.5).fp1616(48, -2.25)
+			.u16(52, 1).u16(54, 1).u32(84, 1 << 3).u32(88, 0b1101).fp3232(92, 640.0).fp3232(100, 0.75).fp3232(108, -1.5);
+		e.0
+	}
+
+	#[test]
+	fn device_events_are_decoded()
+	{
+		let d = pen_press();
+		let e = DeviceEvent::new(&d).unwrap();
+		assert_eq!((e.deviceid, e.detail, e.window, e.pos, e.buttons), (12, 1, 0x0040_0001, (10.5, -2.25), 1 << 3));
+		assert_eq!(e.valuators.values, 92);
+		assert_eq!(e.valuators.count(), 3);
+		assert_eq!(fp3232_at(&d, e.valuators.values + e.valuators.index_of(2).unwrap() * 8), Some(0.75));
+		assert_eq!(fp3232_at(&d, e.valuators.values + e.valuators.index_of(3).unwrap() * 8), Some(-1.5));
+		// truncated events are rejected
+		assert!(DeviceEvent::new(&d[.. 90]).is_none());
+	}
+	#[test]
+	fn valuator_indices_count_the_preceding_bits()
+	{
+		let mask = [0b1010_0001, 0, 0b0000_0100, 0];
+		let v = Valuators { mask: &mask, values: 0 };
+		assert_eq!(v.count(), 4);
+		assert_eq!([0, 5, 7, 18].iter().map(|&n| v.index_of(n)).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(2), Some(3)]);
+		assert_eq!(v.index_of(1), None);
+		assert_eq!(v.index_of(17), None);
+		assert_eq!(v.index_of(32), None);
+	}
+	#[test]
+	fn query_device_replies_are_parsed()
+	{
+		// reply header, then a slave pointer with a valuator and a vertical scroll class, then a keyboard without classes
+		let name = b"Wacom Pen eraser";
+		let mut r = Bytes::new(32 + 12 + 16 + 44 + 24 + 12 + 4);
+		r.u16(8, 2);
+		r.u16(32, 12).u16(34, XI_SLAVE_POINTER).u16(38, 2).u16(40, name.len() as _).bytes(44, name);
+		r.u16(60, XI_VALUATOR_CLASS).u16(62, 11).u16(66, 2).u32(68, 77).fp3232(72, 0.0).fp3232(80, 2047.0);
+		r.u16(104, XI_SCROLL_CLASS).u16(106, 6).u16(110, 3).u16(112, 1).fp3232(120, 15.0);
+		r.u16(128, 13).u16(130, 4).u16(136, 3).bytes(140, b"kbd");
+		let infos = DeviceInfo::parse(&r.0);
+		assert_eq!(infos.len(), 2);
+		let (pen, kbd) = (&infos[0], &infos[1]);
+		assert_eq!((pen.id, pen.use_, &pen.name[..]), (12, XI_SLAVE_POINTER, "Wacom Pen eraser"));
+		assert_eq!(pen.valuators.len(), 1);
+		let v = &pen.valuators[0];
+		assert_eq!((v.number, v.label, v.min, v.max), (2, 77, 0.0, 2047.0));
+		assert_eq!(v.normalize(511.75), 0.25);
+		assert_eq!(pen.scroll.len(), 1);
+		assert_eq!((pen.scroll[0].number, pen.scroll[0].horizontal, pen.scroll[0].increment), (3, false, 15.0));
+		assert_eq!((kbd.id, &kbd.name[..], kbd.valuators.len()), (13, "kbd", 0));
+		// a device whose classes are cut off is dropped
+		assert_eq!(DeviceInfo::parse(&r.0[.. 100]).len(), 0);
+	}
+	#[test]
+	fn scroll_classes_without_increment_are_skipped()
+	{
+		let mut r = Bytes::new(32 + 12 + 24 + 24);
+		r.u16(8, 1);
+		r.u16(32, 9).u16(34, XI_SLAVE_POINTER).u16(38, 2);
+		r.u16(44, XI_SCROLL_CLASS).u16(46, 6).u16(50, 2).u16(52, XI_SCROLL_TYPE_HORIZONTAL).fp3232(60, 120.0);
+		r.u16(68, XI_SCROLL_CLASS).u16(70, 6).u16(74, 3).u16(76, 1);
+		let infos = DeviceInfo::parse(&r.0);
+		assert_eq!(infos.len(), 1);
+		assert_eq!(infos[0].scroll.len(), 1);
+		let a = &infos[0].scroll[0];
+		assert_eq!((a.number, a.horizontal, a.increment), (2, true, 120.0));
+	}
+	#[test]
+	fn scroll_notches_are_positive_upwards()
+	{
+		let a = ScrollAxis { number: 3, horizontal: false, increment: 15.0, last: Cell::new(None) };
+		assert_eq!(a.notches(300.0), None);
+		assert_eq!(a.notches(315.0), Some(-1.0));
+		assert_eq!(a.notches(307.5), Some(0.5));
+		assert_eq!(a.notches(277.5), Some(2.0));
+	}
+}
@@ -0,0 +1,112 @@
+//! Names of X11 requests and errors, for diagnostics
+
+/// Core errors, indexed by error code
+pub const CORE_ERRORS: &[&str] = &[
+	"Success", "BadRequest", "BadValue", "BadWindow", "BadPixmap", "BadAtom", "BadCursor", "BadFont", "BadMatch",
+	"BadDrawable", "BadAccess", "BadAlloc", "BadColormap", "BadGContext", "BadIDChoice", "BadName", "BadLength",
+	"BadImplementation"
+];
+/// Core requests, indexed by major opcode
+pub const CORE_REQUESTS: &[&str] = &[
+	"", "CreateWindow", "ChangeWindowAttributes", "GetWindowAttributes", "DestroyWindow", "DestroySubwindows",
+	"ChangeSaveSet", "ReparentWindow", "MapWindow", "MapSubwindows", "UnmapWindow", "UnmapSubwindows",
+	"ConfigureWindow", "CirculateWindow", "GetGeometry", "QueryTree", "InternAtom", "GetAtomName", "ChangeProperty",
+	"DeleteProperty", "GetProperty", "ListProperties", "SetSelectionOwner", "GetSelectionOwner", "ConvertSelection",
+	"SendEvent", "GrabPointer", "UngrabPointer", "GrabButton", "UngrabButton", "ChangeActivePointerGrab",
+	"GrabKeyboard", "UngrabKeyboard", "GrabKey", "UngrabKey", "AllowEvents", "GrabServer", "UngrabServer",
+	"QueryPointer", "GetMotionEvents", "TranslateCoordinates", "WarpPointer", "SetInputFocus", "GetInputFocus",
+	"QueryKeymap", "OpenFont", "CloseFont", "QueryFont", "QueryTextExtents", "ListFonts", "ListFontsWithInfo",
+	"SetFontPath", "GetFontPath", "CreatePixmap", "FreePixmap", "CreateGC", "ChangeGC", "CopyGC", "SetDashes",
+	"SetClipRectangles", "FreeGC", "ClearArea", "CopyArea", "CopyPlane", "PolyPoint", "PolyLine", "PolySegment",
+	"PolyRectangle", "PolyArc", "FillPoly", "PolyFillRectangle", "PolyFillArc", "PutImage", "GetImage", "PolyText8",
+	"PolyText16", "ImageText8", "ImageText16", "CreateColormap", "FreeColormap", "CopyColormapAndFree",
+	"InstallColormap", "UninstallColormap", "ListInstalledColormaps", "AllocColor", "AllocNamedColor",
+	"AllocColorCells", "AllocColorPlanes", "FreeColors", "StoreColors", "StoreNamedColor", "QueryColors",
+	"LookupColor", "CreateCursor", "CreateGlyphCursor", "FreeCursor", "RecolorCursor", "QueryBestSize",
+	"QueryExtension", "ListExtensions", "ChangeKeyboardMapping", "GetKeyboardMapping", "ChangeKeyboardControl",
+	"GetKeyboardControl", "Bell", "ChangePointerControl", "GetPointerControl", "SetScreenSaver", "GetScreenSaver",
+	"ChangeHosts", "ListHosts", "SetAccessControl", "SetCloseDownMode", "KillClient", "RotateProperties",
+	"ForceScreenSaver", "SetPointerMapping", "GetPointerMapping", "SetModifierMapping", "GetModifierMapping",
+	"", "", "", "", "", "", "", "NoOperation"
+];
+
+/// Requests(indexed by minor opcode) and errors(from the first error code) of an extension
+pub struct ExtensionNames
+{
+	pub name: &'static str, pub requests: &'static [&'static str], pub errors: &'static [&'static str]
+}
+
+pub const PRESENT: ExtensionNames = ExtensionNames
+{
+	name: "Present", requests: &["QueryVersion", "Pixmap", "NotifyMSC", "SelectInput", "QueryCapabilities"], errors: &[]
+};
+pub const MIT_SHM: ExtensionNames = ExtensionNames
+{
+	name: "MIT-SHM",
+	requests: &["QueryVersion", "Attach", "Detach", "PutImage", "GetImage", "CreatePixmap", "AttachFd", "CreateSegment"],
+	errors: &["BadSeg"]
+};
+pub const COMPOSITE: ExtensionNames = ExtensionNames
+{
+	name: "Composite",
+	requests: &[
+		"QueryVersion", "RedirectWindow", "RedirectSubwindows", "UnredirectWindow", "UnredirectSubwindows",
+		"CreateRegionFromBorderClip", "NameWindowPixmap", "GetOverlayWindow", "ReleaseOverlayWindow"
+	],
+	errors: &[]
+};
+pub const RENDER: ExtensionNames = ExtensionNames
+{
+	name: "RENDER",
+	requests: &[
+		"QueryVersion", "QueryPictFormats", "QueryPictIndexValues", "", "CreatePicture", "ChangePicture",
+		"SetPictureClipRectangles", "FreePicture", "Composite", "", "Trapezoids", "Triangles", "TriStrip", "TriFan",
+		"", "", "", "CreateGlyphSet", "ReferenceGlyphSet", "FreeGlyphSet", "AddGlyphs", "", "FreeGlyphs",
+		"CompositeGlyphs8", "CompositeGlyphs16", "CompositeGlyphs32", "FillRectangles", "CreateCursor",
+		"SetPictureTransform", "QueryFilters", "SetPictureFilter", "CreateAnimCursor", "AddTraps", "CreateSolidFill",
+		"CreateLinearGradient", "CreateRadialGradient", "CreateConicalGradient"
+	],
+	errors: &["BadPictFormat", "BadPicture", "BadPictOp", "BadGlyphSet", "BadGlyph"]
+};
+pub const XINPUT: ExtensionNames = ExtensionNames
+{
+	name: "XInputExtension",
+	requests: &[
+		"", "GetExtensionVersion", "ListInputDevices", "OpenDevice", "CloseDevice", "SetDeviceMode", "SelectExtensionEvent",
+		"GetSelectedExtensionEvents", "ChangeDeviceDontPropagateList", "GetDeviceDontPropagateList", "GetDeviceMotionEvents",
+		"ChangeKeyboardDevice", "ChangePointerDevice", "GrabDevice", "UngrabDevice", "GrabDeviceKey", "UngrabDeviceKey",
+		"GrabDeviceButton", "UngrabDeviceButton", "AllowDeviceEvents", "GetDeviceFocus", "SetDeviceFocus",
+		"GetFeedbackControl", "ChangeFeedbackControl", "GetDeviceKeyMapping", "ChangeDeviceKeyMapping",
+		"GetDeviceModifierMapping", "SetDeviceModifierMapping", "GetDeviceButtonMapping", "SetDeviceButtonMapping",
+		"QueryDeviceState", "SendExtensionEvent", "DeviceBell", "SetDeviceValuators", "GetDeviceControl",
+		"ChangeDeviceControl", "ListDeviceProperties", "ChangeDeviceProperty", "DeleteDeviceProperty", "GetDeviceProperty",
+		"XIQueryPointer", "XIWarpPointer", "XIChangeCursor", "XIChangeHierarchy", "XISetClientPointer",
+		"XIGetClientPointer", "XISelectEvents", "XIQueryVersion", "XIQueryDevice", "XISetFocus", "XIGetFocus",
+		"XIGrabDevice", "XIUngrabDevice", "XIAllowEvents", "XIPassiveGrabDevice", "XIPassiveUngrabDevice",
+		"XIListProperties", "XIChangeProperty", "XIDeleteProperty", "XIGetProperty", "XIGetSelectedEvents",
+		"XIBarrierReleasePointer"
+	],
+	errors: &["BadDevice", "BadEvent", "BadMode", "DeviceBusy", "BadClass"]
+};
+pub const RANDR: ExtensionNames = ExtensionNames
+{
+	name: "RANDR",
+	requests: &[
+		"QueryVersion", "", "SetScreenConfig", "", "SelectInput", "GetScreenInfo", "GetScreenSizeRange", "SetScreenSize",
+		"GetScreenResources", "GetOutputInfo", "ListOutputProperties", "QueryOutputProperty", "ConfigureOutputProperty",
+		"ChangeOutputProperty", "DeleteOutputProperty", "GetOutputProperty", "CreateMode", "DestroyMode",
+		"AddOutputMode", "DeleteOutputMode", "GetCrtcInfo", "SetCrtcConfig", "GetCrtcGammaSize", "GetCrtcGamma",
+		"SetCrtcGamma", "GetScreenResourcesCurrent", "SetCrtcTransform", "GetCrtcTransform", "GetPanning",
+		"SetPanning", "SetOutputPrimary", "GetOutputPrimary", "GetProviders", "GetProviderInfo",
+		"SetProviderOffloadSink", "SetProviderOutputSource", "ListProviderProperties", "QueryProviderProperty",
+		"ConfigureProviderProperty", "ChangeProviderProperty", "DeleteProviderProperty", "GetProviderProperty",
+		"GetMonitors", "SetMonitor", "DeleteMonitor", "CreateLease", "FreeLease"
+	],
+	errors: &["BadOutput", "BadCrtc", "BadMode", "BadProvider"]
+};
+
+/// Name in a table, `"Unknown"` for holes
+pub fn lookup(table: &[&'static str], index: usize) -> &'static str
+{
+	match table.get(index) { Some(n) if !n.is_empty() => n, _ => "Unknown" }
+}
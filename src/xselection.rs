@@ -0,0 +1,495 @@
+//! X11 selections(ICCCM section 2): serving the owned selections and reading the others'
+
+use rxcb; use rxcb::Event;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use std::ops::Range;
+use clipboard::{ReceiveCallback, Selection};
+use mime::{MimeData, TEXT_UTF8};
+
+/// Transfers without progress for this period are abandoned
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the clipboard manager may take to save the clipboard on exit
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest property written at once. Larger data is sent incrementally(INCR)
+const MAX_CHUNK: usize = 256 * 1024;
+
+struct Atoms
+{
+	clipboard: rxcb::Atom, targets: rxcb::Atom, timestamp: rxcb::Atom, multiple: rxcb::Atom, atom_pair: rxcb::Atom,
+	incr: rxcb::Atom, utf8_string: rxcb::Atom, save_targets: rxcb::Atom, clipboard_manager: rxcb::Atom,
+	/// Property of the selection window receiving converted data
+	transfer: rxcb::Atom, xdnd_selection: rxcb::Atom
+}
+/// Produces the data of an owned selection in a MIME type
+pub type Provider = Rc<dyn Fn(&str) -> Option<Vec<u8>>>;
+/// A selection owned by the application
+struct Owned
+{
+	provider: Provider, time: rxcb::Timestamp,
+	/// (target, MIME type)
+	targets: Vec<(rxcb::Atom, String)>
+}
+impl Owned
+{
+	fn has_text(&self) -> bool { self.targets.iter().any(|t| t.1 == TEXT_UTF8) }
+	/// Targets with data, as saved by the clipboard manager
+	fn data_targets(&self) -> Vec<rxcb::Atom> { self.targets.iter().map(|t| t.0).collect() }
+	/// Answer to TARGETS
+	fn target_list(&self, atoms: &Atoms) -> Vec<rxcb::Atom>
+	{
+		let mut list = vec![atoms.targets, atoms.timestamp, atoms.multiple];
+		list.extend(self.data_targets());
+		if self.has_text() { list.push(rxcb::XCB_ATOM_STRING); }
+		list
+	}
+	/// MIME type of a target. STRING is converted from the text
+	fn mime(&self, target: rxcb::Atom) -> Option<String>
+	{
+		if target == rxcb::XCB_ATOM_STRING && self.has_text() { return Some(TEXT_UTF8.to_owned()); }
+		self.targets.iter().find(|t| t.0 == target).map(|t| t.1.clone())
+	}
+}
+/// Data sent to a requestor in chunks
+struct IncrSend
+{
+	requestor: rxcb::WindowID, property: rxcb::Atom, type_: rxcb::Atom, data: Vec<u8>, offset: usize,
+	last_activity: Instant
+}
+impl IncrSend
+{
+	/// Range of the next chunk and whether it is the empty one ending the transfer
+	fn next_chunk(&mut self, chunk_size: usize) -> (Range<usize>, bool)
+	{
+		let (start, end) = (self.offset, (self.offset + chunk_size).min(self.data.len()));
+		self.offset = end; self.last_activity = Instant::now();
+		(start .. end, start == end)
+	}
+}
+/// A conversion requested from the owner of a selection. Conversions are sent one by one
+struct Read
+{
+	selection: rxcb::Atom, target: rxcb::Atom, callback: ReceiveCallback,
+	/// None until the conversion is sent
+	last_activity: Option<Instant>,
+	/// Chunks received so far in an incremental transfer
+	incr: Option<Vec<u8>>
+}
+impl Read
+{
+	/// Append a chunk of an incremental transfer. Returns the data once the empty chunk ends it
+	fn receive_chunk(&mut self, chunk: &[u8]) -> Option<Vec<u8>>
+	{
+		self.last_activity = Some(Instant::now());
+		if chunk.is_empty() { return self.incr.take(); }
+		if let Some(ref mut d) = self.incr { d.extend_from_slice(chunk); }
+		None
+	}
+}
+
+pub struct Selections
+{
+	window: rxcb::Window, atoms: Atoms, user_time: Cell<rxcb::Timestamp>,
+	owned: RefCell<HashMap<rxcb::Atom, Owned>>, sends: RefCell<Vec<IncrSend>>, reads: RefCell<VecDeque<Read>>,
+	/// Finished reads waiting for `deliver_ready`
+	ready: RefCell<VecDeque<(ReceiveCallback, Option<Vec<u8>>)>>
+}
+impl Selections
+{
+	/// Creates an unmapped InputOnly window owning the selections
+	pub fn new(srv: &rxcb::Connection, root: rxcb::WindowID) -> Result<Self, rxcb::GenericError>
+	{
+		let names = ["CLIPBOARD", "TARGETS", "TIMESTAMP", "MULTIPLE", "ATOM_PAIR", "INCR", "UTF8_STRING", "SAVE_TARGETS",
+			"CLIPBOARD_MANAGER", "APPFRAME_SELECTION", "XdndSelection"];
+		let cookies = names.iter().map(|n| srv.intern_create(n)).collect::<Vec<_>>();
+		let mut a = Vec::with_capacity(names.len());
+		for c in cookies { a.push(c.reply()?); }
+		let window = srv.new_window_id();
+		let mut vlist = rxcb::WindowValueList::new();
+		vlist.eventmask(rxcb::XCB_EVENT_MASK_PROPERTY_CHANGE);
+		srv.create_window(&window, rxcb::WindowParams
+		{
+			depth: None, parent: Some(root), pos: (-1, -1), size: (1, 1), border_width: 0,
+			class: rxcb::WindowIOClass::InputOnly, visual: None, values: &vlist
+		})?;
+		Ok(Selections
+		{
+			window, user_time: Cell::new(rxcb::XCB_TIME_CURRENT_TIME),
+			atoms: Atoms
+			{
+				clipboard: a[0], targets: a[1], timestamp: a[2], multiple: a[3], atom_pair: a[4], incr: a[5],
+				utf8_string: a[6], save_targets: a[7], clipboard_manager: a[8], transfer: a[9],
+				xdnd_selection: a[10]
+			},
+			owned: RefCell::new(HashMap::new()), sends: RefCell::new(Vec::new()), reads: RefCell::new(VecDeque::new()),
+			ready: RefCell::new(VecDeque::new())
+		})
+	}
+	fn atom(&self, which: Selection) -> rxcb::Atom
+	{
+		match which
+		{
+			Selection::Clipboard => self.atoms.clipboard, Selection::Primary => rxcb::XCB_ATOM_PRIMARY,
+			Selection::Dnd => self.atoms.xdnd_selection
+		}
+	}
+	/// Remember the time of user input. Selections are taken and requested as of the latest input
+	pub fn set_user_time(&self, t: rxcb::Timestamp) { self.user_time.set(t); }
+
+	pub fn set(&self, srv: &rxcb::Connection, which: Selection, data: MimeData)
+	{
+		if data.is_empty() { self.release(srv, which); return; }
+		let formats = data.keys().cloned().collect::<Vec<_>>();
+		self.set_provider(srv, which, &formats, Rc::new(move |m| data.get(m).cloned()));
+	}
+	/// Take the selection with data produced on request. Returns the targets offered, or None if the server refused
+	pub fn set_provider(&self, srv: &rxcb::Connection, which: Selection, formats: &[String], provider: Provider)
+		-> Option<Vec<rxcb::Atom>>
+	{
+		let (selection, time) = (self.atom(which), self.user_time.get());
+		let cookies = formats.iter().map(|m| (srv.intern_create(m), m.clone())).collect::<Vec<_>>();
+		let mut targets = cookies.into_iter().filter_map(|(c, m)| c.reply().ok().map(|a| (a, m))).collect::<Vec<_>>();
+		if formats.iter().any(|m| m == TEXT_UTF8) { targets.push((self.atoms.utf8_string, TEXT_UTF8.to_owned())); }
+		srv.set_selection_owner(self.window.id(), selection, time);
+		// the server ignores the request if the time is older than the last change of the owner
+		if srv.selection_owner(selection).ok() != Some(self.window.id())
+		{
+			self.owned.borrow_mut().remove(&selection);
+			return None;
+		}
+		let atoms = targets.iter().map(|t| t.0).collect();
+		self.owned.borrow_mut().insert(selection, Owned { provider, time, targets });
+		Some(atoms)
+	}
+	pub fn release(&self, srv: &rxcb::Connection, which: Selection)
+	{
+		let selection = self.atom(which);
+		if self.owned.borrow_mut().remove(&selection).is_some()
+		{
+			srv.set_selection_owner(rxcb::XCB_WINDOW_NONE, selection, self.user_time.get());
+		}
+	}
+	pub fn user_time(&self) -> rxcb::Timestamp { self.user_time.get() }
+	pub fn get(&self, srv: &rxcb::Connection, which: Selection, mime: &str, callback: ReceiveCallback)
+	{
+		let selection = self.atom(which);
+		// answered without a round trip if the application owns the selection
+		let provider = self.owned.borrow().get(&selection).map(|o| o.provider.clone());
+		if let Some(p) = provider { self.ready.borrow_mut().push_back((callback, p(mime))); return; }
+		let target = if mime == TEXT_UTF8 { Ok(self.atoms.utf8_string) } else { srv.intern(mime).reply() };
+		match target
+		{
+			// nobody has interned the format, so no owner can offer it
+			Ok(rxcb::XCB_ATOM_NONE) | Err(_) => self.ready.borrow_mut().push_back((callback, None)),
+			Ok(target) =>
+			{
+				self.reads.borrow_mut().push_back(Read { selection, target, callback, last_activity: None, incr: None });
+				self.start_read(srv);
+			}
+		}
+	}
+
+	/// Handle the selection events and the property changes of the transfers
+	pub fn handle_event(&self, srv: &rxcb::Connection, e: &rxcb::GenericEvent)
+	{
+		match e.response_type()
+		{
+			rxcb::SelectionRequestEvent::RESPONSE_ENUM => self.serve(srv, unsafe { rxcb::SelectionRequestEvent::from_ref(e) }),
+			rxcb::SelectionClearEvent::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::SelectionClearEvent::from_ref(e) };
+				if e.owner() == self.window.id() { self.owned.borrow_mut().remove(&e.selection()); }
+			},
+			rxcb::SelectionNotifyEvent::RESPONSE_ENUM => self.receive(srv, unsafe { rxcb::SelectionNotifyEvent::from_ref(e) }),
+			rxcb::PropertyNotifyEvent::RESPONSE_ENUM =>
+			{
+				let e = unsafe { rxcb::PropertyNotifyEvent::from_ref(e) };
+				if e.is_deleted() { self.send_next_chunk(srv, e.window(), e.atom()); }
+				else if e.window() == self.window.id() && e.atom() == self.atoms.transfer { self.receive_chunk(srv); }
+			},
+			_ => ()
+		}
+	}
+	/// Time until the next transfer expires. Zero if callbacks are waiting
+	pub fn timeout(&self) -> Option<Duration>
+	{
+		if !self.ready.borrow().is_empty() { return Some(Duration::from_secs(0)); }
+		let read = self.reads.borrow().front().and_then(|r| r.last_activity);
+		let send = self.sends.borrow().iter().map(|s| s.last_activity).min();
+		let now = Instant::now();
+		read.into_iter().chain(send).min().map(|t| (t + TRANSFER_TIMEOUT).checked_duration_since(now).unwrap_or_default())
+	}
+	/// Abandon the transfers without progress
+	pub fn expire(&self, srv: &rxcb::Connection)
+	{
+		let now = Instant::now();
+		let live = |t: Instant| now.duration_since(t) < TRANSFER_TIMEOUT;
+		self.sends.borrow_mut().retain(|s| live(s.last_activity));
+		let stalled = self.reads.borrow().front().and_then(|r| r.last_activity).is_some_and(|t| !live(t));
+		if stalled { self.finish_read(srv, None); }
+	}
+	/// Call the callbacks of the finished reads
+	pub fn deliver_ready(&self)
+	{
+		loop
+		{
+			let r = self.ready.borrow_mut().pop_front();
+			match r { Some((callback, data)) => callback(data), None => break }
+		}
+	}
+	/// Ask the clipboard manager to save the owned CLIPBOARD(SAVE_TARGETS), so that it outlives the application.
+	/// Serves the manager's requests until it replies or a timeout elapses
+	pub fn hand_over(&self, srv: &rxcb::Connection)
+	{
+		let targets = match self.owned.borrow().get(&self.atoms.clipboard) { Some(o) => o.data_targets(), None => return };
+		match srv.selection_owner(self.atoms.clipboard_manager)
+		{
+			Ok(w) if w != rxcb::XCB_WINDOW_NONE => (), _ => return
+		}
+		srv.change_property32(self.window.id(), self.atoms.transfer, rxcb::XCB_ATOM_ATOM, &targets);
+		srv.convert_selection(self.window.id(), self.atoms.clipboard_manager, self.atoms.save_targets,
+			self.atoms.transfer, self.user_time.get());
+		srv.flush();
+		let deadline = Instant::now() + HANDOVER_TIMEOUT;
+		while let Some(remaining) = deadline.checked_duration_since(Instant::now())
+		{
+			let e = match srv.wait_event_timeout(Some(remaining), &[]) { Some(e) => e, None => continue };
+			if e.response_type() == rxcb::SelectionNotifyEvent::RESPONSE_ENUM &&
+				unsafe { rxcb::SelectionNotifyEvent::from_ref(&e) }.selection() == self.atoms.clipboard_manager
+			{
+				break;
+			}
+			self.handle_event(srv, &e);
+			srv.flush();
+		}
+	}
+
+	// Owner side //
+	fn serve(&self, srv: &rxcb::Connection, req: &rxcb::SelectionRequestEvent)
+	{
+		// obsolete requestors pass None as the property
+		let property = if req.property() == rxcb::XCB_ATOM_NONE { req.target() } else { req.property() };
+		let converted = if req.target() == self.atoms.multiple && req.property() != rxcb::XCB_ATOM_NONE
+		{
+			self.convert_multiple(srv, req, property)
+		}
+		else { self.convert(srv, req.selection(), req.target(), req.time(), req.requestor(), property) };
+		srv.send_selection_notify(req.requestor(), req.selection(), req.target(),
+			if converted { property } else { rxcb::XCB_ATOM_NONE }, req.time());
+	}
+	/// The property holds pairs of (target, property). Failed conversions are replaced with None
+	fn convert_multiple(&self, srv: &rxcb::Connection, req: &rxcb::SelectionRequestEvent, property: rxcb::Atom) -> bool
+	{
+		let mut pairs = match srv.get_property(req.requestor(), property, false)
+		{
+			Ok(r) => r.value32().to_owned(), Err(_) => return false
+		};
+		convert_pairs(&mut pairs, |target, property| self.convert(srv, req.selection(), target, req.time(), req.requestor(), property));
+		srv.change_property32(req.requestor(), property, self.atoms.atom_pair, &pairs);
+		true
+	}
+	/// Store the selection into the property of the requestor. Returns false if refused
+	fn convert(&self, srv: &rxcb::Connection, selection: rxcb::Atom, target: rxcb::Atom, time: rxcb::Timestamp,
+		requestor: rxcb::WindowID, property: rxcb::Atom) -> bool
+	{
+		let (provider, mime) =
+		{
+			let owned = self.owned.borrow();
+			let o = match owned.get(&selection) { Some(o) => o, None => return false };
+			// requests made before the application took the selection are for the previous owner
+			if time != rxcb::XCB_TIME_CURRENT_TIME && o.time != rxcb::XCB_TIME_CURRENT_TIME && time < o.time { return false; }
+			if target == self.atoms.targets
+			{
+				srv.change_property32(requestor, property, rxcb::XCB_ATOM_ATOM, &o.target_list(&self.atoms));
+				return true;
+			}
+			if target == self.atoms.timestamp
+			{
+				srv.change_property32(requestor, property, rxcb::XCB_ATOM_INTEGER, &[o.time]);
+				return true;
+			}
+			match o.mime(target) { Some(m) => (o.provider.clone(), m), None => return false }
+		};
+		// the provider may use the selections
+		let data = match provider(&mime) { Some(d) => d, None => return false };
+		// Latin-1 for clients predating UTF8_STRING
+		let data = if target == rxcb::XCB_ATOM_STRING { latin1(&data) } else { data };
+		if data.len() > self.chunk_size(srv)
+		{
+			srv.select_input(requestor, rxcb::XCB_EVENT_MASK_PROPERTY_CHANGE);
+			srv.change_property32(requestor, property, self.atoms.incr, &[data.len() as u32]);
+			self.sends.borrow_mut().push(IncrSend
+			{
+				requestor, property, type_: target, data, offset: 0, last_activity: Instant::now()
+			});
+		}
+		else { srv.change_property(requestor, property, target, 8, &data); }
+		true
+	}
+	fn chunk_size(&self, srv: &rxcb::Connection) -> usize
+	{
+		// ChangeProperty header is 24 bytes
+		MAX_CHUNK.min(srv.maximum_request_bytes() - 24)
+	}
+	/// The requestor deleted the property: write the next chunk, or an empty one at the end
+	fn send_next_chunk(&self, srv: &rxcb::Connection, requestor: rxcb::WindowID, property: rxcb::Atom)
+	{
+		let chunk_size = self.chunk_size(srv);
+		let mut sends = self.sends.borrow_mut();
+		let i = match sends.iter().position(|s| s.requestor == requestor && s.property == property) { Some(i) => i, None => return };
+		let done = {
+			let s = &mut sends[i];
+			let (chunk, done) = s.next_chunk(chunk_size);
+			srv.change_property(requestor, property, s.type_, 8, &s.data[chunk]);
+			done
+		};
+		if done
+		{
+			sends.remove(i);
+			if !sends.iter().any(|s| s.requestor == requestor) { srv.select_input(requestor, 0); }
+		}
+	}
+
+	// Requestor side //
+	fn start_read(&self, srv: &rxcb::Connection)
+	{
+		let mut reads = self.reads.borrow_mut();
+		if let Some(r) = reads.front_mut().filter(|r| r.last_activity.is_none())
+		{
+			srv.convert_selection(self.window.id(), r.selection, r.target, self.atoms.transfer, self.user_time.get());
+			r.last_activity = Some(Instant::now());
+		}
+	}
+	fn finish_read(&self, srv: &rxcb::Connection, data: Option<Vec<u8>>)
+	{
+		let r = self.reads.borrow_mut().pop_front();
+		if let Some(r) = r { self.ready.borrow_mut().push_back((r.callback, data)); }
+		self.start_read(srv);
+	}
+	fn receive(&self, srv: &rxcb::Connection, e: &rxcb::SelectionNotifyEvent)
+	{
+		let active = self.reads.borrow().front().is_some_and(|r|
+			r.last_activity.is_some() && r.selection == e.selection() && r.target == e.target());
+		if e.requestor() != self.window.id() || !active { return; }
+		if e.property() == rxcb::XCB_ATOM_NONE { self.finish_read(srv, None); return; }
+		// deleting the property starts an incremental transfer
+		match srv.get_property(self.window.id(), e.property(), true)
+		{
+			Ok(ref r) if r.type_() == self.atoms.incr =>
+			{
+				let mut reads = self.reads.borrow_mut();
+				let r = reads.front_mut().unwrap();
+				r.incr = Some(Vec::new()); r.last_activity = Some(Instant::now());
+			},
+			Ok(r) => self.finish_read(srv, Some(r.value().to_owned())),
+			Err(_) => self.finish_read(srv, None)
+		}
+	}
+	/// The owner wrote a chunk of an incremental transfer. An empty chunk ends it
+	fn receive_chunk(&self, srv: &rxcb::Connection)
+	{
+		if self.reads.borrow().front().is_none_or(|r| r.incr.is_none()) { return; }
+		match srv.get_property(self.window.id(), self.atoms.transfer, true)
+		{
+			Ok(r) =>
+			{
+				let data = self.reads.borrow_mut().front_mut().and_then(|read| read.receive_chunk(r.value()));
+				if data.is_some() { self.finish_read(srv, data); }
+			},
+			Err(_) => self.finish_read(srv, None)
+		}
+	}
+}
+
+/// Convert the (target, property) pairs of a MULTIPLE request, replacing the property of failed conversions with None
+fn convert_pairs<F: FnMut(rxcb::Atom, rxcb::Atom) -> bool>(pairs: &mut [rxcb::Atom], mut convert: F)
+{
+	for p in pairs.chunks_mut(2).filter(|p| p.len() == 2)
+	{
+		if !convert(p[0], p[1]) { p[1] = rxcb::XCB_ATOM_NONE; }
+	}
+}
+/// Text for STRING targets. Characters out of Latin-1 become `?`
+fn latin1(utf8: &[u8]) -> Vec<u8>
+{
+	String::from_utf8_lossy(utf8).chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const TEXT: rxcb::Atom = 100;
+	const PNG: rxcb::Atom = 101;
+	fn atoms() -> Atoms
+	{
+		Atoms
+		{
+			clipboard: 1, targets: 2, timestamp: 3, multiple: 4, atom_pair: 5, incr: 6, utf8_string: TEXT, save_targets: 7,
+			clipboard_manager: 8, transfer: 9, xdnd_selection: 10
+		}
+	}
+	fn owned(formats: &[(rxcb::Atom, &str)]) -> Owned
+	{
+		Owned
+		{
+			provider: Rc::new(|_| None), time: rxcb::XCB_TIME_CURRENT_TIME,
+			targets: formats.iter().map(|&(a, m)| (a, m.to_owned())).collect()
+		}
+	}
+
+	#[test]
+	fn incremental_sends_end_with_an_empty_chunk()
+	{
+		let mut s = IncrSend
+		{
+			requestor: 1, property: 2, type_: TEXT, data: (0 .. 10).collect(), offset: 0, last_activity: Instant::now()
+		};
+		assert_eq!(s.next_chunk(4), (0 .. 4, false));
+		assert_eq!(s.next_chunk(4), (4 .. 8, false));
+		assert_eq!(s.next_chunk(4), (8 .. 10, false));
+		assert_eq!(s.next_chunk(4), (10 .. 10, true));
+	}
+	#[test]
+	fn incremental_reads_collect_chunks_until_the_empty_one()
+	{
+		let mut r = Read
+		{
+			selection: 1, target: TEXT, callback: Box::new(|_| ()), last_activity: None, incr: Some(Vec::new())
+		};
+		assert_eq!(r.receive_chunk(b"abc"), None);
+		assert_eq!(r.receive_chunk(b"de"), None);
+		assert!(r.last_activity.is_some());
+		assert_eq!(r.receive_chunk(b""), Some(b"abcde".to_vec()));
+	}
+	#[test]
+	fn multiple_replaces_failed_properties_with_none()
+	{
+		let mut pairs = [TEXT, 20, PNG, 21, rxcb::XCB_ATOM_STRING, 22, 999];
+		let mut requested = Vec::new();
+		convert_pairs(&mut pairs, |target, property| { requested.push((target, property)); target != PNG });
+		assert_eq!(requested, vec![(TEXT, 20), (PNG, 21), (rxcb::XCB_ATOM_STRING, 22)]);
+		assert_eq!(pairs, [TEXT, 20, PNG, rxcb::XCB_ATOM_NONE, rxcb::XCB_ATOM_STRING, 22, 999]);
+	}
+	#[test]
+	fn save_targets_list_the_data_targets()
+	{
+		let (a, o) = (atoms(), owned(&[(PNG, "image/png"), (TEXT, TEXT_UTF8)]));
+		assert_eq!(o.data_targets(), vec![PNG, TEXT]);
+		assert_eq!(o.target_list(&a), vec![a.targets, a.timestamp, a.multiple, PNG, TEXT, rxcb::XCB_ATOM_STRING]);
+		assert_eq!(owned(&[(PNG, "image/png")]).target_list(&a), vec![a.targets, a.timestamp, a.multiple, PNG]);
+	}
+	#[test]
+	fn string_targets_are_latin1_text()
+	{
+		let o = owned(&[(PNG, "image/png"), (TEXT, TEXT_UTF8)]);
+		assert_eq!(o.mime(rxcb::XCB_ATOM_STRING).as_ref().map(|m| &m[..]), Some(TEXT_UTF8));
+		assert_eq!(o.mime(PNG).as_ref().map(|m| &m[..]), Some("image/png"));
+		assert_eq!(o.mime(999), None);
+		assert_eq!(owned(&[(PNG, "image/png")]).mime(rxcb::XCB_ATOM_STRING), None);
+		assert_eq!(latin1("café ☕".as_bytes()), b"caf\xe9 ?".to_vec());
+	}
+}